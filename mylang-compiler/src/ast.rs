@@ -1,136 +1,282 @@
 use std::rc::Rc;
 use std::fmt::Debug;
-use std::ptr::NonNull;
+use std::ops::{Index, IndexMut};
 
 pub type RcStr = Rc<str>;
 pub type IdStr = RcStr;
+/// The index of a source file among all files of the program.
+pub type FileId = u32;
 
+/// A position in a source file, as a byte offset.
+/// The line and column are only computed when needed, from the line index of the `SourceFile`.
 #[derive(Clone,Copy,Debug)]
 pub struct Location {
-    pub line: u16,
-    pub char: u16,
+    pub file: FileId,
+    /// The number of bytes before the location in the file.
+    pub offset: u32,
 }
 
 impl Location {
     pub fn str(&self) -> RcStr {
-        return format!("line: {}, char: {}", self.line, self.char).into();
+        return format!("file: {}, byte: {}", self.file, self.offset).into();
     }
 
-    pub fn inc_line(&mut self) {
-        self.line += 1;
-        self.char = 0;
+    /// Files are at most `diagnostics::MAX_SOURCE_LEN` bytes, so the offset cannot overflow within a file.
+    pub fn advance(&mut self, bytes: usize) {
+        self.offset += bytes as u32;
+    }
+}
+
+/// The part of a file between two locations, where `end` is just after the last character.
+#[derive(Clone,Copy,Debug)]
+pub struct Span {
+    pub start: Location,
+    pub end: Location,
+}
+
+impl Span {
+    pub fn new(start: Location, end: Location) -> Span {
+        return Span{start, end};
+    }
+
+    /// The span from the start of this one to the end of `other`.
+    pub fn to(&self, other: Span) -> Span {
+        return Span{start: self.start, end: other.end};
+    }
+
+    pub fn file(&self) -> FileId {
+        return self.start.file;
     }
 
-    pub fn inc_char(&mut self, count: u16) {
-        self.char += count;
+    /// Whether the other span is inside this one.
+    pub fn contains(&self, other: Span) -> bool {
+        return self.file() == other.file() && self.start.offset <= other.start.offset && other.end.offset <= self.end.offset;
     }
 }
 
 #[derive(Debug)]
 pub struct Program {
     pub name: RcStr,
-    pub ast: GlobalNamespace,
+    /// The declarations, scopes and identifiers of every file, starting with the global namespace.
+    pub arena: Arena,
 }
 
 impl Program {
     pub fn get_all_namespace_full_names(&self) -> Box<[RcStr]> {
         let mut vector = Vec::new();
-        vector.push("".into());
-        for subnamespace in &self.ast.subnamespaces {
-            subnamespace.add_all_subnamespace_full_names(&mut vector);
-        }
+        self.add_all_namespace_full_names(GLOBAL_NAMESPACE, &mut vector);
         return vector.into();
     }
-    pub fn get_all_namespaced_declarations(&mut self) -> Box<[(RcStr, &Declaration)]> {
+    pub fn get_all_namespaced_declarations(&self) -> Box<[(RcStr, DeclId)]> {
         let mut vector = Vec::new();
-
-        for declaration in &self.ast.declarations {
-            vector.push(("".into(), declaration));
+        self.add_all_namespaced_declarations(GLOBAL_NAMESPACE, &mut vector);
+        return vector.into();
+    }
+    fn add_all_namespace_full_names(&self, scope: ScopeId, vector: &mut Vec<RcStr>) {
+        let namespace = self.arena.namespace(scope);
+        vector.push(namespace.full_name.clone());
+        for subnamespace in &namespace.subnamespaces {
+            self.add_all_namespace_full_names(*subnamespace, vector);
         }
-
-        for subnamespace in &self.ast.subnamespaces {
-            subnamespace.add_all_namespaced_declarations(&mut vector);
+    }
+    fn add_all_namespaced_declarations(&self, scope: ScopeId, vector: &mut Vec<(RcStr, DeclId)>) {
+        let namespace = self.arena.namespace(scope);
+        for Declaration::ValueDecl(id) in &namespace.declarations {
+            vector.push((namespace.full_name.clone(), *id));
+        }
+        for subnamespace in &namespace.subnamespaces {
+            self.add_all_namespaced_declarations(*subnamespace, vector);
         }
-
-        return vector.into();
     }
 }
 
-#[derive(Debug)]
-pub enum NamespaceKind {
-    GlobalNamespace(*const GlobalNamespace),
-    SubNamespace(*const SubNamespace),
-}
+/// The index of a declaration in the arena.
+#[derive(Copy,Clone,Debug,Eq,PartialEq,Hash)]
+pub struct DeclId(u32);
+
+/// The index of a scope in the arena.
+#[derive(Copy,Clone,Debug,Eq,PartialEq,Hash)]
+pub struct ScopeId(u32);
+
+/// The index of an identifier in the arena, where what it resolves to is stored.
+#[derive(Copy,Clone,Debug,Eq,PartialEq,Hash)]
+pub struct ExprId(u32);
 
+/// The global namespace, which is the first scope of every arena.
+pub const GLOBAL_NAMESPACE: ScopeId = ScopeId(0);
+
+/// Owns the declarations and scopes of a program and the resolutions of its identifiers,
+/// so the rest of the tree and later passes can refer to them by id.
+/// Nothing is removed from the arena, so an id stays valid for as long as the arena exists.
 #[derive(Debug)]
-pub struct GlobalNamespace {
-    pub declarations: Vec<Declaration>,
-    pub subnamespaces: Vec<SubNamespace>,
+pub struct Arena {
+    declarations: Vec<ValueDecl>,
+    scopes: Vec<Scope>,
+    identifiers: Vec<IdKind>,
 }
 
-impl GlobalNamespace {
-    pub fn new() -> GlobalNamespace {
-        return GlobalNamespace {
+impl Arena {
+    /// Creates an arena that only contains the global namespace.
+    pub fn new() -> Arena {
+        let global_namespace = Namespace {
+            name: "".into(),
+            full_name: "".into(),
             declarations: Vec::new(),
+            imports: Vec::new(),
             subnamespaces: Vec::new(),
         };
+        return Arena {
+            declarations: Vec::new(),
+            scopes: vec![Scope{kind: ScopeKind::Namespace(global_namespace), parent: None}],
+            identifiers: Vec::new(),
+        };
     }
-}
 
-#[derive(Debug)]
-pub struct SubNamespace {
-    pub name: RcStr,
-    pub full_name: RcStr,
-    pub declarations: Vec<Declaration>,
-    pub subnamespaces: Vec<SubNamespace>,
-    pub parent: NamespaceKind,
-}
+    pub fn add_declaration(&mut self, value_decl: ValueDecl) -> DeclId {
+        self.declarations.push(value_decl);
+        return DeclId(self.declarations.len() as u32 - 1);
+    }
+
+    pub fn add_scope(&mut self, kind: ScopeKind, parent: ScopeId) -> ScopeId {
+        self.scopes.push(Scope{kind, parent: Some(parent)});
+        return ScopeId(self.scopes.len() as u32 - 1);
+    }
+
+    pub fn add_identifier(&mut self, id_kind: IdKind) -> ExprId {
+        self.identifiers.push(id_kind);
+        return ExprId(self.identifiers.len() as u32 - 1);
+    }
 
-impl SubNamespace {
-    pub fn new(name: RcStr, parent: NonNull<SubNamespace>) -> SubNamespace {
-        let mut full_name = unsafe {parent.as_ref()}.get_path().join(".");
-        full_name.push('.');
-        full_name.push_str(name.as_ref());
-        return SubNamespace {
+    /// Returns the namespace with the name in `parent`, and adds it first if it does not exist yet.
+    pub fn get_or_add_namespace(&mut self, name: RcStr, parent: ScopeId) -> ScopeId {
+        let parent_namespace = self.namespace(parent);
+        if let Some(subnamespace) = parent_namespace.subnamespaces.iter().find(|subnamespace| self.namespace(**subnamespace).name == name) {
+            return *subnamespace;
+        }
+        let full_name = match parent {
+            GLOBAL_NAMESPACE => name.clone(),
+            _ => format!("{}.{}", parent_namespace.full_name, name).into(),
+        };
+        let subnamespace = self.add_scope(ScopeKind::Namespace(Namespace {
             name,
-            full_name: full_name.into(),
+            full_name,
             declarations: Vec::new(),
+            imports: Vec::new(),
             subnamespaces: Vec::new(),
-            parent: NamespaceKind::SubNamespace(parent.as_ptr()),
+        }), parent);
+        self.namespace_mut(parent).subnamespaces.push(subnamespace);
+        return subnamespace;
+    }
+
+    pub fn namespace(&self, scope: ScopeId) -> &Namespace {
+        return match &self[scope].kind {
+            ScopeKind::Namespace(namespace) => namespace,
+            _ => panic!("Scope is not a namespace"),
         };
     }
-    fn get_path(&self) -> Box<[RcStr]> {
-        let mut path = Vec::new();
-        let mut namespace = self;
-        loop {
-            path.push(namespace.name.clone());
-            match namespace.parent {
-                NamespaceKind::GlobalNamespace(_) => break,
-                NamespaceKind::SubNamespace(parent) => namespace = unsafe {&*parent},
-            };
+
+    pub fn namespace_mut(&mut self, scope: ScopeId) -> &mut Namespace {
+        return match &mut self[scope].kind {
+            ScopeKind::Namespace(namespace) => namespace,
+            _ => panic!("Scope is not a namespace"),
         };
-        path.reverse();
-        return path.into();
     }
-    fn add_all_subnamespace_full_names(&self, vector: &mut Vec<RcStr>) {
-        vector.push(self.name.clone());
-        for subnamespace in &self.subnamespaces {
-            subnamespace.add_all_subnamespace_full_names(vector);
+
+    /// The full name of the namespace that the scope is in.
+    pub fn full_name(&self, scope: ScopeId) -> RcStr {
+        let mut current = scope;
+        loop {
+            let scope = &self[current];
+            match (&scope.kind, scope.parent) {
+                (ScopeKind::Namespace(namespace), _) => return namespace.full_name.clone(),
+                (_, Some(parent)) => current = parent,
+                (_, None) => panic!("Scope is not inside a namespace"),
+            };
         }
     }
-    fn add_all_namespaced_declarations<'a>(&'a self, vector: &mut Vec<(RcStr, &'a Declaration)>) {
-        for declaration in &self.declarations {
-            vector.push((self.full_name.clone(), declaration));
-        }
-        for subnamespace in &self.subnamespaces {
-            subnamespace.add_all_namespaced_declarations(vector);
-        }
+}
+
+impl Index<DeclId> for Arena {
+    type Output = ValueDecl;
+
+    fn index(&self, id: DeclId) -> &ValueDecl {
+        return &self.declarations[id.0 as usize];
+    }
+}
+
+impl IndexMut<DeclId> for Arena {
+    fn index_mut(&mut self, id: DeclId) -> &mut ValueDecl {
+        return &mut self.declarations[id.0 as usize];
+    }
+}
+
+impl Index<ScopeId> for Arena {
+    type Output = Scope;
+
+    fn index(&self, id: ScopeId) -> &Scope {
+        return &self.scopes[id.0 as usize];
+    }
+}
+
+impl IndexMut<ScopeId> for Arena {
+    fn index_mut(&mut self, id: ScopeId) -> &mut Scope {
+        return &mut self.scopes[id.0 as usize];
+    }
+}
+
+impl Index<ExprId> for Arena {
+    type Output = IdKind;
+
+    fn index(&self, id: ExprId) -> &IdKind {
+        return &self.identifiers[id.0 as usize];
+    }
+}
+
+impl IndexMut<ExprId> for Arena {
+    fn index_mut(&mut self, id: ExprId) -> &mut IdKind {
+        return &mut self.identifiers[id.0 as usize];
     }
 }
 
+/// The global namespace, or a namespace nested in it.
 #[derive(Debug)]
+pub struct Namespace {
+    /// Empty for the global namespace.
+    pub name: RcStr,
+    /// The path of names from the global namespace, e.g. `Std.Compare`.
+    pub full_name: RcStr,
+    pub declarations: Vec<Declaration>,
+    pub imports: Vec<Import>,
+    pub subnamespaces: Vec<ScopeId>,
+}
+
+/// `import Std.Mem;` or `use Std.Mem;`, where the first part of the path can also be a string, as in `import "std".Mem;`.
+#[derive(Clone,Debug)]
+pub struct Import {
+    pub kind: ImportKind,
+    pub path: Box<[RcStr]>,
+    /// The span of the path.
+    pub span: Span,
+}
+
+#[derive(Copy,Clone,Debug,Eq,PartialEq)]
+pub enum ImportKind {
+    /// Loads the file that the path names.
+    Import,
+    /// Loads the file that the path names, if there is one, and makes the public declarations of the namespace that the path names
+    /// visible without a path inside the namespace of the import.
+    Use,
+}
+
+impl Import {
+    pub fn path_str(&self) -> String {
+        return self.path.join(".");
+    }
+}
+
+#[derive(Copy,Clone,Debug)]
 pub enum Declaration {
-    ValueDecl(Box<ValueDecl>),
+    ValueDecl(DeclId),
 }
 
 impl Declaration {
@@ -141,11 +287,14 @@ impl Declaration {
     }
 }
 
-#[derive(Debug)]
+#[derive(Clone,Debug)]
 pub struct ValueDecl {
     pub public: bool,
     pub decl_keyword: DeclKeyword,
     pub identifier: RcStr,
+    pub identifier_span: Span,
+    /// The span of the whole declaration, from the keyword to the end of its value or body.
+    pub span: Span,
     pub type_kind: TypeKind,
     pub decl_kind: DeclKind,
 }
@@ -155,7 +304,7 @@ pub enum DeclKeyword {
     Var, Let, Def
 }
 
-#[derive(Debug)]
+#[derive(Clone,Debug)]
 pub enum TypeKind {
     Inferred,
     Identifier(Box<ScopedId>),
@@ -164,7 +313,17 @@ pub enum TypeKind {
 }
 
 impl TypeKind {
-    pub fn is_same_type(&self, other: &TypeKind) -> bool {
+    pub fn str(&self, arena: &Arena) -> RcStr {
+        use TypeKind::*;
+        return match self {
+            Inferred => "_".into(),
+            Identifier(scoped_id) => scoped_id.name.clone(),
+            FuncType(func_type) => func_type.str(arena),
+            Primitive(primitive) => primitive.str(),
+        };
+    }
+
+    pub fn is_same_type(&self, other: &TypeKind, arena: &Arena) -> bool {
         use TypeKind::*;
         if let Inferred = other {
             panic!("Cannot compare inferred type");
@@ -172,13 +331,13 @@ impl TypeKind {
         match self {
             Inferred => panic!("Cannot compare inferred type"),
             Identifier(scoped_id) => {
-                match scoped_id.id_kind {
+                match arena[scoped_id.id] {
                     IdKind::Resolved{declaration, ..} => {
                         return match other {
                             Identifier(other_scoped_id) => {
-                                match other_scoped_id.id_kind {
+                                match arena[other_scoped_id.id] {
                                     IdKind::Resolved{declaration: other_declaration, ..} => {
-                                        return unsafe {&*declaration}.is_same_type(unsafe {&*other_declaration});
+                                        return Declaration::ValueDecl(declaration).is_same_type(&Declaration::ValueDecl(other_declaration));
                                     }
                                     IdKind::Unresolved{..} => panic!("Cannot compare unresolved type"),
                                 }
@@ -191,7 +350,7 @@ impl TypeKind {
             }
             FuncType(func_type) => {
                 return match other {
-                    FuncType(other_func_type) => func_type.is_same_type(other_func_type),
+                    FuncType(other_func_type) => func_type.is_same_type(other_func_type, arena),
                     _ => false,
                 }
             }
@@ -211,24 +370,99 @@ pub enum Primitive {
     I8, I16, I32, I64, I128,
         F16, F32, F64, F128,
     U1, Bool,
+    /// A string, stored as a pointer to its UTF-8 bytes and their length.
+    Str,
 }
 
-#[derive(Debug)]
+impl Primitive {
+    pub const fn is_int(&self) -> bool {
+        use Primitive::*;
+        return match self {
+            I8 | I16 | I32 | I64 | I128 => true,
+            U8 | U16 | U32 | U64 | U128 => true,
+            U1 => true,
+            _ => false,
+        };
+    }
+
+    pub const fn is_float(&self) -> bool {
+        use Primitive::*;
+        return match self {
+            F16 | F32 | F64 | F128 => true,
+            _ => false,
+        };
+    }
+
+    pub const fn is_bool(&self) -> bool {
+        use Primitive::*;
+        return match self {
+            U1 | Bool => true,
+            _ => false,
+        };
+    }
+
+    pub fn str(&self) -> RcStr {
+        return format!("{:?}", self).into();
+    }
+
+    /// Returns the type of a number literal suffix, e.g. `U8` for `u8` in `42u8`.
+    pub fn from_suffix(suffix: &str) -> Option<Primitive> {
+        use Primitive::*;
+        return Some(match suffix {
+            "u8"   => U8,
+            "u16"  => U16,
+            "u32"  => U32,
+            "u64"  => U64,
+            "u128" => U128,
+            "i8"   => I8,
+            "i16"  => I16,
+            "i32"  => I32,
+            "i64"  => I64,
+            "i128" => I128,
+            "f16"  => F16,
+            "f32"  => F32,
+            "f64"  => F64,
+            "f128" => F128,
+            _ => return None,
+        });
+    }
+}
+
+#[derive(Clone,Debug)]
 pub struct FuncType {
     pub arguments: Box<[Argument]>,
     pub return_type: Box<TypeKind>,
 }
 
 impl FuncType {
-    fn is_same_type(&self, other: &FuncType) -> bool {
-        if !self.return_type.is_same_type(&other.return_type) {
+    pub fn str(&self, arena: &Arena) -> RcStr {
+        let mut string = String::from("(");
+        let mut first = true;
+        for argument in self.arguments.iter() {
+            let Declaration::ValueDecl(id) = argument.decl; {
+                let value_decl = &arena[id];
+                if !first {
+                    string.push_str(", ");
+                }
+                first = false;
+                string.push_str(&format!("{}: {}", value_decl.identifier, value_decl.type_kind.str(arena)));
+            }
+        }
+        string.push_str(&format!(") -> {}", self.return_type.str(arena)));
+        return string.into();
+    }
+
+    fn is_same_type(&self, other: &FuncType, arena: &Arena) -> bool {
+        if !self.return_type.is_same_type(&other.return_type, arena) {
             return false;
         }
         if self.arguments.len() != other.arguments.len() {
             return false;
         }
         for index in 0..self.arguments.len() {
-            if !self.arguments.get(index).unwrap().decl.is_same_type(&other.arguments.get(index).unwrap().decl) {
+            let Declaration::ValueDecl(argument) = self.arguments.get(index).unwrap().decl;
+            let Declaration::ValueDecl(other_argument) = other.arguments.get(index).unwrap().decl;
+            if !arena[argument].type_kind.is_same_type(&arena[other_argument].type_kind, arena) {
                 return false;
             }
         }
@@ -236,309 +470,507 @@ impl FuncType {
     }
 }
 
-#[derive(Debug)]
+#[derive(Clone,Debug)]
 pub struct Argument {
     pub decl: Declaration,
 }
 
-#[derive(Debug)]
+#[derive(Clone,Debug)]
 pub enum DeclKind {
     EmptyDecl,
     AssignDecl(Box<Expression>),
     FuncDecl(Box<Expression>),
+    /// A labeled block, `let label: Type { ... yield label = value; }`, whose value is given by `yield`.
+    BlockDecl(Box<Block>),
+    /// A function defined outside of the program, `extern def name(arguments) -> Type;`, which is linked by its name.
+    ExternDecl,
+    /// The variable of a `for` loop, which takes each integer from `start` to `end`, both included.
+    LoopDecl {
+        start: Box<Expression>,
+        end: Box<Expression>,
+    },
 }
 
-#[derive(Debug)]
+#[derive(Clone,Debug)]
 pub enum Expression {
     Identifier(Box<ScopedId>),
-    Integer(RcStr),
-    Decimal(RcStr),
-    Bool(bool),
-    //Block(Block),
+    Integer(RcStr, Span),
+    Decimal(RcStr, Span),
+    Bool(bool, Span),
+    /// A string literal, where the escapes have been replaced.
+    String(RcStr, Span),
+    Unary(Box<UnaryExpr>),
+    Binary(Box<BinaryExpr>),
+    Call(Box<CallExpr>),
+    Field(Box<FieldExpr>),
+    Block(Box<Block>),
+    If(Box<IfExpr>),
+    While(Box<WhileExpr>),
+    For(Box<ForExpr>),
+    Switch(Box<SwitchExpr>),
 }
 
-#[derive(Debug)]
-pub struct ScopedId {
-    pub name: RcStr,
-    pub id_kind: IdKind,
+impl Expression {
+    /// The span of the expression, without any parentheses around it.
+    pub fn span(&self) -> Span {
+        use Expression::*;
+        return match self {
+            Identifier(scoped_id) => scoped_id.span,
+            Integer(_, span) | Decimal(_, span) | Bool(_, span) | String(_, span) => *span,
+            Unary(unary) => unary.span,
+            Binary(binary) => binary.span,
+            Call(call) => call.span,
+            Field(field) => field.span,
+            Block(block) => block.span,
+            If(if_expr) => if_expr.span,
+            While(while_expr) => while_expr.span,
+            For(for_expr) => for_expr.span,
+            Switch(switch) => switch.span,
+        };
+    }
 }
 
-#[derive(Debug)]
-pub enum IdKind {
-    Resolved {
-        declaration: *const Declaration,
-        scope: ScopeKind,
-    },
-    Unresolved {
-        scope_used: ScopeKind,
-        scope_described: Box<[IdStr]>,
-    },
+#[derive(Clone,Debug)]
+pub struct UnaryExpr {
+    pub operator: UnaryOp,
+    pub operand: Expression,
+    pub operator_span: Span,
+    pub span: Span,
 }
 
-#[derive(Copy,Clone,Debug)]
-pub enum ScopeKind {
-    /// Non-nullable
-    GlobalNamespace(*const GlobalNamespace),
-    /// Non-nullable
-    SubNamespace(*const SubNamespace),
-    /// Non-nullable
-    Function(*const ValueDecl),
-    //Block(*const Block),
-}
-
-pub trait Scope {
-    fn get_full_name(&self) -> RcStr;
-    #[must_use]
-    fn is_unique_identifier(&self, declaration: &Declaration) -> bool;
-    #[must_use]
-    fn resolve_identifier(&self, identifier: &mut ScopedId) -> bool;
-    fn into_scopekind(scope: *const Self) -> ScopeKind;
-}
-
-impl Scope for GlobalNamespace {
-    fn get_full_name(&self) -> RcStr {
-        return "".into();
-    }
-    fn is_unique_identifier(&self, declaration: &Declaration) -> bool {
-        let Declaration::ValueDecl(value_declaration) = declaration; {
-            for decl in &self.declarations {
-                let Declaration::ValueDecl(value_decl) = decl; {
-                    if value_decl.identifier == value_declaration.identifier {
-                        return false;
-                    }
-                }
-            }
-        };
-        return true;
+#[derive(Clone,Debug)]
+pub struct BinaryExpr {
+    pub operator: BinaryOp,
+    pub left: Expression,
+    pub right: Expression,
+    pub operator_span: Span,
+    pub span: Span,
+}
+
+#[derive(Clone,Debug)]
+pub struct CallExpr {
+    pub callee: Expression,
+    pub arguments: Box<[Expression]>,
+    /// The span from the callee to the closing parenthesis.
+    pub span: Span,
+}
+
+#[derive(Clone,Debug)]
+pub struct FieldExpr {
+    pub object: Expression,
+    pub field: RcStr,
+    pub field_span: Span,
+    pub span: Span,
+}
+
+/// `if (condition) then_branch else else_branch`, where an `elif` is an `if` in the else branch.
+/// When used as a statement, the branches are statements.
+/// When used as a value, the branches are expressions.
+#[derive(Clone,Debug)]
+pub struct IfExpr {
+    pub condition: Expression,
+    pub then_branch: Statement,
+    pub else_branch: Option<Statement>,
+    /// The span from the `if` keyword to the end of the last branch.
+    pub span: Span,
+}
+
+/// `while (condition) body`. Can only be used as a statement.
+#[derive(Clone,Debug)]
+pub struct WhileExpr {
+    pub condition: Expression,
+    pub body: Statement,
+    pub span: Span,
+}
+
+/// `for (i in start..end) body`, which runs the body once for each integer from `start` to `end`, both included.
+/// Can only be used as a statement.
+#[derive(Clone,Debug)]
+pub struct ForExpr {
+    /// The declaration of the loop variable, whose kind is a `LoopDecl`.
+    pub variable: DeclId,
+    pub body: Statement,
+    pub span: Span,
+}
+
+/// `switch value { case 1, 2..5: a; else: b; }`, where the `else` case is taken when no other case matches.
+/// As with `if`, the cases are statements when used as a statement and expressions when used as a value.
+#[derive(Clone,Debug)]
+pub struct SwitchExpr {
+    pub value: Expression,
+    pub cases: Box<[SwitchCase]>,
+    pub default: Option<Statement>,
+    /// The span from the `switch` keyword to the closing bracket.
+    pub span: Span,
+}
+
+#[derive(Clone,Debug)]
+pub struct SwitchCase {
+    pub patterns: Box<[Pattern]>,
+    pub body: Statement,
+    /// The span from the `case` keyword to the end of the body.
+    pub span: Span,
+}
+
+#[derive(Clone,Debug)]
+pub struct Pattern {
+    pub kind: PatternKind,
+    pub span: Span,
+}
+
+#[derive(Clone,Debug)]
+pub enum PatternKind {
+    Bool(bool),
+    Integer(IntLiteral),
+    /// An inclusive range of integers, `start..end`.
+    Range(IntLiteral, IntLiteral),
+}
+
+/// An integer literal in a pattern, which unlike an integer expression can be negative.
+#[derive(Clone,Debug)]
+pub struct IntLiteral {
+    pub literal: RcStr,
+    pub negative: bool,
+}
+
+impl IntLiteral {
+    /// The magnitude of the literal, or `None` if it does not fit in 128 bits.
+    pub fn magnitude(&self) -> Option<u128> {
+        return NumberLiteral::split(&self.literal).int_value();
     }
-    fn resolve_identifier(&self, identifier: &mut ScopedId) -> bool {
-        for decl in &self.declarations {
-            let Declaration::ValueDecl(value_decl) = decl; {
-                if value_decl.identifier == identifier.name {
-                    identifier.id_kind = IdKind::Resolved {
-                        declaration: decl,
-                        scope: ScopeKind::GlobalNamespace(self),
-                    };
-                    return true;
-                }
-            }
+
+    /// The value as a 128 bit two's complement integer.
+    pub fn bits(&self) -> Option<u128> {
+        let magnitude = self.magnitude()?;
+        if self.negative {
+            return Some(magnitude.wrapping_neg());
         }
-        return false;
+        return Some(magnitude);
     }
-    fn into_scopekind(namespace_ptr: *const GlobalNamespace) -> ScopeKind {
-        return ScopeKind::GlobalNamespace(namespace_ptr);
+
+    pub fn str(&self) -> String {
+        if self.negative {
+            return format!("-{}", self.literal);
+        }
+        return self.literal.to_string();
     }
 }
 
-impl Scope for SubNamespace {
-    fn get_full_name(&self) -> RcStr {
-        return self.full_name.clone();
-    }
-    fn is_unique_identifier(&self, declaration: &Declaration) -> bool {
-        let Declaration::ValueDecl(value_declaration) = declaration; {
-            for decl in &self.declarations {
-                let Declaration::ValueDecl(value_decl) = decl; {
-                    if value_decl.identifier == value_declaration.identifier {
-                        return false;
-                    }
+/// A number literal as it was written, split into its parts, e.g. `0xFF_u8` into the digits `FF`, the radix 16 and the suffix `u8`.
+pub struct NumberLiteral<'a> {
+    /// The digits without the prefix, the suffix and the `_` separators.
+    /// Decimal literals also have their `.` and exponent, e.g. `1.5e-3`.
+    pub digits: String,
+    pub radix: u32,
+    /// The type suffix, which is empty when the literal does not have one.
+    pub suffix: &'a str,
+    /// Whether the literal has a fraction or an exponent.
+    pub is_decimal: bool,
+}
+
+impl<'a> NumberLiteral<'a> {
+    pub fn split(literal: &'a str) -> NumberLiteral<'a> {
+        let (radix, text) = match literal.get(..2) {
+            Some("0x") => (16, &literal[2..]),
+            Some("0o") => (8, &literal[2..]),
+            Some("0b") => (2, &literal[2..]),
+            _ => (10, literal),
+        };
+        let is_digit = |c: char| c.is_ascii_digit() || c == '_';
+        let mut is_decimal = false;
+        let end = if radix == 10 {
+            let mut end = text.find(|c| !is_digit(c)).unwrap_or(text.len());
+            if text[end..].starts_with('.') && text[end + 1..].starts_with(|c: char| c.is_ascii_digit()) {
+                is_decimal = true;
+                end += 1;
+                end += text[end..].find(|c| !is_digit(c)).unwrap_or(text.len() - end);
+            }
+            if text[end..].starts_with(['e', 'E']) {
+                let exponent = text[end + 1..].strip_prefix(['+', '-']).unwrap_or(&text[end + 1..]);
+                if exponent.starts_with(|c: char| c.is_ascii_digit()) {
+                    is_decimal = true;
+                    end = text.len() - exponent.len();
+                    end += exponent.find(|c| !is_digit(c)).unwrap_or(exponent.len());
                 }
             }
+            end
+        }
+        else {
+            // Every hex digit is read, so that a wrong digit is reported as such rather than as a suffix.
+            text.find(|c: char| !c.is_ascii_hexdigit() && c != '_').unwrap_or(text.len())
         };
-        for subnamespace in &self.subnamespaces {
-            if !subnamespace.is_unique_identifier(declaration) {
-                return false;
-            }
+        return NumberLiteral{
+            digits: text[..end].replace('_', ""),
+            radix,
+            suffix: &text[end..],
+            is_decimal,
+        };
+    }
+
+    /// The type given by the suffix, if there is a valid one.
+    pub fn primitive(&self) -> Option<Primitive> {
+        return Primitive::from_suffix(self.suffix);
+    }
+
+    /// The value of an integer literal, or `None` if it does not fit in 128 bits.
+    pub fn int_value(&self) -> Option<u128> {
+        if self.is_decimal {
+            return None;
         }
-        return true;
+        return u128::from_str_radix(&self.digits, self.radix).ok();
     }
-    fn resolve_identifier(&self, identifier: &mut ScopedId) -> bool {
-        for decl in &self.declarations {
-            let Declaration::ValueDecl(value_decl) = decl; {
-                if value_decl.identifier == identifier.name {
-                    identifier.id_kind = IdKind::Resolved {
-                        declaration: decl,
-                        scope: ScopeKind::SubNamespace(self),
-                    };
-                    return true;
-                }
-            }
+
+    /// The value in decimal notation, as it is read for floating point types.
+    pub fn decimal_str(&self) -> Option<String> {
+        if self.radix == 10 {
+            return Some(self.digits.clone());
         }
-        return false;
+        return self.int_value().map(|value| value.to_string());
     }
-    fn into_scopekind(namespace_ptr: *const SubNamespace) -> ScopeKind {
-        return ScopeKind::SubNamespace(namespace_ptr);
+}
+
+#[derive(Copy,Clone,Debug,Eq,PartialEq)]
+pub enum UnaryOp {
+    Neg,
+}
+
+impl UnaryOp {
+    pub fn from_str(string: &str) -> Option<UnaryOp> {
+        return match string {
+            "-" => Some(UnaryOp::Neg),
+            _ => None,
+        };
+    }
+
+    pub fn str(&self) -> &'static str {
+        return match self {
+            UnaryOp::Neg => "-",
+        };
     }
 }
 
-impl Scope for ValueDecl {
-    fn get_full_name(&self) -> RcStr {
-        todo!("Figure out what to do here");
+#[derive(Copy,Clone,Debug,Eq,PartialEq)]
+pub enum Associativity {
+    Left,
+    /// The operator cannot be chained without parentheses, e.g. `a < b < c`.
+    None,
+}
+
+/// Binary operators, from lowest to highest precedence:
+///
+/// | Operators          | Associativity |
+/// |--------------------|---------------|
+/// | `==` `!=`          | none          |
+/// | `<` `<=` `>` `>=`  | none          |
+/// | `+` `-`            | left          |
+/// | `*` `/`            | left          |
+///
+/// Unary operators bind tighter than all binary operators,
+/// and calls and field accesses bind tighter than unary operators.
+#[derive(Copy,Clone,Debug,Eq,PartialEq)]
+pub enum BinaryOp {
+    Add, Sub, Mul, Div,
+    Eq, Ne, Lt, Le, Gt, Ge,
+}
+
+impl BinaryOp {
+    pub fn from_str(string: &str) -> Option<BinaryOp> {
+        use BinaryOp::*;
+        return match string {
+            "+"  => Some(Add),
+            "-"  => Some(Sub),
+            "*"  => Some(Mul),
+            "/"  => Some(Div),
+            "==" => Some(Eq),
+            "!=" => Some(Ne),
+            "<"  => Some(Lt),
+            "<=" => Some(Le),
+            ">"  => Some(Gt),
+            ">=" => Some(Ge),
+            _ => None,
+        };
     }
-    fn is_unique_identifier(&self, declaration: &Declaration) -> bool {
-        let Declaration::ValueDecl(value_declaration) = declaration; {
-            // Arguments
-            if let TypeKind::FuncType(func_type) = &self.type_kind {
-                for arg in func_type.arguments.into_iter() {
-                    match &arg.decl {
-                        Declaration::ValueDecl(value_decl) => {
-                            if value_decl.identifier == value_declaration.identifier {
-                                return false;
-                            }
-                        }
-                    }
-                }
-            }
-            else {
-                panic!("Only function types shouls can be scopes. If reached, this is a bug.");
-            }
-            // Earlier declarations
-            match &self.decl_kind {
-                DeclKind::EmptyDecl => panic!("Empty declarations should never contain declarations. If reached, this is a bug."),
-                DeclKind::FuncDecl(expression) => {
-                    match expression {
-                        //Expression::Block...
-                        _ => return true,
-                    }
-                }
-                DeclKind::AssignDecl(lambda) => {
-                    match lambda {
-                        //Expression::Lambda...
-                        _ => todo!(),
-                    }
-                }
-            }
+
+    pub fn str(&self) -> &'static str {
+        use BinaryOp::*;
+        return match self {
+            Add => "+",
+            Sub => "-",
+            Mul => "*",
+            Div => "/",
+            Eq  => "==",
+            Ne  => "!=",
+            Lt  => "<",
+            Le  => "<=",
+            Gt  => ">",
+            Ge  => ">=",
         };
     }
-    fn resolve_identifier(&self, identifier: &mut ScopedId) -> bool {
-        if let TypeKind::FuncType(func_type) = &self.type_kind {
-            for arg in func_type.arguments.into_iter() {
-                match &arg.decl {
-                    Declaration::ValueDecl(value_decl) => {
-                        if value_decl.identifier == identifier.name {
-                            identifier.id_kind = IdKind::Resolved {
-                                declaration: &arg.decl,
-                                scope: ScopeKind::Function(self),
-                            };
-                            return true;
-                        }
-                    }
-                }
-            }
-            return false;
-        }
-        else {
-            panic!("Only function types shouls can be scopes. If reached, this is a bug.");
-        }
+
+    pub const fn precedence(&self) -> u8 {
+        use BinaryOp::*;
+        return match self {
+            Eq | Ne => 1,
+            Lt | Le | Gt | Ge => 2,
+            Add | Sub => 3,
+            Mul | Div => 4,
+        };
     }
-    fn into_scopekind(function_ptr: *const ValueDecl) -> ScopeKind {
-        match unsafe {&*function_ptr}.type_kind {
-            TypeKind::FuncType(..) => {},
-            TypeKind::Inferred => panic!("Cannot create scope for inferred function"),
-            _ => panic!("Cannot create scope for non-function"),
-        }
-        return ScopeKind::Function(function_ptr);
+
+    pub const fn associativity(&self) -> Associativity {
+        use BinaryOp::*;
+        return match self {
+            Eq | Ne | Lt | Le | Gt | Ge => Associativity::None,
+            Add | Sub | Mul | Div => Associativity::Left,
+        };
+    }
+
+    /// Whether the operator compares its operands and results in a `Bool`.
+    pub const fn is_comparison(&self) -> bool {
+        use BinaryOp::*;
+        return match self {
+            Eq | Ne | Lt | Le | Gt | Ge => true,
+            Add | Sub | Mul | Div => false,
+        };
     }
 }
 
-impl Scope for ScopeKind {
-    fn get_full_name(&self) -> RcStr {
-        match *self {
-            ScopeKind::GlobalNamespace(namespace_ptr) => unsafe {&*namespace_ptr}.get_full_name(),
-            ScopeKind::SubNamespace(namespace_ptr) => unsafe {&*namespace_ptr}.get_full_name(),
-            ScopeKind::Function(function_ptr) => unsafe {&*function_ptr}.get_full_name(),
-            //ScopeKind::Block(block_ptr) => unsafe {&*block_ptr}.get_namespace_full_name(),
-        }
+#[derive(Clone,Debug)]
+pub struct ScopedId {
+    pub name: RcStr,
+    /// The span of the whole path, e.g. `Std.Mem.copy`.
+    pub span: Span,
+    /// Where the resolution of the identifier is stored in the arena.
+    pub id: ExprId,
+}
+
+#[derive(Clone,Debug)]
+pub enum IdKind {
+    Resolved {
+        declaration: DeclId,
+        /// The scope that the declaration was found in.
+        scope: ScopeId,
+    },
+    Unresolved {
+        scope_used: ScopeId,
+        scope_described: Box<[IdStr]>,
+    },
+}
+
+/// A scope that identifiers are looked up in.
+#[derive(Debug)]
+pub struct Scope {
+    pub kind: ScopeKind,
+    /// The scope that identifiers which are not found in this one are looked up in next.
+    /// Only the global namespace has no parent.
+    pub parent: Option<ScopeId>,
+}
+
+#[derive(Debug)]
+pub enum ScopeKind {
+    Namespace(Namespace),
+    /// The arguments of a function. The parent is the scope that the function is declared in.
+    Function(DeclId),
+    /// The start of a block, before any of its declarations.
+    Block,
+    /// A declaration in a block. Each declaration in a block is a scope in the previous one,
+    /// so that a declaration is only visible to the statements after it.
+    Declaration(DeclId),
+}
+
+/// A `{ ... }` block of statements. Declarations in a block are only visible to the statements after them.
+#[derive(Clone,Debug)]
+pub struct Block {
+    pub statements: Vec<Statement>,
+    /// The scope at the start of the block.
+    pub scope: ScopeId,
+    /// The span from the opening to the closing bracket.
+    pub span: Span,
+}
+
+#[derive(Clone,Debug)]
+pub enum Statement {
+    Declaration(Declaration),
+    Expression(Expression),
+    Return {
+        expression: Expression,
+        span: Span,
+    },
+    /// `yield label = expression;`, which gives the labeled block its value and leaves it.
+    Yield {
+        label: RcStr,
+        /// The declaration of the labeled block.
+        target: DeclId,
+        expression: Expression,
+        span: Span,
+    },
+    Break {
+        span: Span,
+    },
+    Continue {
+        span: Span,
+    },
+    /// `name = expression;`, which stores a new value in a `var`.
+    Assign {
+        target: ScopedId,
+        expression: Expression,
+        span: Span,
+    },
+}
+
+impl Statement {
+    /// Whether every path through the statement ends in a `return`.
+    /// `is_exhaustive` tells whether a `switch` without an `else` case matches every value, which depends on the type of the value.
+    pub fn always_returns(&self, arena: &Arena, is_exhaustive: &dyn Fn(&SwitchExpr) -> bool) -> bool {
+        return self.always_ends(arena, &|statement| matches!(statement, Statement::Return{..}), is_exhaustive);
     }
-    fn is_unique_identifier(&self, declaration: &Declaration) -> bool {
-        match *self {
-            ScopeKind::GlobalNamespace(namespace_ptr) => unsafe {&*namespace_ptr}.is_unique_identifier(declaration),
-            ScopeKind::SubNamespace(namespace_ptr) => unsafe {&*namespace_ptr}.is_unique_identifier(declaration),
-            ScopeKind::Function(function_ptr) => unsafe {&*function_ptr}.is_unique_identifier(declaration),
-            //ScopeKind::Block(block_ptr) => unsafe {&*block_ptr}.is_unique_identifier(declaration),
-        }
+
+    /// Whether every path through the statement leaves the enclosing labeled block,
+    /// with a `yield`, `return`, `break` or `continue`.
+    pub fn always_exits(&self, arena: &Arena, is_exhaustive: &dyn Fn(&SwitchExpr) -> bool) -> bool {
+        return self.always_ends(arena, &|statement| matches!(statement, Statement::Return{..} | Statement::Yield{..} | Statement::Break{..} | Statement::Continue{..}), is_exhaustive);
     }
-    fn resolve_identifier(&self, identifier: &mut ScopedId) -> bool {
-        match *self {
-            ScopeKind::GlobalNamespace(namespace_ptr) => unsafe {&*namespace_ptr}.resolve_identifier(identifier),
-            ScopeKind::SubNamespace(namespace_ptr) => unsafe {&*namespace_ptr}.resolve_identifier(identifier),
-            ScopeKind::Function(function_ptr) => unsafe {&*function_ptr}.resolve_identifier(identifier),
-            //ScopeKind::Block(block_ptr) => unsafe {&*block_ptr}.resolve_identifier(identifier),
+
+    /// Whether every path through the statement ends in a statement for which `is_end` is true.
+    fn always_ends(&self, arena: &Arena, is_end: &dyn Fn(&Statement) -> bool, is_exhaustive: &dyn Fn(&SwitchExpr) -> bool) -> bool {
+        if is_end(self) {
+            return true;
         }
+        return match self {
+            // A labeled block is only left past its declaration by the ends that are not a `yield` to itself.
+            Statement::Declaration(Declaration::ValueDecl(id)) => match &arena[*id].decl_kind {
+                DeclKind::BlockDecl(block) => {
+                    let is_outer_end = |statement: &Statement| is_end(statement) && !matches!(statement, Statement::Yield{target, ..} if target == id);
+                    block.statements.iter().any(|statement| statement.always_ends(arena, &is_outer_end, is_exhaustive))
+                }
+                _ => false,
+            },
+            Statement::Expression(Expression::Block(block)) => block.statements.iter().any(|statement| statement.always_ends(arena, is_end, is_exhaustive)),
+            Statement::Expression(Expression::If(if_expr)) => match &if_expr.else_branch {
+                Some(else_branch) => if_expr.then_branch.always_ends(arena, is_end, is_exhaustive) && else_branch.always_ends(arena, is_end, is_exhaustive),
+                None => false,
+            },
+            Statement::Expression(Expression::Switch(switch)) => {
+                // Without an `else` case, a value that no case matches continues after the switch.
+                let default_ends = match &switch.default {
+                    Some(default) => default.always_ends(arena, is_end, is_exhaustive),
+                    None => is_exhaustive(switch),
+                };
+                default_ends && switch.cases.iter().all(|case| case.body.always_ends(arena, is_end, is_exhaustive))
+            }
+            _ => false,
+        };
+    }
+}
+
+impl Block {
+    /// Whether every path through the block ends in a `return`.
+    pub fn always_returns(&self, arena: &Arena, is_exhaustive: &dyn Fn(&SwitchExpr) -> bool) -> bool {
+        return self.statements.iter().any(|statement| statement.always_returns(arena, is_exhaustive));
+    }
+
+    /// Whether every path through the block leaves the enclosing labeled block.
+    pub fn always_exits(&self, arena: &Arena, is_exhaustive: &dyn Fn(&SwitchExpr) -> bool) -> bool {
+        return self.statements.iter().any(|statement| statement.always_exits(arena, is_exhaustive));
     }
-    fn into_scopekind(scoperef: *const ScopeKind) -> ScopeKind {
-        return unsafe {&*scoperef}.clone();
-    }
-}
-
-impl ScopeKind {
-    pub fn from_ptr<S: Scope>(pointer: *const S) -> ScopeKind {
-        return S::into_scopekind(pointer);
-    }
-}
-
-// TODO
-
-// #[derive(Debug)]
-// pub struct Block {
-//     pub name: Option<IdStr>,
-//     pub statements: Vec<Statement>,
-//     pub parent: *mut ScopeRef,
-// }
-//
-// #[derive(Debug)]
-// pub enum Statement {
-//     Declaration(Declaration),
-//     Block(Block),
-// }
-//
-// impl Scope for Block {
-//     fn get_module_full_name(&self) -> IdStr {
-//         let mut scope = self;
-//         loop { match unsafe {&*scope.parent} {
-//             ScopeRef::Module(module_ptr) => return unsafe {&**module_ptr}.get_module_full_name(),
-//             ScopeRef::Block(block_ptr) => {
-//                 scope = unsafe {&**block_ptr};
-//             }
-//         }}
-//     }
-//     fn is_unique_identifier(&self, declaration: &Declaration) -> bool {
-//         for stmt in &self.statements {
-//             match stmt {
-//                 Statement::Declaration(decl) => {
-//                     if ptr::eq(decl, declaration) {
-//                         return true;
-//                     }
-//                     if decl.identifier == declaration.identifier {
-//                         return false;
-//                     }
-//                 }
-//                 Statement::Block(_) => {
-//                     continue;
-//                 }
-//             }
-//         }
-//         return true;
-//     }
-//     fn resolve_identifier(&self, identifier: &mut ScopedId) -> bool {
-//         for stmt in &self.statements {
-//             match stmt {
-//                 Statement::Declaration(decl) => {
-//                     if decl.identifier == identifier.name {
-//                         identifier.id_type = IdentifierType::Resolved{
-//                             declaration: decl,
-//                             scope: ScopeRef::Block(self),
-//                         };
-//                         return true;
-//                     }
-//                 }
-//                 Statement::Block(..) => {
-//                     continue;
-//                 }
-//             }
-//         }
-//         return false;
-//     }
-//     fn into_scoperef(block: *const Block) -> ScopeRef {
-//         return ScopeRef::Block(block);
-//     }
-// }
+}
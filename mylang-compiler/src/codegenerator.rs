@@ -1,18 +1,116 @@
+use std::cell::RefCell;
 use std::collections::{HashMap, VecDeque};
+use std::fmt::Display;
+use std::path::Path;
 
+use inkwell::{AddressSpace, FloatPredicate, IntPredicate, OptimizationLevel};
 use inkwell::module::Linkage;
-use inkwell::types::{StringRadix, BasicType, BasicMetadataTypeEnum, FloatType, BasicTypeEnum};
-use inkwell::{values::FunctionValue, types::IntType};
+use inkwell::passes::PassManager;
+use inkwell::targets::{CodeModel, FileType, InitializationConfig, RelocMode, Target, TargetMachine};
+use inkwell::types::{BasicType, BasicMetadataTypeEnum, FloatType, BasicTypeEnum, StructType};
+use inkwell::basic_block::BasicBlock;
+use inkwell::values::{BasicValue, BasicValueEnum, FunctionValue, GlobalValue, IntValue, PointerValue, StructValue};
+use inkwell::types::IntType;
 use inkwell::{context::Context, builder::Builder, module::Module};
 
-use crate::ast::{self, Scope};
+use crate::ast::{self, RcStr};
+use crate::diagnostics::{Diagnostic, Severity};
+use crate::typechecker::{self, Constant, TypedProgram};
 
 type Queue<T> = VecDeque<T>;
 
-pub fn generate(mut program: ast::Program) -> ast::RcStr {
+pub type CodeGenError = Diagnostic;
+
+fn codegen_error<S: Display>(message: S) -> CodeGenError {
+    return Diagnostic::new(Severity::Error, "E0501", message.to_string());
+}
+
+/// An error caused by the program rather than by LLVM, which points at the code that caused it.
+fn codegen_error_at<S: Display>(message: S, span: ast::Span) -> CodeGenError {
+    return Diagnostic::error("E0501", message.to_string(), span);
+}
+
+pub type CodeGenResult<T> = Result<T, CodeGenError>;
+
+#[derive(Copy,Clone,Debug,Eq,PartialEq)]
+pub enum OutputFile {
+    Object,
+    Assembly,
+}
+
+/// Returns the textual LLVM IR of the whole program.
+pub fn generate(program: &TypedProgram) -> CodeGenResult<RcStr> {
+    let context = Context::create();
+    let module = build_module(&context, program)?;
+    return Ok(module.to_string().into());
+}
+
+/// Compiles the program for the host machine and writes it as an object or assembly file.
+pub fn write_to_file(program: &TypedProgram, output_file: OutputFile, path: &Path) -> CodeGenResult<()> {
+    let context = Context::create();
+    let module = build_module(&context, program)?;
+    let target_machine = create_host_target_machine()?;
+
+    module.set_triple(&target_machine.get_triple());
+    module.set_data_layout(&target_machine.get_target_data().get_data_layout());
+
+    let file_type = match output_file {
+        OutputFile::Object => FileType::Object,
+        OutputFile::Assembly => FileType::Assembly,
+    };
+    return target_machine.write_to_file(&module, file_type, path).map_err(codegen_error);
+}
+
+/// Compiles the program in memory and executes its `main` function with a JIT.
+/// Returns the exit code of the program.
+pub fn run(program: &TypedProgram, arguments: &[&str]) -> CodeGenResult<i32> {
     let context = Context::create();
+    let module = build_module(&context, program)?;
+
+    let main = match module.get_function("main") {
+        Some(main) => main,
+        None => return Err(codegen_error("The program does not have a `main` function")
+            .with_note("the program starts by calling `def main() -> I32` in the global namespace")),
+    };
+    match (main.get_type().get_return_type(), find_main(program)) {
+        (Some(BasicTypeEnum::IntType(_)), _) => {},
+        (_, Some(main_decl)) => return Err(codegen_error_at("The `main` function must return an integer", main_decl.identifier_span)
+            .with_label("does not return an integer")),
+        (_, None) => return Err(codegen_error("The `main` function must return an integer")),
+    };
+
+    Target::initialize_native(&InitializationConfig::default()).map_err(codegen_error)?;
+    let engine = module.create_jit_execution_engine(OptimizationLevel::None).map_err(codegen_error)?;
+
+    // Computes the globals of labeled blocks, see `build_global_ctors`.
+    engine.run_static_constructors();
+    return Ok(unsafe {engine.run_function_as_main(main, arguments)});
+}
+
+/// The declaration of `main` in the global namespace, which is the entry point of the program.
+fn find_main(program: &ast::Program) -> Option<&ast::ValueDecl> {
+    return program.arena.namespace(ast::GLOBAL_NAMESPACE).declarations.iter()
+        .map(|ast::Declaration::ValueDecl(id)| &program.arena[*id])
+        .find(|value_decl| &*value_decl.identifier == "main");
+}
+
+fn create_host_target_machine() -> CodeGenResult<TargetMachine> {
+    Target::initialize_native(&InitializationConfig::default()).map_err(codegen_error)?;
+
+    let triple = TargetMachine::get_default_triple();
+    let target = Target::from_triple(&triple).map_err(codegen_error)?;
+    let cpu = TargetMachine::get_host_cpu_name().to_string();
+    let features = TargetMachine::get_host_cpu_features().to_string();
+
+    return target.create_target_machine(&triple, &cpu, &features, OptimizationLevel::Default, RelocMode::PIC, CodeModel::Default)
+        .ok_or_else(|| codegen_error(format!("Could not create a target machine for `{}`", triple.as_str().to_string_lossy())));
+}
+
+/// Generates one module per namespace and links them all into the root module.
+/// All symbols are external while the modules are linked, and the ones that are not public are made private afterwards.
+fn build_module<'c>(context: &'c Context, program: &'c ast::Program) -> CodeGenResult<Module<'c>> {
     let builder = context.create_builder();
-    let mut codegen = CodeGen::new(&context, builder);
+    let mut codegen = CodeGen::new(context, builder, &program.arena);
 
     for full_name in program.get_all_namespace_full_names().into_iter() {
         codegen.modules.insert(full_name.clone(), codegen.context.create_module(&full_name));
@@ -24,274 +122,901 @@ pub fn generate(mut program: ast::Program) -> ast::RcStr {
         unprocessed_declarations.push_back(namespaced_declaration);
     }
 
-    for (full_name, declaration) in unprocessed_declarations.into_iter() {
+    // Every global is declared before any is defined, so that the order of the declarations does not matter.
+    for (full_name, id) in unprocessed_declarations.iter() {
+        let module = codegen.modules.get(full_name).unwrap();
+        codegen.declare_global(module, full_name, *id);
+    }
+    for (full_name, id) in unprocessed_declarations.into_iter() {
         let module = codegen.modules.get(full_name).unwrap();
+        codegen.define_global(module, full_name, *id)?;
+    }
 
-        let ast::Declaration::ValueDecl(value_decl) = declaration; {
-            codegen.add_global(module, value_decl);
+    let root_module = codegen.modules.remove("").unwrap();
+    for (_, module) in codegen.modules.drain() {
+        root_module.link_in_module(module).map_err(codegen_error)?;
+    }
+    codegen.build_global_ctors(&root_module);
+    for symbol in codegen.private_symbols.borrow().iter() {
+        if let Some(function) = root_module.get_function(symbol) {
+            function.set_linkage(Linkage::Private);
+        }
+        else if let Some(global) = root_module.get_global(symbol) {
+            global.set_linkage(Linkage::Private);
         }
     }
+    root_module.set_name(&program.name);
+    root_module.verify().map_err(codegen_error)?;
 
-    return codegen.modules.get("").unwrap().to_string().into();
+    return Ok(root_module);
 }
 
 struct CodeGen<'c> {
     context: &'c Context,
     builder: Builder<'c>,
+    arena: &'c ast::Arena,
     modules: HashMap<ast::RcStr, Module<'c>>,
+    /// Symbols that are made private once all modules have been linked.
+    private_symbols: RefCell<Vec<RcStr>>,
+    /// Functions declared inside blocks. They are private to the module of the enclosing function.
+    local_functions: RefCell<HashMap<ast::DeclId, FunctionValue<'c>>>,
+    /// Functions that compute the globals of labeled blocks, in the order they are declared.
+    initializers: RefCell<Vec<RcStr>>,
 }
 
 impl<'c> CodeGen<'c> {
-    fn new(context: &'c Context, builder: Builder<'c>) -> Self {
-        return CodeGen{context: &context, builder, modules: HashMap::new()};
+    fn new(context: &'c Context, builder: Builder<'c>, arena: &'c ast::Arena) -> Self {
+        return CodeGen{
+            context: &context,
+            builder,
+            arena,
+            modules: HashMap::new(),
+            private_symbols: RefCell::new(Vec::new()),
+            local_functions: RefCell::new(HashMap::new()),
+            initializers: RefCell::new(Vec::new()),
+        };
     }
 
-    fn add_global(&self, module: &Module<'c>, value_decl: &ast::ValueDecl) {
+    /// Declares the global or function of a declaration in a namespace, without its value or body.
+    fn declare_global(&self, module: &Module<'c>, full_name: &str, id: ast::DeclId) {
+        let value_decl = &self.arena[id];
+        let symbol = decl_symbol(full_name, value_decl);
+        if !is_exported(full_name, value_decl) {
+            self.private_symbols.borrow_mut().push(symbol.clone());
+        }
         match &value_decl.decl_kind {
-            ast::DeclKind::EmptyDecl => {
-                todo!();
+            ast::DeclKind::AssignDecl(..) => {
+                let global = self.get_or_declare_global(module, &symbol, get_type_primitive(self.context, &value_decl.type_kind));
+                if value_decl.decl_keyword != ast::DeclKeyword::Var {
+                    global.set_constant(true);
+                }
             }
-            ast::DeclKind::AssignDecl(expression) => {
-                self.add_global_assign(module, expression, value_decl);
+            // Not constant, since the value is stored when the program starts.
+            ast::DeclKind::BlockDecl(..) => {
+                self.get_or_declare_global(module, &symbol, get_type_primitive(self.context, &value_decl.type_kind));
             }
-            ast::DeclKind::FuncDecl(expression) => {
-                self.add_global_function(module, expression, value_decl);
+            ast::DeclKind::FuncDecl(..) | ast::DeclKind::ExternDecl => {
+                self.get_or_declare_function(module, &symbol, value_decl);
             }
+            ast::DeclKind::EmptyDecl => unreachable!("Global without a value"),
+            ast::DeclKind::LoopDecl{..} => unreachable!("Global loop variable"),
         }
     }
 
-    fn add_global_assign(&self, module: &Module<'c>, expression: &ast::Expression, value_decl: &ast::ValueDecl) {
-        match expression {
-            ast::Expression::Identifier(ref other_identifier) => {
-                match other_identifier.id_kind {
-                    ast::IdKind::Resolved{scope: other_scope, ..} => {
-                        'is_integer: {
-                            let int_type = match try_get_type_int(self.context, &value_decl.type_kind) {
-                                Some(int_type) => int_type,
-                                None => break 'is_integer,
-                            };
-                            self.add_global_assign_identifier_primitive(module, value_decl, int_type, other_identifier, other_scope);
-                            return;
-                        }
-                        'is_float: {
-                            let float_type = match try_get_type_float(self.context, &value_decl.type_kind) {
-                                Some(float_type) => float_type,
-                                None => break 'is_float,
-                            };
-                            self.add_global_assign_identifier_primitive(module, value_decl, float_type, other_identifier, other_scope);
-                            return;
-                        }
-                        panic!("Identifier of this type is not implemented");
-                    }
-                    ast::IdKind::Unresolved{..} => panic!("Unknown identifier"),
-                };
+    /// Gives the global of a declaration in a namespace its value, or generates the body of the function.
+    fn define_global(&self, module: &Module<'c>, full_name: &str, id: ast::DeclId) -> CodeGenResult<()> {
+        let value_decl = &self.arena[id];
+        let symbol = decl_symbol(full_name, value_decl);
+        match &value_decl.decl_kind {
+            ast::DeclKind::AssignDecl(..) => {
+                let global = module.get_global(&symbol).expect("Global was not declared before it was defined");
+                let constant = typechecker::constant_value(self.arena, id);
+                global.set_initializer(&self.build_constant(module, &constant, &value_decl.type_kind));
             }
-            ast::Expression::Integer(literal) => {
-                if is_type_int(&value_decl.type_kind) {
-                    self.add_global_assign_int(module, value_decl, literal);
-                }
-                else if is_type_float(&value_decl.type_kind) {
-                    self.add_global_assign_float(module, value_decl, literal);
-                }
-                else {
-                    todo!("Integer literal of this type is not implemented");
-                }
+            ast::DeclKind::FuncDecl(expression) => {
+                let function = module.get_function(&symbol).expect("Function was not declared before it was defined");
+                return FunctionCodeGen::new(self, module, function, id).build_body(expression);
             }
-            ast::Expression::Decimal(literal) => {
-                if is_type_float(&value_decl.type_kind) {
-                    self.add_global_assign_float(module, value_decl, literal);
-                }
-                else {
-                    todo!("Decimal literal of this type is not implemented");
-                }
+            // The global starts as zero, and a private function that runs before `main` stores the value of the block.
+            ast::DeclKind::BlockDecl(block) => {
+                let global = module.get_global(&symbol).expect("Global was not declared before it was defined");
+                global.set_initializer(&get_type_primitive(self.context, &value_decl.type_kind).const_zero());
+
+                let initializer = format!("{}.init", symbol);
+                let function = module.add_function(&initializer, self.context.void_type().fn_type(&[], false), None);
+                self.private_symbols.borrow_mut().push(initializer.as_str().into());
+                self.initializers.borrow_mut().push(initializer.as_str().into());
+                return FunctionCodeGen::new_initializer(self, module, function).build_initializer(id, global.as_pointer_value(), block);
             }
-            ast::Expression::Bool(literal) => {
-                if is_type_int(&value_decl.type_kind) {
-                    self.add_global_assign_int(module, value_decl, if *literal { "1" } else { "0" });
-                }
-                else {
-                    todo!("Boolean literal of this type is not implemented");
+            // Only declared, since they are defined outside of the program.
+            ast::DeclKind::ExternDecl => {},
+            ast::DeclKind::EmptyDecl | ast::DeclKind::LoopDecl{..} => unreachable!("Not a global declaration"),
+        }
+        return Ok(());
+    }
+
+    /// Adds the initializers of the globals of labeled blocks to `llvm.global_ctors`, which runs them before `main`.
+    fn build_global_ctors(&self, root_module: &Module<'c>) {
+        let initializers = self.initializers.borrow();
+        if initializers.is_empty() {
+            return;
+        }
+        let priority_type = self.context.i32_type();
+        let pointer_type = self.context.ptr_type(AddressSpace::default());
+        let entry_type = self.context.struct_type(&[priority_type.into(), pointer_type.into(), pointer_type.into()], false);
+        let entries: Vec<StructValue> = initializers.iter().map(|initializer| {
+            let function = root_module.get_function(initializer).expect("Initializer was not linked");
+            let priority = priority_type.const_int(65535, false);
+            return entry_type.const_named_struct(&[priority.into(), function.as_global_value().as_pointer_value().into(), pointer_type.const_null().into()]);
+        }).collect();
+
+        let ctors = entry_type.const_array(&entries);
+        let global = root_module.add_global(ctors.get_type(), None, "llvm.global_ctors");
+        global.set_linkage(Linkage::Appending);
+        global.set_initializer(&ctors);
+    }
+
+    /// Builds the value of a global, which the type checker computed.
+    fn build_constant(&self, module: &Module<'c>, constant: &Constant, type_kind: &ast::TypeKind) -> BasicValueEnum<'c> {
+        return match constant {
+            Constant::Int(bits) => const_int_bits(get_type_int(self.context, type_kind), *bits).into(),
+            Constant::FloatLiteral(literal) => get_type_float(self.context, type_kind).const_float_from_string(literal).into(),
+            Constant::Float(value) => get_type_float(self.context, type_kind).const_float(*value).into(),
+            Constant::Bool(value) => get_type_int(self.context, type_kind).const_int(*value as u64, false).into(),
+            Constant::Str(literal) => self.const_str(module, literal).into(),
+        };
+    }
+
+    /// Returns a string that points to a private constant global with the bytes of the literal.
+    /// The bytes are followed by a zero byte that is not counted in the length, so C functions can also read them as a C string.
+    fn const_str(&self, module: &Module<'c>, literal: &str) -> StructValue<'c> {
+        let bytes = self.context.const_string(literal.as_bytes(), true);
+        let global = module.add_global(bytes.get_type(), None, "str");
+        global.set_linkage(Linkage::Private);
+        global.set_constant(true);
+        global.set_unnamed_addr(true);
+        global.set_initializer(&bytes);
+
+        let length = self.context.i64_type().const_int(literal.len() as u64, false);
+        return get_type_str(self.context).const_named_struct(&[global.as_pointer_value().into(), length.into()]);
+    }
+
+    /// Returns the global with the symbol in the module.
+    /// If it has not been defined or used in the module yet, it is declared, so it can be defined by another module.
+    fn get_or_declare_global<T: BasicType<'c>>(&self, module: &Module<'c>, symbol: &str, value_type: T) -> GlobalValue<'c> {
+        if let Some(global) = module.get_global(symbol) {
+            return global;
+        }
+        return module.add_global(value_type, None, symbol);
+    }
+
+    /// Returns the function with the symbol in the module.
+    /// If it has not been defined or used in the module yet, it is declared, so it can be defined by another module.
+    fn get_or_declare_function(&self, module: &Module<'c>, symbol: &str, value_decl: &ast::ValueDecl) -> FunctionValue<'c> {
+        if let Some(function) = module.get_function(symbol) {
+            return function;
+        }
+        return self.declare_function(module, symbol, value_decl);
+    }
+
+    fn declare_function(&self, module: &Module<'c>, symbol: &str, value_decl: &ast::ValueDecl) -> FunctionValue<'c> {
+        let func_type = match &value_decl.type_kind {
+            ast::TypeKind::FuncType(func_type) => func_type,
+            _ => panic!("Function did not have a function type"),
+        };
+        let arg_types = get_argument_types(self.context, self.arena, &func_type.arguments);
+        let function_type = get_type_primitive(self.context, &func_type.return_type).fn_type(&arg_types, false);
+        return module.add_function(symbol, function_type, None);
+    }
+}
+
+/// Generates the body of a single function.
+struct FunctionCodeGen<'a, 'c> {
+    codegen: &'a CodeGen<'c>,
+    module: &'a Module<'c>,
+    function: FunctionValue<'c>,
+    /// The type of the function, or `None` for the initializer of a global labeled block.
+    func_type: Option<&'a ast::FuncType>,
+    /// The stack slots of the arguments and local values.
+    locals: HashMap<ast::DeclId, PointerValue<'c>>,
+    /// The blocks that `continue` and `break` jump to, for each loop that is being generated.
+    loops: Vec<(BasicBlock<'c>, BasicBlock<'c>)>,
+    /// The blocks after each labeled block that is being generated, which `yield` jumps to.
+    labels: HashMap<ast::DeclId, BasicBlock<'c>>,
+}
+
+impl<'a, 'c> FunctionCodeGen<'a, 'c> {
+    fn new(codegen: &'a CodeGen<'c>, module: &'a Module<'c>, function: FunctionValue<'c>, id: ast::DeclId) -> Self {
+        let func_type = match &codegen.arena[id].type_kind {
+            ast::TypeKind::FuncType(func_type) => func_type,
+            _ => panic!("Function did not have a function type"),
+        };
+        return FunctionCodeGen{codegen, module, function, func_type: Some(func_type), locals: HashMap::new(), loops: Vec::new(), labels: HashMap::new()};
+    }
+
+    fn new_initializer(codegen: &'a CodeGen<'c>, module: &'a Module<'c>, function: FunctionValue<'c>) -> Self {
+        return FunctionCodeGen{codegen, module, function, func_type: None, locals: HashMap::new(), loops: Vec::new(), labels: HashMap::new()};
+    }
+
+    fn build_body(mut self, body: &ast::Expression) -> CodeGenResult<()> {
+        let codegen = self.codegen;
+        let func_type = self.func_type.expect("Function did not have a function type");
+        let builder = &codegen.builder;
+        let entry_block = codegen.context.append_basic_block(self.function, "entry");
+        builder.position_at_end(entry_block);
+
+        for (index, argument) in func_type.arguments.iter().enumerate() {
+            let ast::Declaration::ValueDecl(argument_id) = argument.decl;
+            let parameter = self.function.get_nth_param(index as u32).unwrap();
+            parameter.set_name(&codegen.arena[argument_id].identifier);
+            let pointer = self.build_local(argument_id)?;
+            builder.build_store(pointer, parameter).map_err(codegen_error)?;
+        }
+
+        match body {
+            ast::Expression::Block(block) => {
+                if !self.build_block(block)? {
+                    // The type checker makes sure that every path returns.
+                    builder.build_unreachable().map_err(codegen_error)?;
                 }
             }
+            expression => {
+                let value = self.build_expression(expression, &func_type.return_type)?;
+                builder.build_return(Some(&value)).map_err(codegen_error)?;
+            }
         }
+
+        self.promote_locals();
+        return Ok(());
     }
 
-    fn add_global_assign_identifier_primitive<T: BasicType<'c> + Copy>(&self, module: &Module<'c>, value_decl: &ast::ValueDecl, value_type: T, other_identifier: &ast::ScopedId, other_scope: ast::ScopeKind) {
-        let global = module.add_global(value_type, None, &value_decl.identifier);
-        global.set_linkage(get_linkage(value_decl.public));
-        if value_decl.decl_keyword != ast::DeclKeyword::Var {
-            global.set_constant(true);
+    /// Builds the initializer of a global labeled block, where each `yield` stores to the global.
+    fn build_initializer(mut self, id: ast::DeclId, global: PointerValue<'c>, block: &ast::Block) -> CodeGenResult<()> {
+        let builder = &self.codegen.builder;
+        let entry_block = self.codegen.context.append_basic_block(self.function, "entry");
+        let end_block = self.codegen.context.append_basic_block(self.function, "endlabel");
+        builder.position_at_end(entry_block);
+        self.locals.insert(id, global);
+        self.labels.insert(id, end_block);
+
+        if !self.build_block(block)? {
+            builder.build_unreachable().map_err(codegen_error)?;
         }
+        builder.position_at_end(end_block);
+        builder.build_return(None).map_err(codegen_error)?;
 
-        let other_module = self.modules.get(&other_scope.get_full_name()).unwrap();
-        let other_global = other_module.get_global(&other_identifier.name).expect("Likely failed because identifier had not yet been declared");
+        self.promote_locals();
+        return Ok(());
+    }
 
-        global.set_initializer(&other_global.get_initializer().unwrap());
+    /// Promotes the stack slots of the locals to registers.
+    fn promote_locals(&self) {
+        let pass_manager = PassManager::create(self.module);
+        pass_manager.add_promote_memory_to_register_pass();
+        pass_manager.initialize();
+        pass_manager.run_on(&self.function);
+    }
 
-        // Maybe make having non literal values for compile time variables illegal at this point,
-        // and instead interpret and evaluate any other expressions during an earlier compilation stage.
-        match &value_decl.decl_keyword {
-            ast::DeclKeyword::Def => {
+    /// Allocates a stack slot for a local value at the start of the function, where mem2reg can promote it.
+    fn build_local(&mut self, id: ast::DeclId) -> CodeGenResult<PointerValue<'c>> {
+        let value_decl = &self.codegen.arena[id];
+        let builder = self.codegen.context.create_builder();
+        let entry_block = self.function.get_first_basic_block().unwrap();
+        match entry_block.get_first_instruction() {
+            Some(instruction) => builder.position_before(&instruction),
+            None => builder.position_at_end(entry_block),
+        };
+        let value_type = get_type_primitive(self.codegen.context, &value_decl.type_kind);
+        let pointer = builder.build_alloca(value_type, &value_decl.identifier).map_err(codegen_error)?;
+        self.locals.insert(id, pointer);
+        return Ok(pointer);
+    }
+
+    /// Returns whether the block ended with a jump (e.g. `return`), after which no more code can be generated.
+    fn build_block(&mut self, block: &ast::Block) -> CodeGenResult<bool> {
+        for statement in &block.statements {
+            if self.build_statement(statement)? {
+                return Ok(true);
+            }
+        }
+        return Ok(false);
+    }
+
+    /// Returns whether the statement ended with a jump, after which no more code can be generated.
+    fn build_statement(&mut self, statement: &ast::Statement) -> CodeGenResult<bool> {
+        let codegen = self.codegen;
+        match statement {
+            ast::Statement::Declaration(ast::Declaration::ValueDecl(id)) => {
+                self.build_local_decl(*id)?;
+                return Ok(false);
+            }
+            ast::Statement::Expression(ast::Expression::Block(block)) => return self.build_block(block),
+            ast::Statement::Expression(ast::Expression::If(if_expr)) => return self.build_if_statement(if_expr),
+            ast::Statement::Expression(ast::Expression::While(while_expr)) => {
+                self.build_while(while_expr)?;
+                return Ok(false);
+            }
+            ast::Statement::Expression(ast::Expression::For(for_expr)) => {
+                self.build_for(for_expr)?;
+                return Ok(false);
+            }
+            ast::Statement::Expression(ast::Expression::Switch(switch)) => return self.build_switch_statement(switch),
+            ast::Statement::Expression(expression) => {
+                if let Some(type_kind) = typechecker::type_of_expression(codegen.arena, expression) {
+                    if is_type_primitive(&type_kind) {
+                        self.build_expression(expression, &type_kind)?;
+                    }
+                }
+                return Ok(false);
+            }
+            ast::Statement::Return{expression, ..} => {
+                let func_type = self.func_type.expect("`return` outside of a function");
+                let value = self.build_expression(expression, &func_type.return_type)?;
+                codegen.builder.build_return(Some(&value)).map_err(codegen_error)?;
+                return Ok(true);
+            }
+            ast::Statement::Yield{target, expression, ..} => {
+                let target_decl = &codegen.arena[*target];
+                let value = self.build_expression(expression, &target_decl.type_kind)?;
+                codegen.builder.build_store(self.locals[target], value).map_err(codegen_error)?;
+                codegen.builder.build_unconditional_branch(self.labels[target]).map_err(codegen_error)?;
+                return Ok(true);
+            }
+            ast::Statement::Assign{target, expression, ..} => {
+                let (id, scope) = get_resolved_decl(codegen.arena, target);
+                let value = self.build_expression(expression, &codegen.arena[id].type_kind)?;
+                let pointer = self.get_value_pointer(id, scope, target.span)?;
+                codegen.builder.build_store(pointer, value).map_err(codegen_error)?;
+                return Ok(false);
             }
-            ast::DeclKeyword::Var => {
-                // let pointer = other_global.as_pointer_value();
-                // println!("null: {}, undef: {}, const: {}, ", pointer.is_null(), pointer.is_undef(), pointer.is_const());
-                // println!("type: {}, name: {}", pointer.get_type(), pointer.get_name().to_str().unwrap());
-                // let pointee = &self.builder.build_load(value_type, pointer, "load2").unwrap();
-                // global.set_initializer(pointee);
+            ast::Statement::Break{..} => {
+                let (_, break_block) = *self.loops.last().expect("`break` outside of a loop");
+                codegen.builder.build_unconditional_branch(break_block).map_err(codegen_error)?;
+                return Ok(true);
             }
-            ast::DeclKeyword::Let => {
-                // let pointer = other_global.as_pointer_value();
-                // println!("null: {}, undef: {}, const: {}, ", pointer.is_null(), pointer.is_undef(), pointer.is_const());
-                // println!("type: {}, name: {}", pointer.get_type(), pointer.get_name().to_str().unwrap());
-                // let pointee = &self.builder.build_load(value_type, pointer, "load2").unwrap();
-                // global.set_initializer(pointee);
+            ast::Statement::Continue{..} => {
+                let (continue_block, _) = *self.loops.last().expect("`continue` outside of a loop");
+                codegen.builder.build_unconditional_branch(continue_block).map_err(codegen_error)?;
+                return Ok(true);
             }
         }
     }
 
-    fn add_global_assign_int(&self, module: &Module<'c>, value_decl: &ast::ValueDecl, literal: &str) {
-        let int_type = get_type_int(self.context, &value_decl.type_kind);
+    fn build_condition(&mut self, condition: &ast::Expression) -> CodeGenResult<IntValue<'c>> {
+        return Ok(self.build_expression(condition, &ast::TypeKind::Primitive(ast::Primitive::Bool))?.into_int_value());
+    }
+
+    /// Returns whether both branches ended with a jump, after which no more code can be generated.
+    fn build_if_statement(&mut self, if_expr: &ast::IfExpr) -> CodeGenResult<bool> {
+        let codegen = self.codegen;
+        let builder = &codegen.builder;
+        let condition = self.build_condition(&if_expr.condition)?;
+        let then_block = codegen.context.append_basic_block(self.function, "then");
+        let else_block = codegen.context.append_basic_block(self.function, "else");
+        let merge_block = codegen.context.append_basic_block(self.function, "endif");
+        builder.build_conditional_branch(condition, then_block, else_block).map_err(codegen_error)?;
+
+        builder.position_at_end(then_block);
+        let then_jumps = self.build_statement(&if_expr.then_branch)?;
+        if !then_jumps {
+            builder.build_unconditional_branch(merge_block).map_err(codegen_error)?;
+        }
+
+        builder.position_at_end(else_block);
+        let else_jumps = match &if_expr.else_branch {
+            Some(else_branch) => self.build_statement(else_branch)?,
+            None => false,
+        };
+        if !else_jumps {
+            builder.build_unconditional_branch(merge_block).map_err(codegen_error)?;
+        }
 
-        let global = module.add_global(int_type, None, &value_decl.identifier);
-        global.set_linkage(get_linkage(value_decl.public));
-        if value_decl.decl_keyword != ast::DeclKeyword::Var {
-            global.set_constant(true);
+        if then_jumps && else_jumps {
+            merge_block.remove_from_function().map_err(|_| codegen_error("Could not remove an unreachable block"))?;
+            return Ok(true);
         }
+        builder.position_at_end(merge_block);
+        return Ok(false);
+    }
+
+    /// Builds an `if` that is used as a value, where both branches are joined with a phi node.
+    fn build_if_value(&mut self, if_expr: &ast::IfExpr, type_kind: &ast::TypeKind) -> CodeGenResult<BasicValueEnum<'c>> {
+        let (then_expression, else_expression) = match (&if_expr.then_branch, &if_expr.else_branch) {
+            (ast::Statement::Expression(then_expression), Some(ast::Statement::Expression(else_expression))) => (then_expression, else_expression),
+            _ => unreachable!("`if` value without `else`"),
+        };
+        let codegen = self.codegen;
+        let builder = &codegen.builder;
+        let condition = self.build_condition(&if_expr.condition)?;
+        let then_block = codegen.context.append_basic_block(self.function, "then");
+        let else_block = codegen.context.append_basic_block(self.function, "else");
+        let merge_block = codegen.context.append_basic_block(self.function, "endif");
+        builder.build_conditional_branch(condition, then_block, else_block).map_err(codegen_error)?;
+
+        // The branches can end in other blocks than they started in, e.g. when they contain another `if`.
+        builder.position_at_end(then_block);
+        let then_value = self.build_expression(then_expression, type_kind)?;
+        let then_end = builder.get_insert_block().unwrap();
+        builder.build_unconditional_branch(merge_block).map_err(codegen_error)?;
 
-        let value = int_type.const_int_from_string(literal, StringRadix::Decimal).expect("Failed to parse integer literal");
-        global.set_initializer(&value);
+        builder.position_at_end(else_block);
+        let else_value = self.build_expression(else_expression, type_kind)?;
+        let else_end = builder.get_insert_block().unwrap();
+        builder.build_unconditional_branch(merge_block).map_err(codegen_error)?;
+
+        builder.position_at_end(merge_block);
+        let phi = builder.build_phi(get_type_primitive(codegen.context, type_kind), "if").map_err(codegen_error)?;
+        phi.add_incoming(&[(&then_value as &dyn BasicValue<'c>, then_end), (&else_value as &dyn BasicValue<'c>, else_end)]);
+        return Ok(phi.as_basic_value());
     }
 
-    fn add_global_assign_float(&self, module: &Module<'c>, value_decl: &ast::ValueDecl, literal: &str) {
-        let float_type = get_type_float(self.context, &value_decl.type_kind);
+    fn build_while(&mut self, while_expr: &ast::WhileExpr) -> CodeGenResult<()> {
+        let codegen = self.codegen;
+        let builder = &codegen.builder;
+        let condition_block = codegen.context.append_basic_block(self.function, "while");
+        let body_block = codegen.context.append_basic_block(self.function, "loop");
+        let end_block = codegen.context.append_basic_block(self.function, "endwhile");
+        builder.build_unconditional_branch(condition_block).map_err(codegen_error)?;
+
+        builder.position_at_end(condition_block);
+        let condition = self.build_condition(&while_expr.condition)?;
+        builder.build_conditional_branch(condition, body_block, end_block).map_err(codegen_error)?;
 
-        let global = module.add_global(float_type, None, &value_decl.identifier);
-        global.set_linkage(get_linkage(value_decl.public));
-        if value_decl.decl_keyword != ast::DeclKeyword::Var {
-            global.set_constant(true);
+        builder.position_at_end(body_block);
+        self.loops.push((condition_block, end_block));
+        let body_jumps = self.build_statement(&while_expr.body)?;
+        self.loops.pop();
+        if !body_jumps {
+            builder.build_unconditional_branch(condition_block).map_err(codegen_error)?;
         }
 
-        let value = float_type.const_float_from_string(literal);
-        global.set_initializer(&value);
+        builder.position_at_end(end_block);
+        return Ok(());
     }
 
-    fn add_global_function(&self, module: &Module<'c>, expression: &ast::Expression, value_decl: &ast::ValueDecl) {
-        let (arguments, return_type) = match &value_decl.type_kind {
-            ast::TypeKind::FuncType(func_type) => (func_type.arguments.as_ref(), func_type.return_type.as_ref()),
-            _ => panic!("Function did not have a function type"),
+    fn build_for(&mut self, for_expr: &ast::ForExpr) -> CodeGenResult<()> {
+        let codegen = self.codegen;
+        let builder = &codegen.builder;
+        let variable = &codegen.arena[for_expr.variable];
+        let (start, end) = match &variable.decl_kind {
+            ast::DeclKind::LoopDecl{start, end} => (start, end),
+            _ => unreachable!("Not a loop variable"),
         };
+        let start = self.build_expression(start, &variable.type_kind)?.into_int_value();
+        let end = self.build_expression(end, &variable.type_kind)?.into_int_value();
+        let pointer = self.build_local(for_expr.variable)?;
+        builder.build_store(pointer, start).map_err(codegen_error)?;
+        let body_block = codegen.context.append_basic_block(self.function, "for");
+        let next_block = codegen.context.append_basic_block(self.function, "next");
+        let step_block = codegen.context.append_basic_block(self.function, "step");
+        let end_block = codegen.context.append_basic_block(self.function, "endfor");
+        let less_equal = if is_type_signed(&variable.type_kind) { IntPredicate::SLE } else { IntPredicate::ULE };
+        let not_empty = builder.build_int_compare(less_equal, start, end, "cmp").map_err(codegen_error)?;
+        builder.build_conditional_branch(not_empty, body_block, end_block).map_err(codegen_error)?;
 
-        if is_type_int(return_type) {
-            self.add_global_int_function(module, &value_decl, arguments, return_type, expression);
+        builder.position_at_end(body_block);
+        self.loops.push((next_block, end_block));
+        let body_jumps = self.build_statement(&for_expr.body)?;
+        self.loops.pop();
+        if !body_jumps {
+            builder.build_unconditional_branch(next_block).map_err(codegen_error)?;
         }
-        else if is_type_float(return_type) {
-            self.add_global_float_function(module, &value_decl, arguments, return_type, expression);
+
+        // The variable is compared with the end before it is increased, so a range ending at the largest value does not overflow.
+        builder.position_at_end(next_block);
+        let value = builder.build_load(start.get_type(), pointer, &variable.identifier).map_err(codegen_error)?.into_int_value();
+        let at_end = builder.build_int_compare(IntPredicate::EQ, value, end, "cmp").map_err(codegen_error)?;
+        builder.build_conditional_branch(at_end, end_block, step_block).map_err(codegen_error)?;
+
+        builder.position_at_end(step_block);
+        let next = builder.build_int_add(value, start.get_type().const_int(1, false), "next").map_err(codegen_error)?;
+        builder.build_store(pointer, next).map_err(codegen_error)?;
+        builder.build_unconditional_branch(body_block).map_err(codegen_error)?;
+
+        builder.position_at_end(end_block);
+        return Ok(());
+    }
+
+    /// Returns whether every case ended with a jump, after which no more code can be generated.
+    fn build_switch_statement(&mut self, switch: &ast::SwitchExpr) -> CodeGenResult<bool> {
+        let codegen = self.codegen;
+        let builder = &codegen.builder;
+        let case_blocks: Vec<BasicBlock> = switch.cases.iter().map(|_| codegen.context.append_basic_block(self.function, "case")).collect();
+        let default_block = switch.default.as_ref().map(|_| codegen.context.append_basic_block(self.function, "default"));
+        let merge_block = codegen.context.append_basic_block(self.function, "endswitch");
+        self.build_switch_jump(switch, &case_blocks, default_block.unwrap_or(merge_block))?;
+
+        // Without an `else` case, values that are not matched continue after the switch.
+        let mut all_jump = default_block.is_some();
+        let bodies = switch.cases.iter().map(|case| &case.body).chain(&switch.default);
+        for (body, block) in bodies.zip(case_blocks.into_iter().chain(default_block)) {
+            builder.position_at_end(block);
+            if !self.build_statement(body)? {
+                builder.build_unconditional_branch(merge_block).map_err(codegen_error)?;
+                all_jump = false;
+            }
         }
-        else {
-            todo!("Function declaration of this type is not implemented");
+
+        if all_jump {
+            merge_block.remove_from_function().map_err(|_| codegen_error("Could not remove an unreachable block"))?;
+            return Ok(true);
         }
+        builder.position_at_end(merge_block);
+        return Ok(false);
     }
 
-    fn add_global_int_function(&self, module: &Module<'c>, value_decl: &ast::ValueDecl, arguments: &[ast::Argument], return_type: &ast::TypeKind, expression: &ast::Expression) {
-        let arg_types = get_argument_types(self.context, arguments);
-        let int_type = get_type_int(self.context, return_type);
-        let function_type = int_type.fn_type(&arg_types, false);
-        let function = module.add_function(&value_decl.identifier, function_type, Some(get_linkage(value_decl.public)));
+    /// Builds a `switch` that is used as a value, where the values of the cases are joined with a phi node.
+    fn build_switch_value(&mut self, switch: &ast::SwitchExpr, type_kind: &ast::TypeKind) -> CodeGenResult<BasicValueEnum<'c>> {
+        let codegen = self.codegen;
+        let builder = &codegen.builder;
+        let case_blocks: Vec<BasicBlock> = switch.cases.iter().map(|_| codegen.context.append_basic_block(self.function, "case")).collect();
+        let default_block = codegen.context.append_basic_block(self.function, "default");
+        let merge_block = codegen.context.append_basic_block(self.function, "endswitch");
+        self.build_switch_jump(switch, &case_blocks, default_block)?;
 
-        match expression {
-            ast::Expression::Identifier(identifier) => {
-                for index in 0..arguments.len() {
-                    let argument_decl = &arguments.get(index).unwrap().decl;
-                    if &*identifier.name == match argument_decl {
-                        ast::Declaration::ValueDecl(value_decl) => &*value_decl.identifier,
-                    } {
-                        let entry_block = self.context.append_basic_block(function, "entry");
-                        self.builder.position_at_end(entry_block);
-
-                        let ast::Declaration::ValueDecl(value_decl) = argument_decl; {
-                            if !return_type.is_same_type(&value_decl.type_kind) {
-                                panic!("Cannot return a value of this type from an integer function");
-                            }
-                            let arg = function.get_nth_param(index as u32).unwrap().into_int_value();
-                            self.builder.build_return(Some(&arg)).unwrap();
+        let mut incoming = Vec::new();
+        let bodies = switch.cases.iter().map(|case| &case.body);
+        for (body, block) in bodies.zip(case_blocks) {
+            builder.position_at_end(block);
+            let value = self.build_expression(switch_value(body), type_kind)?;
+            incoming.push((value, builder.get_insert_block().unwrap()));
+            builder.build_unconditional_branch(merge_block).map_err(codegen_error)?;
+        }
+        builder.position_at_end(default_block);
+        match &switch.default {
+            Some(default) => {
+                let value = self.build_expression(switch_value(default), type_kind)?;
+                incoming.push((value, builder.get_insert_block().unwrap()));
+                builder.build_unconditional_branch(merge_block).map_err(codegen_error)?;
+            }
+            // The type checker made sure that every value is matched by a case.
+            None => { builder.build_unreachable().map_err(codegen_error)?; }
+        };
+
+        builder.position_at_end(merge_block);
+        let phi = builder.build_phi(get_type_primitive(codegen.context, type_kind), "switch").map_err(codegen_error)?;
+        let incoming: Vec<(&dyn BasicValue<'c>, BasicBlock<'c>)> = incoming.iter().map(|(value, block)| (value as &dyn BasicValue<'c>, *block)).collect();
+        phi.add_incoming(&incoming);
+        return Ok(phi.as_basic_value());
+    }
+
+    /// Builds the jump from the switched value to the block of the case that matches it, or to `default_block`.
+    /// Patterns become the cases of an LLVM `switch`, except for large ranges,
+    /// which are checked with comparisons when none of the other cases match.
+    fn build_switch_jump(&mut self, switch: &ast::SwitchExpr, case_blocks: &[BasicBlock<'c>], default_block: BasicBlock<'c>) -> CodeGenResult<()> {
+        let codegen = self.codegen;
+        let builder = &codegen.builder;
+        let value_type = typechecker::type_of_expression(codegen.arena, &switch.value).expect("The type of the switched value was not inferred");
+        let int_type = get_type_int(codegen.context, &value_type);
+        let value = self.build_expression(&switch.value, &value_type)?.into_int_value();
+        let switch_block = builder.get_insert_block().unwrap();
+
+        let mut cases = Vec::new();
+        let mut ranges = Vec::new();
+        for (case, block) in switch.cases.iter().zip(case_blocks) {
+            for pattern in case.patterns.iter() {
+                match &pattern.kind {
+                    ast::PatternKind::Bool(boolean) => cases.push((int_type.const_int(*boolean as u64, false), *block)),
+                    ast::PatternKind::Integer(literal) => cases.push((const_int_bits(int_type, pattern_bits(literal)), *block)),
+                    ast::PatternKind::Range(start, end) => {
+                        let (start, end) = (pattern_bits(start), pattern_bits(end));
+                        // One less than the number of values, which wraps correctly for both signed and unsigned ranges.
+                        let span = end.wrapping_sub(start);
+                        if span < MAX_SWITCH_RANGE {
+                            cases.extend((0..=span).map(|offset| (const_int_bits(int_type, start.wrapping_add(offset)), *block)));
+                        }
+                        else {
+                            ranges.push((const_int_bits(int_type, start), const_int_bits(int_type, end), *block));
                         }
-                        return;
                     }
-                }
-                panic!("Identifier not found in function arguments");
+                };
             }
-            ast::Expression::Integer(literal) => {
-                let entry_block = self.context.append_basic_block(function, "entry");
-                self.builder.position_at_end(entry_block);
+        }
 
-                let value = int_type.const_int_from_string(literal, StringRadix::Decimal).expect("Failed to parse integer literal");
-                self.builder.build_return(Some(&value)).unwrap();
-            }
-            ast::Expression::Decimal(_) => panic!("Cannot return a decimal literal from an integer function"),
-            ast::Expression::Bool(literal) => {
-                let entry_block = self.context.append_basic_block(function, "entry");
-                self.builder.position_at_end(entry_block);
+        let signed = is_type_signed(&value_type);
+        let (greater_equal, less_equal) = if signed { (IntPredicate::SGE, IntPredicate::SLE) } else { (IntPredicate::UGE, IntPredicate::ULE) };
+        let mut fallback = default_block;
+        for (start, end, block) in ranges.into_iter().rev() {
+            let range_block = codegen.context.insert_basic_block_after(switch_block, "range");
+            builder.position_at_end(range_block);
+            let above_start = builder.build_int_compare(greater_equal, value, start, "cmp").map_err(codegen_error)?;
+            let below_end = builder.build_int_compare(less_equal, value, end, "cmp").map_err(codegen_error)?;
+            let in_range = builder.build_and(above_start, below_end, "inrange").map_err(codegen_error)?;
+            builder.build_conditional_branch(in_range, block, fallback).map_err(codegen_error)?;
+            fallback = range_block;
+        }
+
+        builder.position_at_end(switch_block);
+        builder.build_switch(value, fallback, &cases).map_err(codegen_error)?;
+        return Ok(());
+    }
 
-                let value = int_type.const_int_from_string(if *literal { "1" } else { "0" }, StringRadix::Decimal).expect("Failed to parse integer literal");
-                self.builder.build_return(Some(&value)).unwrap();
+    fn build_local_decl(&mut self, id: ast::DeclId) -> CodeGenResult<()> {
+        let value_decl = &self.codegen.arena[id];
+        match &value_decl.decl_kind {
+            ast::DeclKind::AssignDecl(expression) => {
+                // The value is built first, since the declaration is not visible in its own value.
+                let value = self.build_expression(expression, &value_decl.type_kind)?;
+                let pointer = self.build_local(id)?;
+                self.codegen.builder.build_store(pointer, value).map_err(codegen_error)?;
+                return Ok(());
+            }
+            ast::DeclKind::FuncDecl(body) => return self.build_local_function(id, body),
+            ast::DeclKind::BlockDecl(block) => {
+                // The value is stored by each `yield`, then the code continues after the block.
+                self.build_local(id)?;
+                let end_block = self.codegen.context.append_basic_block(self.function, "endlabel");
+                self.labels.insert(id, end_block);
+                if !self.build_block(block)? {
+                    self.codegen.builder.build_unreachable().map_err(codegen_error)?;
+                }
+                self.codegen.builder.position_at_end(end_block);
+                return Ok(());
             }
+            ast::DeclKind::EmptyDecl => unreachable!("Local without a value"),
+            ast::DeclKind::ExternDecl => unreachable!("Local `extern` declaration"),
+            ast::DeclKind::LoopDecl{..} => unreachable!("Loop variable outside of `for`"),
         }
     }
 
-    fn add_global_float_function(&self, module: &Module<'c>, value_decl: &ast::ValueDecl, arguments: &[ast::Argument], return_type: &ast::TypeKind, expression: &ast::Expression) {
-        let arg_types = get_argument_types(self.context, arguments);
-        let float_type = get_type_float(self.context, return_type);
-        let function_type = float_type.fn_type(&arg_types, false);
-        let function = module.add_function(&value_decl.identifier, function_type, Some(get_linkage(value_decl.public)));
+    /// Functions declared in blocks are generated as private functions in the same module.
+    /// They cannot use the locals of the enclosing function.
+    fn build_local_function(&mut self, id: ast::DeclId, body: &ast::Expression) -> CodeGenResult<()> {
+        let value_decl = &self.codegen.arena[id];
+        let symbol = format!("{}.{}", self.function.get_name().to_string_lossy(), value_decl.identifier);
+        let function = self.codegen.declare_function(self.module, &symbol, value_decl);
+        function.set_linkage(Linkage::Private);
+        self.codegen.local_functions.borrow_mut().insert(id, function);
 
+        let insert_block = self.codegen.builder.get_insert_block();
+        FunctionCodeGen::new(self.codegen, self.module, function, id).build_body(body)?;
+        if let Some(insert_block) = insert_block {
+            self.codegen.builder.position_at_end(insert_block);
+        }
+        return Ok(());
+    }
+
+    /// Builds the value of the expression, where `type_kind` is the type the type checker gave it.
+    fn build_expression(&mut self, expression: &ast::Expression, type_kind: &ast::TypeKind) -> CodeGenResult<BasicValueEnum<'c>> {
+        let codegen = self.codegen;
+        let context = codegen.context;
+        let builder = &codegen.builder;
         match expression {
-            ast::Expression::Identifier(identifier) => {
-                for index in 0..arguments.len() {
-                    let argument_decl = &arguments.get(index).unwrap().decl;
-                    if &*identifier.name == match argument_decl {
-                        ast::Declaration::ValueDecl(value_decl) => &*value_decl.identifier,
-                    } {
-                        let entry_block = self.context.append_basic_block(function, "entry");
-                        self.builder.position_at_end(entry_block);
-
-                        let ast::Declaration::ValueDecl(value_decl) = argument_decl; {
-                            if is_type_int(&value_decl.type_kind) {
-                                panic!("Cannot return an integer value from a floating point function");
-                            }
-                            else if is_type_float(&value_decl.type_kind) {
-                                let arg = function.get_nth_param(index as u32).unwrap().into_float_value();
-                                self.builder.build_return(Some(&arg)).unwrap();
-                            }
-                            else {
-                                panic!("Cannot return a value of this type from a floating point function");
-                            }
-                        }
-                        return;
-                    }
+            ast::Expression::Integer(literal, _) => {
+                if let Some(int_type) = try_get_type_int(context, type_kind) {
+                    return Ok(const_int_bits(int_type, int_bits(literal)).into());
                 }
-                panic!("Identifier not found in function arguments");
+                return Ok(get_type_float(context, type_kind).const_float_from_string(&decimal_str(literal)).into());
+            }
+            ast::Expression::Decimal(literal, _) => {
+                return Ok(get_type_float(context, type_kind).const_float_from_string(&decimal_str(literal)).into());
+            }
+            ast::Expression::Bool(literal, _) => {
+                return Ok(get_type_int(context, type_kind).const_int(*literal as u64, false).into());
+            }
+            ast::Expression::String(literal, _) => {
+                return Ok(codegen.const_str(self.module, literal).into());
+            }
+            ast::Expression::Identifier(scoped_id) => {
+                let (id, scope) = get_resolved_decl(codegen.arena, scoped_id);
+                let pointer = self.get_value_pointer(id, scope, scoped_id.span)?;
+                let value_type = get_type_primitive(context, &codegen.arena[id].type_kind);
+                return builder.build_load(value_type, pointer, &scoped_id.name).map_err(codegen_error);
+            }
+            ast::Expression::Unary(unary) => {
+                let operand = self.build_expression(&unary.operand, type_kind)?;
+                let value: BasicValueEnum = match (unary.operator, operand) {
+                    (ast::UnaryOp::Neg, BasicValueEnum::IntValue(operand)) => builder.build_int_neg(operand, "neg").map_err(codegen_error)?.into(),
+                    (ast::UnaryOp::Neg, BasicValueEnum::FloatValue(operand)) => builder.build_float_neg(operand, "neg").map_err(codegen_error)?.into(),
+                    _ => unreachable!("Negated value is not a number"),
+                };
+                return Ok(value);
+            }
+            ast::Expression::Binary(binary) if binary.operator.is_comparison() => {
+                let operand_type = typechecker::type_of_operands(codegen.arena, binary).expect("The type of the operands was not inferred");
+                let left = self.build_expression(&binary.left, &operand_type)?;
+                let right = self.build_expression(&binary.right, &operand_type)?;
+                return self.build_comparison(binary.operator, &operand_type, left, right);
+            }
+            ast::Expression::Binary(binary) => {
+                let left = self.build_expression(&binary.left, type_kind)?;
+                let right = self.build_expression(&binary.right, type_kind)?;
+                return self.build_arithmetic(binary.operator, type_kind, left, right);
             }
-            ast::Expression::Integer(_) => panic!("Cannot return an integer literal from a floating point function"),
-            ast::Expression::Decimal(decimal) => {
-                let entry_block = self.context.append_basic_block(function, "entry");
-                self.builder.position_at_end(entry_block);
+            ast::Expression::Call(call) => {
+                let (function, func_type) = self.get_callee(&call.callee)?;
+                let mut arguments = Vec::new();
+                for (argument, parameter) in call.arguments.iter().zip(func_type.arguments.iter()) {
+                    let ast::Declaration::ValueDecl(parameter_id) = parameter.decl;
+                    arguments.push(self.build_expression(argument, &codegen.arena[parameter_id].type_kind)?.into());
+                }
+                let call_site = builder.build_call(function, &arguments, "call").map_err(codegen_error)?;
+                return call_site.try_as_basic_value().left()
+                    .ok_or_else(|| codegen_error_at(format!("`{}` does not return a value", function.get_name().to_string_lossy()), call.span));
+            }
+            ast::Expression::Field(..) => unreachable!("Field access not implemented"),
+            ast::Expression::Block(..) => unreachable!("Block value not implemented"),
+            ast::Expression::If(if_expr) => return self.build_if_value(if_expr, type_kind),
+            ast::Expression::While(..) => unreachable!("`while` used as a value"),
+            ast::Expression::For(..) => unreachable!("`for` used as a value"),
+            ast::Expression::Switch(switch) => return self.build_switch_value(switch, type_kind),
+        }
+    }
+
+    fn build_arithmetic(&self, operator: ast::BinaryOp, type_kind: &ast::TypeKind, left: BasicValueEnum<'c>, right: BasicValueEnum<'c>) -> CodeGenResult<BasicValueEnum<'c>> {
+        use ast::BinaryOp::*;
+        let builder = &self.codegen.builder;
+        let value: BasicValueEnum = match (left, right) {
+            (BasicValueEnum::IntValue(left), BasicValueEnum::IntValue(right)) => match operator {
+                Add => builder.build_int_add(left, right, "add"),
+                Sub => builder.build_int_sub(left, right, "sub"),
+                Mul => builder.build_int_mul(left, right, "mul"),
+                Div if is_type_signed(type_kind) => builder.build_int_signed_div(left, right, "div"),
+                Div => builder.build_int_unsigned_div(left, right, "div"),
+                _ => unreachable!("Comparisons are built by `build_comparison`."),
+            }.map_err(codegen_error)?.into(),
+            (BasicValueEnum::FloatValue(left), BasicValueEnum::FloatValue(right)) => match operator {
+                Add => builder.build_float_add(left, right, "add"),
+                Sub => builder.build_float_sub(left, right, "sub"),
+                Mul => builder.build_float_mul(left, right, "mul"),
+                Div => builder.build_float_div(left, right, "div"),
+                _ => unreachable!("Comparisons are built by `build_comparison`."),
+            }.map_err(codegen_error)?.into(),
+            _ => unreachable!("Mismatched operands of `{}`", operator.str()),
+        };
+        return Ok(value);
+    }
+
+    fn build_comparison(&self, operator: ast::BinaryOp, operand_type: &ast::TypeKind, left: BasicValueEnum<'c>, right: BasicValueEnum<'c>) -> CodeGenResult<BasicValueEnum<'c>> {
+        use ast::BinaryOp::*;
+        let builder = &self.codegen.builder;
+        let value = match (left, right) {
+            (BasicValueEnum::IntValue(left), BasicValueEnum::IntValue(right)) => {
+                let predicate = match (operator, is_type_signed(operand_type)) {
+                    (Eq, _) => IntPredicate::EQ,
+                    (Ne, _) => IntPredicate::NE,
+                    (Lt, true) => IntPredicate::SLT,
+                    (Le, true) => IntPredicate::SLE,
+                    (Gt, true) => IntPredicate::SGT,
+                    (Ge, true) => IntPredicate::SGE,
+                    (Lt, false) => IntPredicate::ULT,
+                    (Le, false) => IntPredicate::ULE,
+                    (Gt, false) => IntPredicate::UGT,
+                    (Ge, false) => IntPredicate::UGE,
+                    _ => unreachable!("Arithmetic is built by `build_arithmetic`."),
+                };
+                builder.build_int_compare(predicate, left, right, "cmp")
+            }
+            (BasicValueEnum::FloatValue(left), BasicValueEnum::FloatValue(right)) => {
+                let predicate = match operator {
+                    Eq => FloatPredicate::OEQ,
+                    // Unordered, so that `!=` is always the opposite of `==`, also for NaN.
+                    Ne => FloatPredicate::UNE,
+                    Lt => FloatPredicate::OLT,
+                    Le => FloatPredicate::OLE,
+                    Gt => FloatPredicate::OGT,
+                    Ge => FloatPredicate::OGE,
+                    _ => unreachable!("Arithmetic is built by `build_arithmetic`."),
+                };
+                builder.build_float_compare(predicate, left, right, "cmp")
+            }
+            _ => unreachable!("Mismatched operands of `{}`", operator.str()),
+        };
+        return Ok(value.map_err(codegen_error)?.into());
+    }
 
-                let value = float_type.const_float_from_string(decimal);
-                self.builder.build_return(Some(&value)).unwrap();
+    /// Returns the stack slot of a local value, or the global of a value in a namespace.
+    /// `span` is where the value is used, which errors point at.
+    fn get_value_pointer(&self, id: ast::DeclId, scope: ast::ScopeId, span: ast::Span) -> CodeGenResult<PointerValue<'c>> {
+        let value_decl = &self.codegen.arena[id];
+        match &self.codegen.arena[scope].kind {
+            ast::ScopeKind::Namespace(namespace) => {
+                let symbol = symbol_name(&namespace.full_name, &value_decl.identifier);
+                let value_type = get_type_primitive(self.codegen.context, &value_decl.type_kind);
+                return Ok(self.codegen.get_or_declare_global(self.module, &symbol, value_type).as_pointer_value());
+            }
+            ast::ScopeKind::Function(..) | ast::ScopeKind::Block | ast::ScopeKind::Declaration(..) => {
+                return self.locals.get(&id).copied()
+                    .ok_or_else(|| codegen_error_at(format!("`{}` belongs to an enclosing function and cannot be used inside `{}`", value_decl.identifier, self.function.get_name().to_string_lossy()), span)
+                        .with_secondary(value_decl.identifier_span, format!("`{}` is declared here", value_decl.identifier))
+                        .with_note("functions declared in blocks cannot use the locals of the enclosing function"));
             }
-            ast::Expression::Bool(_) => panic!("Cannot return a boolean literal from a floating point function"),
         }
     }
+
+    fn get_callee<'e>(&self, callee: &'e ast::Expression) -> CodeGenResult<(FunctionValue<'c>, &'e ast::FuncType)> {
+        let scoped_id = match callee {
+            ast::Expression::Identifier(scoped_id) => scoped_id,
+            _ => unreachable!("Callee is not an identifier"),
+        };
+        let (id, scope) = get_resolved_decl(self.codegen.arena, scoped_id);
+        let value_decl = &self.codegen.arena[id];
+        let func_type = match &value_decl.type_kind {
+            ast::TypeKind::FuncType(func_type) => &**func_type,
+            _ => unreachable!("Callee is not a function"),
+        };
+        let function = match &self.codegen.arena[scope].kind {
+            ast::ScopeKind::Namespace(namespace) => {
+                let symbol = decl_symbol(&namespace.full_name, value_decl);
+                self.codegen.get_or_declare_function(self.module, &symbol, value_decl)
+            }
+            ast::ScopeKind::Function(..) | ast::ScopeKind::Block | ast::ScopeKind::Declaration(..) => {
+                match self.codegen.local_functions.borrow().get(&id) {
+                    Some(function) => *function,
+                    None => return Err(codegen_error_at(format!("`{}` cannot be called, since it is not a declared function", value_decl.identifier), scoped_id.span)),
+                }
+            }
+        };
+        return Ok((function, func_type));
+    }
+}
+
+fn get_resolved_decl(arena: &ast::Arena, scoped_id: &ast::ScopedId) -> (ast::DeclId, ast::ScopeId) {
+    return match arena[scoped_id.id] {
+        ast::IdKind::Resolved{declaration, scope} => (declaration, scope),
+        ast::IdKind::Unresolved{..} => unreachable!("Unresolved identifier `{}`", scoped_id.name),
+    };
 }
 
-const fn get_linkage(public: bool) -> Linkage {
-    return match public {
-        true => Linkage::External,
-        false => Linkage::Private,
+/// Ranges with more than this many values are checked with comparisons instead of being listed as cases.
+const MAX_SWITCH_RANGE: u128 = 256;
+
+fn switch_value(body: &ast::Statement) -> &ast::Expression {
+    return match body {
+        ast::Statement::Expression(expression) => expression,
+        _ => unreachable!("Switch case is not an expression"),
     };
 }
 
+fn pattern_bits(literal: &ast::IntLiteral) -> u128 {
+    return literal.bits().expect("Pattern out of range");
+}
+
+/// Returns the value of an integer literal, which may have a prefix, separators and a suffix.
+fn int_bits(literal: &str) -> u128 {
+    return ast::NumberLiteral::split(literal).int_value().expect("Literal out of range");
+}
+
+/// Returns a number literal in the decimal notation that floating point constants are read from.
+fn decimal_str(literal: &str) -> String {
+    return ast::NumberLiteral::split(literal).decimal_str().expect("Literal out of range");
+}
+
+/// Returns the integer constant with the given two's complement bits, truncated to the width of the type.
+fn const_int_bits(int_type: IntType<'_>, bits: u128) -> IntValue<'_> {
+    return int_type.const_int_arbitrary_precision(&[bits as u64, (bits >> 64) as u64]);
+}
+
+/// Symbols of declarations in sub namespaces are prefixed by the full name of the namespace,
+/// so declarations with the same name in different namespaces do not collide when the modules are linked.
+fn symbol_name(full_name: &str, identifier: &str) -> RcStr {
+    if full_name.is_empty() {
+        return identifier.into();
+    }
+    return format!("{}.{}", full_name, identifier).into();
+}
+
+/// External functions are linked by their name, since they are defined outside of the program.
+fn decl_symbol(full_name: &str, value_decl: &ast::ValueDecl) -> RcStr {
+    if let ast::DeclKind::ExternDecl = value_decl.decl_kind {
+        return value_decl.identifier.clone();
+    }
+    return symbol_name(full_name, &value_decl.identifier);
+}
+
+/// The `main` function of the global namespace is the entry point of the program,
+/// so it has to be visible to the linker even when it is not public.
+/// External functions are only declared, and a declaration cannot be private.
+fn is_exported(full_name: &str, value_decl: &ast::ValueDecl) -> bool {
+    if full_name.is_empty() && &*value_decl.identifier == "main" {
+        return true;
+    }
+    if let ast::DeclKind::ExternDecl = value_decl.decl_kind {
+        return true;
+    }
+    return value_decl.public;
+}
+
 const fn is_type_primitive(type_kind: &ast::TypeKind) -> bool {
     return match type_kind {
         ast::TypeKind::Primitive(..) => true,
@@ -319,6 +1044,7 @@ fn try_get_type_primitive<'c>(context: &'c Context, type_kind: &ast::TypeKind) -
             F32 => context.f32_type().into(),
             F64 => context.f64_type().into(),
             F128 => context.f128_type().into(),
+            Str => get_type_str(context).into(),
         }),
         _ => return None,
     };
@@ -328,17 +1054,10 @@ fn get_type_primitive<'c>(context: &'c Context, type_kind: &ast::TypeKind) -> Ba
     return try_get_type_primitive(context, type_kind).expect("Primitive type not implemented");
 }
 
-const fn is_type_int(type_kind: &ast::TypeKind) -> bool {
-    use ast::Primitive::*;
-    return match type_kind {
-        ast::TypeKind::Primitive(primitive) => return match primitive {
-            I8 | I16 | I32 | I64 | I128 => true,
-            U8 | U16 | U32 | U64 | U128 => true,
-            U1 | Bool => true,
-            _ => false,
-        },
-        _ => false,
-    }
+/// A string is a pointer to its bytes followed by its length in bytes.
+/// LLVM passes its fields like two separate arguments, so C functions can take it as `(const char *bytes, size_t length)`.
+fn get_type_str<'c>(context: &'c Context) -> StructType<'c> {
+    return context.struct_type(&[context.ptr_type(AddressSpace::default()).into(), context.i64_type().into()], false);
 }
 
 fn try_get_type_int<'c>(context: &'c Context, type_kind: &ast::TypeKind) -> Option<IntType<'c>> {
@@ -367,11 +1086,11 @@ fn get_type_int<'c>(context: &'c Context, type_kind: &ast::TypeKind) -> IntType<
     return try_get_type_int(context, type_kind).expect("Integer type not implemented");
 }
 
-const fn is_type_float(type_kind: &ast::TypeKind) -> bool {
+const fn is_type_signed(type_kind: &ast::TypeKind) -> bool {
     use ast::Primitive::*;
     return match type_kind {
         ast::TypeKind::Primitive(primitive) => return match primitive {
-            F16 | F32 | F64 | F128 => true,
+            I8 | I16 | I32 | I64 | I128 => true,
             _ => false,
         },
         _ => false,
@@ -396,11 +1115,11 @@ fn get_type_float<'c>(context: &'c Context, type_kind: &ast::TypeKind) -> FloatT
     return try_get_type_float(context, type_kind).expect("Floating point type not implemented");
 }
 
-fn get_argument_types<'c>(context: &'c Context, arguments: &[ast::Argument]) -> Box<[BasicMetadataTypeEnum<'c>]> {
+fn get_argument_types<'c>(context: &'c Context, arena: &ast::Arena, arguments: &[ast::Argument]) -> Box<[BasicMetadataTypeEnum<'c>]> {
     let mut args = Vec::new();
     for arg in arguments {
-        let type_kind = match &arg.decl {
-            ast::Declaration::ValueDecl(value_decl) => &value_decl.type_kind,
+        let type_kind = match arg.decl {
+            ast::Declaration::ValueDecl(id) => &arena[id].type_kind,
         };
         args.push(get_type_primitive(context, &type_kind).into());
     }
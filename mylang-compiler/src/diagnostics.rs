@@ -0,0 +1,322 @@
+use std::borrow::Cow;
+use std::fmt::{Debug, Display};
+
+use crate::ast::{Location, RcStr, Span};
+
+#[derive(Copy,Clone,Debug,Eq,PartialEq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+impl Severity {
+    pub fn str(&self) -> &'static str {
+        return match self {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        };
+    }
+}
+
+/// Points at a span of the source, optionally with a message explaining it.
+#[derive(Clone,Debug)]
+pub struct Label {
+    pub span: Span,
+    pub message: Option<RcStr>,
+}
+
+/// A problem found while compiling, shared by all compiler stages.
+#[derive(Clone)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub code: &'static str,
+    pub message: RcStr,
+    /// Where the problem is. Can be missing for problems that are not tied to the source (e.g. in codegen).
+    pub primary: Option<Label>,
+    /// Other related locations.
+    pub secondary: Vec<Label>,
+    pub notes: Vec<RcStr>,
+}
+
+impl Diagnostic {
+    pub fn new<S: Into<RcStr>>(severity: Severity, code: &'static str, message: S) -> Diagnostic {
+        return Diagnostic{
+            severity,
+            code,
+            message: message.into(),
+            primary: None,
+            secondary: Vec::new(),
+            notes: Vec::new(),
+        };
+    }
+
+    pub fn error<S: Into<RcStr>>(code: &'static str, message: S, span: Span) -> Diagnostic {
+        return Diagnostic::new(Severity::Error, code, message).with_span(span);
+    }
+
+    pub fn warning<S: Into<RcStr>>(code: &'static str, message: S, span: Span) -> Diagnostic {
+        return Diagnostic::new(Severity::Warning, code, message).with_span(span);
+    }
+
+    pub fn with_span(mut self, span: Span) -> Diagnostic {
+        self.primary = Some(Label{span, message: None});
+        return self;
+    }
+
+    /// Sets the message shown next to the primary location.
+    pub fn with_label<S: Into<RcStr>>(mut self, message: S) -> Diagnostic {
+        if let Some(primary) = &mut self.primary {
+            primary.message = Some(message.into());
+        }
+        return self;
+    }
+
+    pub fn with_secondary<S: Into<RcStr>>(mut self, span: Span, message: S) -> Diagnostic {
+        self.secondary.push(Label{span, message: Some(message.into())});
+        return self;
+    }
+
+    pub fn with_note<S: Into<RcStr>>(mut self, note: S) -> Diagnostic {
+        self.notes.push(note.into());
+        return self;
+    }
+
+    pub fn is_error(&self) -> bool {
+        return self.severity == Severity::Error;
+    }
+
+    /// Renders the diagnostic with the source lines it points at, e.g.
+    /// ```text
+    /// error[E0401]: Mismatched types
+    ///  --> main.mylang:1:14
+    ///   |
+    /// 1 | let x: I32 = 5.5;
+    ///   |              ^^^ expected `I32`, found decimal literal `5.5`
+    /// ```
+    /// A span within one line is underlined, a span over several lines is marked at its start.
+    /// Labels in another file than the primary location start with `::: file:line:char`.
+    pub fn render(&self, sources: &[SourceFile]) -> String {
+        let mut string = format!("{}[{}]: {}\n", self.severity.str(), self.code, self.message);
+
+        let mut labels = Vec::new();
+        if let Some(primary) = &self.primary {
+            labels.push((primary, '^'));
+        }
+        for secondary in &self.secondary {
+            labels.push((secondary, '-'));
+        }
+
+        let source_of = |span: Span| sources.get(span.file() as usize).map_or("<unknown>", |source| &source.name);
+        // A location in an unknown file is shown as if the file was a single line.
+        let line_column = |location: Location| sources.get(location.file as usize)
+            .map_or((1, location.offset as usize + 1), |source| source.line_column(location.offset));
+
+        let gutter_width = labels.iter()
+            .map(|(label, _)| line_column(label.span.start).0.to_string().len())
+            .max()
+            .unwrap_or(0);
+        let gutter = " ".repeat(gutter_width);
+
+        if let Some(primary) = &self.primary {
+            let (line, column) = line_column(primary.span.start);
+            string.push_str(&format!("{}--> {}:{}:{}\n", gutter, source_of(primary.span), line, column));
+        }
+        let mut file = self.primary.as_ref().map(|primary| primary.span.file());
+        for (label, marker) in labels {
+            let (start_line, start_column) = line_column(label.span.start);
+            let (end_line, end_column) = line_column(label.span.end);
+            if file != Some(label.span.file()) {
+                file = Some(label.span.file());
+                string.push_str(&format!("{}::: {}:{}:{}\n", gutter, source_of(label.span), start_line, start_column));
+            }
+            let line = sources.get(label.span.file() as usize).and_then(|source| source.line(start_line)).unwrap_or_default();
+            let padding: String = line.chars()
+                .take(start_column - 1)
+                .map(|c| if c == '\t' { '\t' } else { ' ' })
+                .collect();
+            let width = if end_line == start_line && end_column > start_column { end_column - start_column } else { 1 };
+
+            string.push_str(&format!("{} |\n", gutter));
+            string.push_str(&format!("{:>width$} | {}\n", start_line, line, width = gutter_width));
+            string.push_str(&format!("{} | {}{}", gutter, padding, marker.to_string().repeat(width)));
+            if let Some(message) = &label.message {
+                string.push(' ');
+                string.push_str(message);
+            }
+            string.push('\n');
+        }
+        for note in &self.notes {
+            string.push_str(&format!("{} = note: {}\n", gutter, note));
+        }
+
+        return string;
+    }
+}
+
+impl Display for Diagnostic {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(fmt, "{}[{}]: {}", self.severity.str(), self.code, self.message)?;
+        if let Some(primary) = &self.primary {
+            write!(fmt, " at {}", primary.span.start.str())?;
+        }
+        return Ok(());
+    }
+}
+
+impl Debug for Diagnostic {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        return Display::fmt(self, fmt);
+    }
+}
+
+/// The largest file that can be compiled, since locations store their byte offset as a `u32`.
+pub const MAX_SOURCE_LEN: usize = u32::MAX as usize;
+
+/// The error for a file that is larger than `MAX_SOURCE_LEN`, which is rejected before it is tokenized.
+pub fn source_too_large(name: &str) -> Diagnostic {
+    return Diagnostic::new(Severity::Error, "E0605", format!("`{}` is too large to compile", name))
+        .with_note(format!("files can be at most {} bytes", MAX_SOURCE_LEN));
+}
+
+/// The text of a compiled file, used to show the lines that diagnostics point at.
+pub struct SourceFile {
+    pub name: RcStr,
+    /// The file as it was read, which the offsets of locations refer to.
+    bytes: Box<[u8]>,
+    /// The offset of the first byte of each line.
+    line_starts: Box<[u32]>,
+}
+
+impl SourceFile {
+    /// Panics if the file is larger than `MAX_SOURCE_LEN`, whose offsets would not fit in a location.
+    pub fn new(name: RcStr, bytes: &[u8]) -> SourceFile {
+        assert!(bytes.len() <= MAX_SOURCE_LEN, "`{}` is too large to compile", name);
+        let mut line_starts = vec![0];
+        for (offset, byte) in bytes.iter().enumerate() {
+            if *byte == b'\n' {
+                line_starts.push(offset as u32 + 1);
+            }
+        }
+        return SourceFile{name, bytes: bytes.into(), line_starts: line_starts.into()};
+    }
+
+    /// Returns the 1-indexed line and column of the byte offset.
+    /// The column counts characters, where an invalid UTF-8 sequence counts as the one replacement character it is shown as.
+    pub fn line_column(&self, offset: u32) -> (usize, usize) {
+        let line = match self.line_starts.binary_search(&offset) {
+            Ok(line) => line,
+            Err(next_line) => next_line - 1,
+        };
+        let start = self.line_starts[line] as usize;
+        let end = (offset as usize).min(self.bytes.len()).max(start);
+        let column = String::from_utf8_lossy(&self.bytes[start..end]).chars().count();
+        return (line + 1, column + 1);
+    }
+
+    /// Returns the line with the given 1-indexed number, without its line ending.
+    pub fn line(&self, number: usize) -> Option<Cow<'_, str>> {
+        if number == 0 || number > self.line_starts.len() {
+            return None;
+        }
+        let start = self.line_starts[number - 1] as usize;
+        let end = self.line_starts.get(number).map_or(self.bytes.len(), |next| *next as usize - 1);
+        let line = &self.bytes[start..end];
+        return Some(String::from_utf8_lossy(line.strip_suffix(b"\r").unwrap_or(line)));
+    }
+}
+
+/// Renders all diagnostics, followed by a summary of how many errors and warnings there were.
+/// The first source is the file that was compiled, the others are the files it imports.
+pub fn render_all(diagnostics: &[Diagnostic], sources: &[SourceFile]) -> String {
+    let mut string = String::new();
+    for diagnostic in diagnostics {
+        string.push_str(&diagnostic.render(sources));
+        string.push('\n');
+    }
+    // The summary names the main source, which is missing if the diagnostics were reported before it was read.
+    let source = match sources.first() {
+        Some(source) => source,
+        None => return string,
+    };
+
+    let errors = diagnostics.iter().filter(|diagnostic| diagnostic.is_error()).count();
+    let warnings = diagnostics.len() - errors;
+    if errors > 0 {
+        string.push_str(&format!("error: could not compile `{}` due to {} previous error{}", source.name, errors, if errors == 1 { "" } else { "s" }));
+        if warnings > 0 {
+            string.push_str(&format!("; {} warning{} emitted", warnings, if warnings == 1 { "" } else { "s" }));
+        }
+        string.push('\n');
+    }
+    else if warnings > 0 {
+        string.push_str(&format!("warning: `{}` generated {} warning{}\n", source.name, warnings, if warnings == 1 { "" } else { "s" }));
+    }
+    return string;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn location(offset: u32) -> Location {
+        return Location{file: 0, offset};
+    }
+
+    #[test]
+    fn columns_count_characters() {
+        let source = SourceFile::new("test".into(), "let é = \"ü€\";\nx".as_bytes());
+        assert_eq!(source.line_column(0), (1, 1));
+        // `é` is two bytes but one column.
+        assert_eq!(source.line_column(6), (1, 6));
+        assert_eq!(source.line_column(11), (1, 11));
+        // `€` is three bytes.
+        assert_eq!(source.line_column(14), (1, 12));
+        assert_eq!(source.line_column(18), (2, 1));
+    }
+
+    #[test]
+    fn crlf_lines() {
+        let source = SourceFile::new("test".into(), b"ab\r\ncd\r\n\r\nef");
+        assert_eq!(source.line_column(1), (1, 2));
+        assert_eq!(source.line_column(4), (2, 1));
+        assert_eq!(source.line_column(10), (4, 1));
+        // The `\r` is not part of the shown line.
+        assert_eq!(source.line(1).as_deref(), Some("ab"));
+        assert_eq!(source.line(3).as_deref(), Some(""));
+        assert_eq!(source.line(4).as_deref(), Some("ef"));
+        assert_eq!(source.line(5), None);
+    }
+
+    #[test]
+    fn invalid_bytes_count_as_one_character() {
+        // The start of a three byte character without its last byte.
+        let source = SourceFile::new("test".into(), b"a\xE2\x82b c");
+        assert_eq!(source.line_column(3), (1, 3));
+        assert_eq!(source.line_column(5), (1, 5));
+        assert_eq!(source.line(1).as_deref(), Some("a\u{FFFD}b c"));
+    }
+
+    #[test]
+    fn render_underlines_the_span() {
+        let source = SourceFile::new("main.mylang".into(), "let é: I32 = 5.5;\n".as_bytes());
+        let diagnostic = Diagnostic::error("E0401", "Mismatched types", Span::new(location(14), location(17)))
+            .with_label("expected `I32`")
+            .with_note("a note");
+        assert_eq!(diagnostic.render(&[source]), "\
+error[E0401]: Mismatched types
+ --> main.mylang:1:14
+  |
+1 | let é: I32 = 5.5;
+  |              ^^^ expected `I32`
+  = note: a note
+");
+    }
+
+    #[test]
+    fn render_all_without_sources() {
+        let diagnostic = Diagnostic::error("E0601", "Cannot find file", Span::new(location(0), location(0)));
+        let rendered = diagnostic.render(&[]);
+        // Without a main source there is no summary.
+        assert_eq!(render_all(&[diagnostic], &[]), rendered + "\n");
+    }
+}
@@ -0,0 +1,572 @@
+use std::fmt::{Debug, Display};
+use std::fs::File;
+use std::io::{BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+
+use crate::ast::{self, RcStr};
+use crate::codegenerator::{self, CodeGenError, OutputFile};
+use crate::diagnostics::{self, Diagnostic, SourceFile, MAX_SOURCE_LEN};
+use crate::loader::Loader;
+use crate::tokenizer::Tokenizer;
+use crate::typechecker::{self, TypedProgram};
+
+pub const USAGE: &str = "\
+Usage: mylangc build <input> [options]
+       mylangc run <input> [--search-path <dir>]... [-- <arguments>...]
+       mylangc help
+
+`build` compiles a .mylang file, `run` compiles it in memory and executes its
+`main` function, exiting with its return value.
+Use `-` as <input> to read from stdin.
+
+Imports are looked up next to the importing file, then in each directory given
+with `--search-path`, then in the standard library.
+
+Build options:
+    -o <file>           Write the output to <file> (`-` for stdout).
+                        Only allowed when emitting a single kind.
+    --out-dir <dir>     Write outputs into <dir> (default: current directory).
+    --emit=<kinds>      Comma separated list of outputs to emit:
+                        tokens, ast, llvm-ir, asm, obj, exe (default: llvm-ir).
+    --search-path <dir> Look up imports in <dir>. Can be given more than once.
+
+Executables are linked with the C compiler named by the `CC` environment
+variable, or `cc` if it is not set.
+";
+
+#[derive(Copy,Clone,Debug,Eq,PartialEq)]
+pub enum EmitKind {
+    Tokens,
+    Ast,
+    LlvmIr,
+    Assembly,
+    Object,
+    Executable,
+}
+
+impl EmitKind {
+    fn from_str(string: &str) -> Option<EmitKind> {
+        return match string {
+            "tokens"  => Some(EmitKind::Tokens),
+            "ast"     => Some(EmitKind::Ast),
+            "llvm-ir" => Some(EmitKind::LlvmIr),
+            "asm"     => Some(EmitKind::Assembly),
+            "obj"     => Some(EmitKind::Object),
+            "exe"     => Some(EmitKind::Executable),
+            _ => None,
+        };
+    }
+
+    fn extension(&self) -> &'static str {
+        return match self {
+            EmitKind::Tokens => "tokens",
+            EmitKind::Ast    => "ast",
+            EmitKind::LlvmIr => "ll",
+            EmitKind::Assembly => "s",
+            EmitKind::Object => "o",
+            EmitKind::Executable => std::env::consts::EXE_EXTENSION,
+        };
+    }
+}
+
+#[derive(Debug)]
+pub enum Input {
+    Stdin,
+    File(PathBuf),
+}
+
+#[derive(Debug)]
+pub enum Command {
+    Help,
+    Build(BuildOptions),
+    Run(RunOptions),
+}
+
+#[derive(Debug)]
+pub struct BuildOptions {
+    pub input: Input,
+    pub output: Option<PathBuf>,
+    pub out_dir: Option<PathBuf>,
+    pub emit: Box<[EmitKind]>,
+    pub search_path: Box<[PathBuf]>,
+}
+
+#[derive(Debug)]
+pub struct RunOptions {
+    pub input: Input,
+    pub search_path: Box<[PathBuf]>,
+    pub arguments: Box<[String]>,
+}
+
+pub enum DriverError {
+    Usage(RcStr),
+    Io{path: RcStr, error: std::io::Error},
+    /// The program could not be compiled. Contains the rendered diagnostics.
+    Compile(RcStr),
+    Link(RcStr),
+}
+
+impl DriverError {
+    /// The exit code the process should terminate with.
+    pub fn exit_code(&self) -> u8 {
+        return match self {
+            DriverError::Usage(..) => 2,
+            DriverError::Io{..} => 1,
+            DriverError::Compile(..) => 1,
+            DriverError::Link(..) => 1,
+        };
+    }
+
+    fn io(path: &Path, error: std::io::Error) -> DriverError {
+        return DriverError::Io{path: path.display().to_string().into(), error};
+    }
+
+    fn codegen(sources: &[SourceFile], error: CodeGenError) -> DriverError {
+        return DriverError::Compile(diagnostics::render_all(&[error], sources).into());
+    }
+}
+
+impl Display for DriverError {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        return match self {
+            DriverError::Usage(message) => write!(fmt, "error: {}\n\n{}", message, USAGE),
+            DriverError::Io{path, error} => write!(fmt, "error: `{}`: {}\n", path, error),
+            DriverError::Compile(rendered) => fmt.write_str(rendered),
+            DriverError::Link(message) => write!(fmt, "error: Linking failed: {}\n", message),
+        };
+    }
+}
+
+impl Debug for DriverError {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        return Display::fmt(self, fmt);
+    }
+}
+
+pub type DriverResult<T> = Result<T, DriverError>;
+
+/// Parses the command line arguments (without the program name).
+pub fn parse_args<I: Iterator<Item = String>>(mut args: I) -> DriverResult<Command> {
+    let command = match args.next() {
+        Some(command) => command,
+        None => return Err(DriverError::Usage("Missing command".into())),
+    };
+    match command.as_str() {
+        "help" | "-h" | "--help" => return Ok(Command::Help),
+        "build" => return parse_build_args(args),
+        "run" => return parse_run_args(args),
+        _ => return Err(DriverError::Usage(format!("Unknown command `{}`", command).into())),
+    };
+}
+
+fn parse_build_args<I: Iterator<Item = String>>(mut args: I) -> DriverResult<Command> {
+
+    let mut input = None;
+    let mut output = None;
+    let mut out_dir = None;
+    let mut emit = Vec::new();
+    let mut search_path = Vec::new();
+
+    while let Some(arg) = args.next() {
+        if arg == "-o" {
+            match args.next() {
+                Some(path) => output = Some(PathBuf::from(path)),
+                None => return Err(DriverError::Usage("Expected a file after `-o`".into())),
+            }
+        }
+        else if arg == "--out-dir" {
+            match args.next() {
+                Some(path) => out_dir = Some(PathBuf::from(path)),
+                None => return Err(DriverError::Usage("Expected a directory after `--out-dir`".into())),
+            }
+        }
+        else if arg == "--search-path" {
+            search_path.push(expect_search_path(&mut args)?);
+        }
+        else if let Some(kinds) = arg.strip_prefix("--emit=") {
+            for kind in kinds.split(',') {
+                match EmitKind::from_str(kind) {
+                    Some(kind) => if !emit.contains(&kind) { emit.push(kind) },
+                    None => return Err(DriverError::Usage(format!("Unknown emit kind `{}`", kind).into())),
+                }
+            }
+        }
+        else {
+            parse_input_arg(arg, &mut input)?;
+        }
+    }
+
+    let input = expect_input(input)?;
+    if emit.is_empty() {
+        emit.push(EmitKind::LlvmIr);
+    }
+    if output.is_some() && emit.len() > 1 {
+        return Err(DriverError::Usage("`-o` cannot be used when emitting more than one kind".into()));
+    }
+
+    return Ok(Command::Build(BuildOptions{input, output, out_dir, emit: emit.into(), search_path: search_path.into()}));
+}
+
+fn parse_run_args<I: Iterator<Item = String>>(mut args: I) -> DriverResult<Command> {
+    let mut input = None;
+    let mut search_path = Vec::new();
+    let mut arguments = Vec::new();
+
+    while let Some(arg) = args.next() {
+        if arg == "--" {
+            arguments.extend(args.by_ref());
+        }
+        else if arg == "--search-path" {
+            search_path.push(expect_search_path(&mut args)?);
+        }
+        else {
+            parse_input_arg(arg, &mut input)?;
+        }
+    }
+
+    let input = expect_input(input)?;
+    return Ok(Command::Run(RunOptions{input, search_path: search_path.into(), arguments: arguments.into()}));
+}
+
+fn expect_search_path<I: Iterator<Item = String>>(args: &mut I) -> DriverResult<PathBuf> {
+    return match args.next() {
+        Some(path) => Ok(PathBuf::from(path)),
+        None => Err(DriverError::Usage("Expected a directory after `--search-path`".into())),
+    };
+}
+
+fn parse_input_arg(arg: String, input: &mut Option<Input>) -> DriverResult<()> {
+    if arg != "-" && arg.starts_with('-') {
+        return Err(DriverError::Usage(format!("Unknown option `{}`", arg).into()));
+    }
+    if input.is_some() {
+        return Err(DriverError::Usage(format!("Unexpected argument `{}`, input was already given", arg).into()));
+    }
+    *input = Some(if arg == "-" { Input::Stdin } else { Input::File(PathBuf::from(arg)) });
+    return Ok(());
+}
+
+fn expect_input(input: Option<Input>) -> DriverResult<Input> {
+    return match input {
+        Some(input) => Ok(input),
+        None => Err(DriverError::Usage("Missing input file".into())),
+    };
+}
+
+/// Runs the command and returns the exit code of the process.
+pub fn run(command: Command) -> DriverResult<u8> {
+    return match command {
+        Command::Help => {
+            print!("{}", USAGE);
+            Ok(0)
+        }
+        Command::Build(options) => build(&options).map(|()| 0),
+        Command::Run(options) => run_program(&options),
+    };
+}
+
+/// Fails if there are any errors among the diagnostics, otherwise prints the warnings.
+fn report(sources: &[SourceFile], diagnostics: &[Diagnostic]) -> DriverResult<()> {
+    if diagnostics.is_empty() {
+        return Ok(());
+    }
+    let rendered = diagnostics::render_all(diagnostics, sources);
+    if diagnostics.iter().any(Diagnostic::is_error) {
+        return Err(DriverError::Compile(rendered.into()));
+    }
+    eprint!("{}", rendered);
+    return Ok(());
+}
+
+/// Parses the input and every file it imports.
+/// Returns the program and the loaded files, which are needed to show later diagnostics.
+fn parse_program(name: RcStr, input: &Input, search_path: &[PathBuf], bytes: &[u8], source: SourceFile) -> DriverResult<(ast::Program, Box<[SourceFile]>)> {
+    let mut loader = Loader::new(search_path);
+    let path = match input {
+        Input::Stdin => None,
+        Input::File(path) => Some(path.as_path()),
+    };
+    loader.load(source, bytes, path);
+    let (program, sources, diagnostics) = loader.finish(name);
+    report(&sources, &diagnostics)?;
+    return Ok((program, sources));
+}
+
+fn check_program(sources: &[SourceFile], program: ast::Program) -> DriverResult<TypedProgram> {
+    return typechecker::check(program).map_err(|diagnostics| DriverError::Compile(diagnostics::render_all(&diagnostics, sources).into()));
+}
+
+fn run_program(options: &RunOptions) -> DriverResult<u8> {
+    let (bytes, source) = read_input(&options.input)?;
+    let name = program_name(&options.input);
+    let (program, sources) = parse_program(name.clone(), &options.input, &options.search_path, &bytes, source)?;
+    let program = check_program(&sources, program)?;
+
+    let mut arguments: Vec<&str> = vec![&name];
+    arguments.extend(options.arguments.iter().map(String::as_str));
+    let exit_code = codegenerator::run(&program, &arguments).map_err(|error| DriverError::codegen(&sources, error))?;
+
+    return Ok(exit_code as u8);
+}
+
+fn build(options: &BuildOptions) -> DriverResult<()> {
+    let (bytes, source) = read_input(&options.input)?;
+    let name = program_name(&options.input);
+
+    if options.emit.contains(&EmitKind::Tokens) {
+        let mut tokenizer = Tokenizer::new(bytes.as_slice(), 0);
+        write_output(options, &name, EmitKind::Tokens, &tokenizer.str())?;
+    }
+
+    let (program, sources) = parse_program(name.clone(), &options.input, &options.search_path, &bytes, source)?;
+    if options.emit.contains(&EmitKind::Ast) {
+        write_output(options, &name, EmitKind::Ast, &format!("{:#?}", program))?;
+    }
+
+    let program = check_program(&sources, program)?;
+
+    if options.emit.contains(&EmitKind::LlvmIr) {
+        let llvm_ir = codegenerator::generate(&program).map_err(|error| DriverError::codegen(&sources, error))?;
+        write_output(options, &name, EmitKind::LlvmIr, &llvm_ir)?;
+    }
+    if options.emit.contains(&EmitKind::Assembly) {
+        write_output_file(options, &name, EmitKind::Assembly, &sources, &program, OutputFile::Assembly)?;
+    }
+    if options.emit.contains(&EmitKind::Object) {
+        write_output_file(options, &name, EmitKind::Object, &sources, &program, OutputFile::Object)?;
+    }
+    if options.emit.contains(&EmitKind::Executable) {
+        let object_path = std::env::temp_dir().join(format!("{}-{}.o", name, process::id()));
+        codegenerator::write_to_file(&program, OutputFile::Object, &object_path).map_err(|error| DriverError::codegen(&sources, error))?;
+        let result = link_executable(&object_path, &output_path(options, &name, EmitKind::Executable));
+        let _ = std::fs::remove_file(&object_path);
+        result?;
+    }
+
+    return Ok(());
+}
+
+/// Writes a file emitted by the code generator, which cannot be written to stdout.
+fn write_output_file(options: &BuildOptions, name: &str, kind: EmitKind, sources: &[SourceFile], program: &TypedProgram, output_file: OutputFile) -> DriverResult<()> {
+    let path = output_path(options, name, kind);
+    if path.as_os_str() == "-" {
+        return Err(DriverError::Usage(format!("Cannot write `{}` output to stdout", kind.extension()).into()));
+    }
+    create_out_dir(options)?;
+    return codegenerator::write_to_file(program, output_file, &path).map_err(|error| DriverError::codegen(sources, error));
+}
+
+/// Links an object file into an executable using the system C compiler.
+fn link_executable(object_path: &Path, output_path: &Path) -> DriverResult<()> {
+    if output_path.as_os_str() == "-" {
+        return Err(DriverError::Usage("Cannot write an executable to stdout".into()));
+    }
+    if let Some(parent) = output_path.parent() {
+        if !parent.as_os_str().is_empty() {
+            std::fs::create_dir_all(parent).map_err(|error| DriverError::io(parent, error))?;
+        }
+    }
+
+    let linker = std::env::var("CC").unwrap_or_else(|_| "cc".into());
+    let output = process::Command::new(&linker)
+        .arg(object_path)
+        .arg("-o")
+        .arg(output_path)
+        .output()
+        .map_err(|error| DriverError::Link(format!("could not run `{}`: {}", linker, error).into()))?;
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(DriverError::Link(format!("`{}` exited with {}\n{}", linker, output.status, stderr.trim_end()).into()));
+    }
+    return Ok(());
+}
+
+/// Reads the input as bytes for the tokenizer, and as text for showing diagnostics.
+fn read_input(input: &Input) -> DriverResult<(Vec<u8>, SourceFile)> {
+    let mut bytes = Vec::new();
+    let name: RcStr = match input {
+        Input::Stdin => {
+            std::io::stdin().read_to_end(&mut bytes).map_err(|error| DriverError::io(Path::new("<stdin>"), error))?;
+            "<stdin>".into()
+        }
+        Input::File(path) => {
+            File::open(path).and_then(|mut file| file.read_to_end(&mut bytes)).map_err(|error| DriverError::io(path, error))?;
+            path.display().to_string().into()
+        }
+    };
+    if bytes.len() > MAX_SOURCE_LEN {
+        return Err(DriverError::Compile(diagnostics::source_too_large(&name).render(&[]).into()));
+    }
+    let source = SourceFile::new(name, &bytes);
+    return Ok((bytes, source));
+}
+
+/// The name of the program is the file stem of the input, or `stdin`.
+fn program_name(input: &Input) -> RcStr {
+    return match input {
+        Input::Stdin => "stdin".into(),
+        Input::File(path) => match path.file_stem() {
+            Some(stem) => stem.to_string_lossy().as_ref().into(),
+            None => "main".into(),
+        },
+    };
+}
+
+fn output_path(options: &BuildOptions, name: &str, kind: EmitKind) -> PathBuf {
+    if let Some(output) = &options.output {
+        return output.clone();
+    }
+    let file_name = match kind.extension() {
+        "" => name.to_owned(),
+        extension => format!("{}.{}", name, extension),
+    };
+    return match &options.out_dir {
+        Some(out_dir) => out_dir.join(file_name),
+        None => PathBuf::from(file_name),
+    };
+}
+
+fn write_output(options: &BuildOptions, name: &str, kind: EmitKind, content: &str) -> DriverResult<()> {
+    let path = output_path(options, name, kind);
+    if path.as_os_str() == "-" {
+        return std::io::stdout().write_all(content.as_bytes()).map_err(|error| DriverError::io(Path::new("<stdout>"), error));
+    }
+    create_out_dir(options)?;
+    return File::create(&path)
+        .and_then(|file| {
+            let mut writer = BufWriter::new(file);
+            writer.write_all(content.as_bytes())?;
+            return writer.flush();
+        })
+        .map_err(|error| DriverError::io(&path, error));
+}
+
+fn create_out_dir(options: &BuildOptions) -> DriverResult<()> {
+    if let Some(out_dir) = &options.out_dir {
+        std::fs::create_dir_all(out_dir).map_err(|error| DriverError::io(out_dir, error))?;
+    }
+    return Ok(());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_dir::TestDir;
+
+    /// Returns 61: the sum of 1 to 10, plus the 6 values of a range that ends at the largest `U8`.
+    const PROGRAM: &str = "
+        def main() -> I32 {
+            var total = 0;
+            for (i in 1..10) {
+                total = total + i;
+            }
+            for (i: U8 in 250..255) {
+                total = total + 1;
+            }
+            return total;
+        }
+    ";
+
+    fn parse(args: &[&str]) -> DriverResult<Command> {
+        return parse_args(args.iter().map(|arg| arg.to_string()));
+    }
+
+    #[test]
+    fn emit_kinds() {
+        let emit = |args: &[&str]| match parse(args) {
+            Ok(Command::Build(options)) => options.emit,
+            other => panic!("expected a build command, got {:?}", other),
+        };
+        use EmitKind::*;
+        assert_eq!(&*emit(&["build", "main.mylang"]), [LlvmIr]);
+        assert_eq!(&*emit(&["build", "main.mylang", "--emit=tokens,ast,llvm-ir,asm,obj,exe,ast"]), [Tokens, Ast, LlvmIr, Assembly, Object, Executable]);
+        assert_eq!(&*emit(&["build", "--emit=obj", "-o", "out.o", "-"]), [Object]);
+    }
+
+    #[test]
+    fn usage_errors() {
+        let usage_errors: &[&[&str]] = &[
+            &[],
+            &["compile", "main.mylang"],
+            &["build"],
+            &["build", "main.mylang", "--emit=wasm"],
+            &["build", "main.mylang", "--emit=asm,obj", "-o", "out"],
+            &["build", "main.mylang", "-o"],
+            &["build", "main.mylang", "other.mylang"],
+            &["run", "main.mylang", "--verbose"],
+        ];
+        for args in usage_errors {
+            match parse(args) {
+                Err(error @ DriverError::Usage(..)) => assert_eq!(error.exit_code(), 2),
+                other => panic!("{:?} should be a usage error, got {:?}", args, other),
+            };
+        }
+        match parse(&["run", "main.mylang", "--", "--verbose", "x"]) {
+            Ok(Command::Run(options)) => assert_eq!(&*options.arguments, ["--verbose", "x"]),
+            other => panic!("expected a run command, got {:?}", other),
+        };
+    }
+
+    #[test]
+    fn compile_errors_exit_with_1() {
+        let directory = TestDir::new("driver-errors");
+        let input = directory.write("main.mylang", "def main() -> I32 { return true; }");
+        let options = BuildOptions{
+            input: Input::File(input),
+            output: None,
+            out_dir: Some(directory.0.clone()),
+            emit: Box::new([EmitKind::Tokens, EmitKind::LlvmIr]),
+            search_path: Box::new([]),
+        };
+        match run(Command::Build(options)) {
+            Err(error @ DriverError::Compile(..)) => assert_eq!(error.exit_code(), 1),
+            other => panic!("expected a compile error, got {:?}", other),
+        };
+        // The tokens are written before the program is checked.
+        assert!(directory.0.join("main.tokens").is_file());
+        assert!(!directory.0.join("main.ll").exists());
+
+        let missing = directory.0.join("missing.mylang");
+        let error = run(Command::Build(BuildOptions{input: Input::File(missing), output: None, out_dir: None, emit: Box::new([EmitKind::Tokens]), search_path: Box::new([])})).unwrap_err();
+        assert!(matches!(error, DriverError::Io{..}));
+        assert_eq!(error.exit_code(), 1);
+    }
+
+    #[test]
+    fn build_every_emit_kind() {
+        let directory = TestDir::new("driver-build");
+        let input = directory.write("main.mylang", PROGRAM);
+        let emit = [EmitKind::Tokens, EmitKind::Ast, EmitKind::LlvmIr, EmitKind::Assembly, EmitKind::Object, EmitKind::Executable];
+        let options = BuildOptions{input: Input::File(input), output: None, out_dir: Some(directory.0.join("out")), emit: emit.into(), search_path: Box::new([])};
+        build(&options).unwrap();
+
+        for kind in emit {
+            let path = output_path(&options, "main", kind);
+            let metadata = std::fs::metadata(&path).unwrap_or_else(|error| panic!("`{}` was not written: {}", path.display(), error));
+            assert!(metadata.len() > 0, "`{}` is empty", path.display());
+        }
+        let llvm_ir = std::fs::read_to_string(output_path(&options, "main", EmitKind::LlvmIr)).unwrap();
+        assert!(llvm_ir.contains("define i32 @main()"), "{}", llvm_ir);
+
+        let status = process::Command::new(output_path(&options, "main", EmitKind::Executable)).status().unwrap();
+        assert_eq!(status.code(), Some(61));
+    }
+
+    #[test]
+    fn run_exits_with_the_result_of_main() {
+        let directory = TestDir::new("driver-run");
+        let input = directory.write("main.mylang", PROGRAM);
+        let options = RunOptions{input: Input::File(input), search_path: Box::new([]), arguments: Box::new([])};
+        assert_eq!(run(Command::Run(options)).unwrap(), 61);
+    }
+
+    #[test]
+    fn global_labeled_blocks_are_computed_before_main() {
+        let directory = TestDir::new("driver-global-block");
+        let input = directory.write("main.mylang", "let total: I32 { var sum = 0; for (i in 1..4) { sum = sum + i; } yield total = sum; }\ndef main() -> I32 { return total; }\n");
+        let options = RunOptions{input: Input::File(input), search_path: Box::new([]), arguments: Box::new([])};
+        assert_eq!(run(Command::Run(options)).unwrap(), 10);
+    }
+}
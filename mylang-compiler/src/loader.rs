@@ -0,0 +1,306 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::ast::{FileId, Import, ImportKind, Program, RcStr};
+use crate::diagnostics::{self, Diagnostic, SourceFile, MAX_SOURCE_LEN};
+use crate::parser::Parser;
+use crate::tokenizer::Tokenizer;
+
+/// The extension of source files.
+const EXTENSION: &str = "mylang";
+/// The file that is imported when a path names a directory, e.g. `import Std;`.
+const PACKAGE_FILE: &str = "prelude";
+
+/// The directory of the standard library, which is always on the search path.
+pub fn std_dir() -> PathBuf {
+    return Path::new(env!("CARGO_MANIFEST_DIR")).join("std");
+}
+
+/// Loads the compiled file and every file it imports, and parses them into one program.
+///
+/// An import path is looked up in the directory of the importing file, then in each directory of the search path.
+/// Each directory of the search path is also a package named after the directory, so with `mylang-compiler/std`
+/// on the search path, `import Std.Mem;` is looked up in that directory as `Mem`.
+///
+/// In a directory, the longest start of the path that names a file is imported, and the rest of the path names
+/// namespaces in that file. `A.B` names `a/b.mylang`, `a/b/b.mylang` or `a/b/prelude.mylang`, ignoring case,
+/// and a package on its own names its `prelude.mylang`.
+///
+/// Every file is parsed once. Imports that lead back to a file that is still being loaded are reported as cycles.
+/// A `use` path that names no file must name a namespace of a loaded file.
+pub struct Loader {
+    search_path: Box<[PathBuf]>,
+    parser: Parser,
+    sources: Vec<SourceFile>,
+    /// The canonical path of each loaded file, or `None` for stdin.
+    paths: Vec<Option<PathBuf>>,
+    /// The `use` imports that name no file, which are checked once every namespace is known.
+    namespace_uses: Vec<Import>,
+}
+
+impl Loader {
+    /// The standard library is added to the end of the search path.
+    pub fn new(search_path: &[PathBuf]) -> Loader {
+        let mut directories = search_path.to_vec();
+        directories.push(std_dir());
+        return Loader {
+            search_path: directories.into(),
+            parser: Parser::new(),
+            sources: Vec::new(),
+            paths: Vec::new(),
+            namespace_uses: Vec::new(),
+        };
+    }
+
+    /// Parses the compiled file and then the files it imports.
+    /// `path` is the path of the compiled file, or `None` when it is read from stdin.
+    pub fn load(&mut self, source: SourceFile, bytes: &[u8], path: Option<&Path>) {
+        let path = path.map(|path| fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf()));
+        self.load_file(source, bytes, path, &mut Vec::new());
+    }
+
+    /// Resolves the identifiers of all loaded files, and returns the program,
+    /// the loaded files indexed by their ids, and every problem that was found.
+    pub fn finish(mut self, name: RcStr) -> (Program, Box<[SourceFile]>, Box<[Diagnostic]>) {
+        for import in std::mem::take(&mut self.namespace_uses) {
+            if !self.parser.is_namespace(&import.path) {
+                let diagnostic = self.not_found(&import);
+                self.parser.push_diagnostic(diagnostic);
+            }
+        }
+        let (arena, diagnostics) = self.parser.finish();
+        return (Program{name, arena}, self.sources.into(), diagnostics);
+    }
+
+    /// `chain` holds the files whose imports are being loaded, starting with the compiled file.
+    fn load_file(&mut self, source: SourceFile, bytes: &[u8], path: Option<PathBuf>, chain: &mut Vec<FileId>) {
+        let file = self.sources.len() as FileId;
+        let directory = match path.as_ref().and_then(|path| path.parent()) {
+            Some(directory) => directory.to_path_buf(),
+            None => PathBuf::from("."),
+        };
+        self.sources.push(source);
+        self.paths.push(path);
+
+        let imports = self.parser.parse(Tokenizer::new(bytes, file));
+        chain.push(file);
+        for import in imports.iter() {
+            self.load_import(import, &directory, chain);
+        }
+        chain.pop();
+    }
+
+    fn load_import(&mut self, import: &Import, directory: &Path, chain: &mut Vec<FileId>) {
+        let path = match self.find(import, directory) {
+            Some(path) => path,
+            // The path can name a namespace of a file that is loaded later.
+            None if import.kind == ImportKind::Use => {
+                self.namespace_uses.push(import.clone());
+                return;
+            }
+            None => {
+                let diagnostic = self.not_found(import);
+                self.parser.push_diagnostic(diagnostic);
+                return;
+            }
+        };
+
+        if let Some(file) = self.paths.iter().position(|loaded| loaded.as_ref() == Some(&path)) {
+            if let Some(start) = chain.iter().position(|&other| other as usize == file) {
+                let mut cycle: Vec<&str> = chain[start..].iter().map(|&other| &*self.sources[other as usize].name).collect();
+                cycle.push(&self.sources[file].name);
+                let note = format!("the imports form the cycle {}", cycle.join(" -> "));
+                self.parser.push_diagnostic(Diagnostic::error("E0602", format!("Import of `{}` forms a cycle", import.path_str()), import.span)
+                    .with_note(note));
+            }
+            return;
+        }
+
+        let bytes = match fs::read(&path) {
+            Ok(bytes) => bytes,
+            Err(error) => {
+                self.parser.push_diagnostic(Diagnostic::error("E0603", format!("Could not read `{}`: {}", path.display(), error), import.span));
+                return;
+            }
+        };
+        if bytes.len() > MAX_SOURCE_LEN {
+            self.parser.push_diagnostic(diagnostics::source_too_large(&path.display().to_string()).with_span(import.span));
+            return;
+        }
+        let source = SourceFile::new(path.display().to_string().into(), &bytes);
+        self.load_file(source, &bytes, Some(path), chain);
+    }
+
+    /// Returns the canonical path of the file that the import names.
+    fn find(&self, import: &Import, directory: &Path) -> Option<PathBuf> {
+        if let Some(path) = find_in(directory, &import.path, false) {
+            return Some(path);
+        }
+        for search_directory in self.search_path.iter() {
+            let is_package = search_directory.file_name()
+                .is_some_and(|name| name.to_string_lossy().eq_ignore_ascii_case(&import.path[0]));
+            if is_package {
+                if let Some(path) = find_in(search_directory, &import.path[1..], true) {
+                    return Some(path);
+                }
+            }
+            if let Some(path) = find_in(search_directory, &import.path, false) {
+                return Some(path);
+            }
+        }
+        return None;
+    }
+
+    fn not_found(&self, import: &Import) -> Diagnostic {
+        return Diagnostic::error("E0601", format!("Cannot find `{}` to import", import.path_str()), import.span)
+            .with_note(format!("looked in the directory of the file and in {}", self.search_path_str()));
+    }
+
+    fn search_path_str(&self) -> String {
+        let directories: Vec<String> = self.search_path.iter().map(|directory| format!("`{}`", directory.display())).collect();
+        return directories.join(", ");
+    }
+}
+
+/// Returns the file named by the longest start of the path in the directory.
+/// In a package, the empty path names the file of the package itself.
+fn find_in(directory: &Path, path: &[RcStr], is_package: bool) -> Option<PathBuf> {
+    let shortest = if is_package { 0 } else { 1 };
+    for length in (shortest..=path.len()).rev() {
+        if let Some(file) = find_named(directory, &path[..length]) {
+            return fs::canonicalize(file).ok();
+        }
+    }
+    return None;
+}
+
+fn find_named(directory: &Path, path: &[RcStr]) -> Option<PathBuf> {
+    let package_file = format!("{}.{}", PACKAGE_FILE, EXTENSION);
+    let (last, parents) = match path.split_last() {
+        Some(split) => split,
+        None => return find_file(directory, &package_file),
+    };
+    let mut current = directory.to_path_buf();
+    for name in parents {
+        current = find_entry(&current, name)?;
+    }
+    let file_name = format!("{}.{}", last, EXTENSION);
+    if let Some(file) = find_file(&current, &file_name) {
+        return Some(file);
+    }
+    let subdirectory = find_entry(&current, last)?;
+    return find_file(&subdirectory, &file_name).or_else(|| find_file(&subdirectory, &package_file));
+}
+
+fn find_file(directory: &Path, name: &str) -> Option<PathBuf> {
+    return find_entry(directory, name).filter(|path| path.is_file());
+}
+
+/// Returns the entry of the directory with the given name, ignoring case.
+fn find_entry(directory: &Path, name: &str) -> Option<PathBuf> {
+    let exact = directory.join(name);
+    if exact.exists() {
+        return Some(exact);
+    }
+    return fs::read_dir(directory).ok()?
+        .filter_map(Result::ok)
+        .map(|entry| entry.path())
+        .find(|path| path.file_name().is_some_and(|file_name| file_name.to_string_lossy().eq_ignore_ascii_case(name)));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_dir::TestDir;
+
+    /// Loads the file, and returns the names of the loaded files relative to the test directory and the diagnostics.
+    fn load(directory: &TestDir, path: &Path, search_path: &[PathBuf]) -> (Vec<String>, Box<[Diagnostic]>) {
+        let bytes = fs::read(path).unwrap();
+        let mut loader = Loader::new(search_path);
+        loader.load(SourceFile::new(path.display().to_string().into(), &bytes), &bytes, Some(path));
+        let (_, sources, diagnostics) = loader.finish("test".into());
+        let names = sources.iter()
+            .map(|source| Path::new(&*source.name).strip_prefix(&directory.0).unwrap().display().to_string())
+            .collect();
+        return (names, diagnostics);
+    }
+
+    fn codes(diagnostics: &[Diagnostic]) -> Vec<&'static str> {
+        return diagnostics.iter().map(|diagnostic| diagnostic.code).collect();
+    }
+
+    #[test]
+    fn search_path_order() {
+        let directory = TestDir::new("loader-order");
+        let main = directory.write("main/main.mylang", "import Local; import Shared; import First;");
+        directory.write("main/local.mylang", "");
+        directory.write("one/local.mylang", "");
+        directory.write("one/shared.mylang", "");
+        directory.write("two/shared.mylang", "");
+        directory.write("two/first.mylang", "");
+        let search_path = [directory.0.join("one"), directory.0.join("two")];
+        let (names, diagnostics) = load(&directory, &main, &search_path);
+        assert!(diagnostics.is_empty(), "{:?}", diagnostics);
+        // The directory of the importing file comes first, then the search path in order.
+        assert_eq!(names, ["main/main.mylang", "main/local.mylang", "one/shared.mylang", "two/first.mylang"]);
+    }
+
+    #[test]
+    fn packages_and_namespaces_in_paths() {
+        let directory = TestDir::new("loader-packages");
+        let main = directory.write("main.mylang", "import Lib; import Lib.Shapes.Circle; import Lib.Sub; import Missing;");
+        directory.write("lib/prelude.mylang", "");
+        directory.write("lib/Shapes.mylang", "namespace Circle {}");
+        directory.write("lib/sub/sub.mylang", "");
+        let (names, diagnostics) = load(&directory, &main, &[directory.0.join("lib")]);
+        assert_eq!(names, ["main.mylang", "lib/prelude.mylang", "lib/Shapes.mylang", "lib/sub/sub.mylang"]);
+        assert_eq!(codes(&diagnostics), ["E0601"]);
+    }
+
+    #[test]
+    fn files_are_loaded_once() {
+        let directory = TestDir::new("loader-once");
+        let main = directory.write("main.mylang", "import a; import b;");
+        directory.write("a.mylang", "import b;");
+        directory.write("b.mylang", "");
+        let (names, diagnostics) = load(&directory, &main, &[]);
+        assert!(diagnostics.is_empty(), "{:?}", diagnostics);
+        assert_eq!(names, ["main.mylang", "a.mylang", "b.mylang"]);
+    }
+
+    #[test]
+    fn import_cycles() {
+        let directory = TestDir::new("loader-cycle");
+        let main = directory.write("main.mylang", "import a;");
+        directory.write("a.mylang", "import b;");
+        directory.write("b.mylang", "import a; import main;");
+        let (names, diagnostics) = load(&directory, &main, &[]);
+        assert_eq!(names, ["main.mylang", "a.mylang", "b.mylang"]);
+        assert_eq!(codes(&diagnostics), ["E0602", "E0602"]);
+        let a = directory.0.join("a.mylang").display().to_string();
+        let b = directory.0.join("b.mylang").display().to_string();
+        assert_eq!(diagnostics[0].notes[0].to_string(), format!("the imports form the cycle {} -> {} -> {}", a, b, a));
+    }
+
+    #[test]
+    fn std_is_on_the_search_path() {
+        let directory = TestDir::new("loader-std");
+        let main = directory.write("main.mylang", "import Std;");
+        let bytes = fs::read(&main).unwrap();
+        let mut loader = Loader::new(&[]);
+        loader.load(SourceFile::new("main.mylang".into(), &bytes), &bytes, Some(&main));
+        let (_, sources, _) = loader.finish("test".into());
+        let prelude = fs::canonicalize(std_dir().join("prelude.mylang")).unwrap();
+        assert_eq!(&*sources[1].name, prelude.display().to_string());
+    }
+
+    #[test]
+    fn uses_can_name_namespaces() {
+        let directory = TestDir::new("loader-use");
+        let main = directory.write("main.mylang", "use Math; use Missing; namespace Math {}");
+        let (names, diagnostics) = load(&directory, &main, &[]);
+        assert_eq!(names, ["main.mylang"]);
+        assert_eq!(codes(&diagnostics), ["E0601"]);
+        assert!(diagnostics[0].message.contains("Missing"));
+    }
+}
@@ -1,17 +1,23 @@
-use std::{io::Read, fmt::Debug};
 use std::collections::VecDeque;
 use crate::ast::{*, self};
+use crate::diagnostics::Diagnostic;
+use crate::resolver::{self, Resolver, UnresolvedName};
 use crate::tokenizer::{Tokenizer, Token, TokenKind};
 
-#[allow(dead_code)]
-pub struct ParseError {
-    message: RcStr,
-    location: Location,
+pub type ParseError = Diagnostic;
+
+/// Constructors for the errors reported by the parser.
+trait ParseErrors {
+    fn unexpected<T>(token: &Token, expected: &[&'static str]) -> ParseResult<T>;
+    fn not_implemented<T>(token: &Token) -> ParseResult<T>;
+    fn unknown_operator<T>(token: &Token) -> ParseResult<T>;
+    fn chained_operator<T>(token: &Token, previous: BinaryOp) -> ParseResult<T>;
+    fn unknown_label<T>(label: &str, span: Span) -> ParseResult<T>;
 }
 
-impl ParseError {
+impl ParseErrors for ParseError {
     fn unexpected<T>(token: &Token, expected: &[&'static str]) -> ParseResult<T> {
-        let mut message = format!("Unexpected token `{}` at {}. Expected ", token.token_kind.str(), token.location.str());
+        let mut message = String::from("expected ");
         let mut first = true;
         for string in expected {
             if first {
@@ -21,30 +27,29 @@ impl ParseError {
                 message.push_str(&format!(", `{}`", string));
             }
         }
-        return Err(ParseError{
-            message: message.into(),
-            location: token.location,
-        });
+        return Err(Diagnostic::error("E0201", format!("Unexpected token `{}`", token.token_kind.str()), token.span)
+            .with_label(message));
     }
 
     fn not_implemented<T>(token: &Token) -> ParseResult<T> {
-        return Err(ParseError{
-            message: format!("Not implemented: `{}` at {}", token.token_kind.str(), token.location.str()).into(),
-            location: token.location,
-        });
+        return Err(Diagnostic::error("E0202", format!("Not implemented: `{}`", token.token_kind.str()), token.span)
+            .with_label("this is not supported by the compiler yet"));
     }
 
-    fn message<T>(token: &Token, message: &'static str) -> ParseResult<T> {
-        return Err(ParseError{
-            message: message.into(),
-            location: token.location,
-        });
+    fn unknown_operator<T>(token: &Token) -> ParseResult<T> {
+        return Err(Diagnostic::error("E0203", format!("Unknown operator `{}`", token.token_kind.str()), token.span)
+            .with_label("not a valid operator here"));
     }
-}
 
-impl Debug for ParseError {
-    fn fmt(&self, fmt: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        return fmt.write_str(&self.message);
+    fn chained_operator<T>(token: &Token, previous: BinaryOp) -> ParseResult<T> {
+        return Err(Diagnostic::error("E0204", format!("Operator `{}` cannot be chained with `{}`", token.token_kind.str(), previous.str()), token.span)
+            .with_label("chained here")
+            .with_note("use parentheses to group the operations"));
+    }
+
+    fn unknown_label<T>(label: &str, span: Span) -> ParseResult<T> {
+        return Err(Diagnostic::error("E0205", format!("No labeled block `{}` to yield from", label), span)
+            .with_note(format!("`yield {} = value;` can only be used inside a labeled block `let {}: Type {{ ... }}`", label, label)));
     }
 }
 
@@ -54,80 +59,212 @@ pub type ParseResult<T> = Result<T, ParseError>;
 type Queue<T> = VecDeque<T>;
 
 pub struct Parser {
-    ast: GlobalNamespace,
-    unresolved_identifiers: Queue<*mut ScopedId>,
+    arena: Arena,
+    unresolved_identifiers: Queue<ScopedId>,
+    /// The labeled blocks around the statement being parsed, innermost last.
+    labels: Vec<DeclId>,
+    /// The imports of the file being parsed.
+    file_imports: Vec<Import>,
+    diagnostics: Vec<Diagnostic>,
 }
 
 impl Parser {
     pub fn new() -> Parser {
         return Parser {
-            ast: GlobalNamespace::new(),
+            arena: Arena::new(),
             unresolved_identifiers: Queue::new(),
+            labels: Vec::new(),
+            file_imports: Vec::new(),
+            diagnostics: Vec::new(),
         };
     }
 
-    pub fn parse<R: Read>(&mut self, mut tokenizer: Tokenizer<R>) -> ParseResult<()> {
+    /// Parses all tokens of a file into the global namespace, and returns the imports of the file.
+    /// Can be called once for each file of the program. Problems are collected and returned by `finish`.
+    pub fn parse(&mut self, mut tokenizer: Tokenizer) -> Box<[Import]> {
+        self.parse_declarations(GLOBAL_NAMESPACE, false, &mut tokenizer);
+        self.diagnostics.extend(tokenizer.take_diagnostics());
+        return std::mem::take(&mut self.file_imports).into();
+    }
+
+    /// Stores a problem that is found outside of the parser, e.g. an import that could not be loaded.
+    pub fn push_diagnostic(&mut self, diagnostic: Diagnostic) {
+        self.diagnostics.push(diagnostic);
+    }
+
+    /// Whether the path names a namespace of the files parsed so far.
+    pub fn is_namespace(&self, path: &[RcStr]) -> bool {
+        return resolver::find_namespace(&self.arena, path).is_some();
+    }
+
+    /// Resolves all identifiers and returns the arena of the parsed program,
+    /// together with every problem found while parsing and resolving.
+    pub fn finish(mut self) -> (Arena, Box<[Diagnostic]>) {
+        let mut resolver = Resolver::new(&mut self.arena);
+        let unresolved = resolver.resolve(self.unresolved_identifiers.drain(..));
+        let duplicates = resolver.find_duplicates();
+        let mut diagnostics = self.diagnostics;
+        diagnostics.extend(unresolved.iter().map(UnresolvedName::diagnostic));
+        diagnostics.extend(duplicates);
+        diagnostics.sort_by_key(|diagnostic| diagnostic.primary.as_ref().map(|label| (label.span.start.file, label.span.start.offset)));
+        return (self.arena, diagnostics.into());
+    }
+
+    /// Parses declarations into the namespace until the end of the file,
+    /// or until the `}` that closes the namespace when `in_brackets` is set.
+    /// When a declaration fails to parse, the error is stored and parsing continues after it.
+    fn parse_declarations(&mut self, namespace: ScopeId, in_brackets: bool, tokenizer: &mut Tokenizer) {
         use TokenKind::*;
         loop {
+            let unresolved_count = self.unresolved_identifiers.len();
             let token = tokenizer.next();
-            match token.token_kind {
-                Pub => {
-                    let declaration = self.parse_top_declaration_public(&self.ast, &mut tokenizer)?;
-                    self.ast.declarations.push(declaration);
-                }
-                Var => {
-                    let declaration = self.parse_top_declaration(false, DeclKeyword::Var, &self.ast, &mut tokenizer)?;
-                    self.ast.declarations.push(declaration);
+            let declaration = match token.token_kind {
+                Pub => self.parse_top_declaration_public(token.span, namespace, tokenizer),
+                Var => self.parse_top_declaration(false, DeclKeyword::Var, token.span, namespace, tokenizer),
+                Let => self.parse_top_declaration(false, DeclKeyword::Let, token.span, namespace, tokenizer),
+                Def => self.parse_top_declaration(false, DeclKeyword::Def, token.span, namespace, tokenizer),
+                Extern => self.parse_extern(false, token.span, namespace, tokenizer),
+                Namespace | Module => match self.parse_namespace(&token, namespace, in_brackets, tokenizer) {
+                    Ok(()) => continue,
+                    Err(error) => Err(error),
+                },
+                Import | Use => {
+                    let kind = if token.token_kind == Import { ImportKind::Import } else { ImportKind::Use };
+                    match parse_import(kind, tokenizer) {
+                        Ok(import) => {
+                            self.file_imports.push(import.clone());
+                            self.arena.namespace_mut(namespace).imports.push(import);
+                            continue;
+                        }
+                        Err(error) => Err(error),
+                    }
                 }
-                Let => {
-                    let declaration = self.parse_top_declaration(false, DeclKeyword::Let, &self.ast, &mut tokenizer)?;
-                    self.ast.declarations.push(declaration);
+                CloseBracket if in_brackets => return,
+                EOF if in_brackets => {
+                    let error: ParseResult<()> = ParseError::unexpected(&token, &["}"]);
+                    self.diagnostics.extend(error.err());
+                    return;
                 }
-                Def => {
-                    let declaration = self.parse_top_declaration(false, DeclKeyword::Def, &self.ast, &mut tokenizer)?;
-                    self.ast.declarations.push(declaration);
+                EOF => return,
+                _ => ParseError::not_implemented(&token),
+            };
+            match declaration {
+                Ok(declaration) => self.arena.namespace_mut(namespace).declarations.push(declaration),
+                Err(error) => {
+                    // The identifiers of the failed declaration have been dropped.
+                    self.unresolved_identifiers.truncate(unresolved_count);
+                    self.diagnostics.push(error);
+                    synchronize(tokenizer, in_brackets);
                 }
-                EOF => return Ok(()),
-                _ => return ParseError::not_implemented(&token),
-            }
+            };
         }
     }
 
-    fn parse_top_declaration_public<R: Read, S: Scope>(&mut self, scope: *const S, tokenizer: &mut Tokenizer<R>) -> ParseResult<Declaration> {
+    /// Parses a `namespace` or `module` after the keyword.
+    /// `namespace Name { ... }` contains the declarations in the brackets,
+    /// while `namespace Name;` contains the rest of the file and can only be used outside of brackets.
+    /// A namespace that is declared more than once in the same parent is extended with the new declarations.
+    fn parse_namespace(&mut self, keyword: &Token, parent: ScopeId, in_brackets: bool, tokenizer: &mut Tokenizer) -> ParseResult<()> {
         use TokenKind::*;
         let token = tokenizer.next();
+        let name = match token.token_kind {
+            Id(name) | Type(name) => name,
+            _ => return ParseError::unexpected(&token, &["identifier"]),
+        };
+        let token = tokenizer.next();
         match token.token_kind {
-            Var => return self.parse_top_declaration(true, DeclKeyword::Var, scope, tokenizer),
-            Let => return self.parse_top_declaration(true, DeclKeyword::Let, scope, tokenizer),
-            Def => return self.parse_top_declaration(true, DeclKeyword::Def, scope, tokenizer),
-            _ => return ParseError::unexpected(&token, &["var","let","def"]),
+            OpenBracket => {},
+            Semicolon if !in_brackets => {},
+            Semicolon => return Err(Diagnostic::error("E0206", format!("`{} {};` can only be used outside of brackets", keyword.token_kind.str(), name), token.span)
+                .with_note(format!("use `{} {} {{ ... }}` to declare it here", keyword.token_kind.str(), name))),
+            _ => return ParseError::unexpected(&token, &["{", ";"]),
         };
+
+        let subnamespace = self.arena.get_or_add_namespace(name, parent);
+        self.parse_declarations(subnamespace, token.token_kind == OpenBracket, tokenizer);
+        return Ok(());
     }
 
-    fn parse_top_declaration<R: Read, S: Scope>(&mut self, public: bool, decl_keyword: DeclKeyword, scope: *const S, tokenizer: &mut Tokenizer<R>) -> ParseResult<Declaration> {
+    /// Parses a declaration after `pub`, where `keyword` is the span of the `pub`.
+    fn parse_top_declaration_public(&mut self, keyword: Span, scope: ScopeId, tokenizer: &mut Tokenizer) -> ParseResult<Declaration> {
+        use TokenKind::*;
+        let token = tokenizer.next();
+        match token.token_kind {
+            Var => return self.parse_top_declaration(true, DeclKeyword::Var, keyword, scope, tokenizer),
+            Let => return self.parse_top_declaration(true, DeclKeyword::Let, keyword, scope, tokenizer),
+            Def => return self.parse_top_declaration(true, DeclKeyword::Def, keyword, scope, tokenizer),
+            Extern => return self.parse_extern(true, keyword, scope, tokenizer),
+            _ => return ParseError::unexpected(&token, &["var","let","def","extern"]),
+        };
+    }
+
+    /// Parses `extern def name(arguments) -> Type;` after `extern`, where `keyword` is the span of the first keyword.
+    fn parse_extern(&mut self, public: bool, keyword: Span, scope: ScopeId, tokenizer: &mut Tokenizer) -> ParseResult<Declaration> {
+        use TokenKind::*;
+        let token = tokenizer.next();
+        match token.token_kind {
+            Def => {},
+            _ => return ParseError::unexpected(&token, &["def"]),
+        };
+        let token = tokenizer.next();
+        let value_decl = match token.token_kind {
+            Id(identifier) => ValueDecl{
+                public,
+                decl_keyword: DeclKeyword::Def,
+                identifier,
+                identifier_span: token.span,
+                span: keyword.to(token.span),
+                type_kind: TypeKind::Inferred,
+                decl_kind: DeclKind::ExternDecl,
+            },
+            _ => return ParseError::unexpected(&token, &["identifier"]),
+        };
+        let token = tokenizer.next();
+        match token.token_kind {
+            OpenParen => return self.parse_func_decl(value_decl, scope, tokenizer),
+            _ => return ParseError::unexpected(&token, &["("]),
+        };
+    }
+
+    /// Parses a declaration after its keyword, where `keyword` is the span of the first keyword.
+    fn parse_top_declaration(&mut self, public: bool, decl_keyword: DeclKeyword, keyword: Span, scope: ScopeId, tokenizer: &mut Tokenizer) -> ParseResult<Declaration> {
         use TokenKind::*;
         let token = tokenizer.next();
         match token.token_kind {
             Id(identifier) => {
-                let mut type_kind = TypeKind::Inferred;
+                // The type, value and end of the span are filled in as the rest of the declaration is parsed.
+                let mut value_decl = ValueDecl{
+                    public,
+                    decl_keyword,
+                    identifier,
+                    identifier_span: token.span,
+                    span: keyword.to(token.span),
+                    type_kind: TypeKind::Inferred,
+                    decl_kind: DeclKind::EmptyDecl{},
+                };
                 let mut token = tokenizer.next();
                 if let Colon = token.token_kind {
-                    type_kind = self.parse_type(scope, tokenizer)?;
+                    value_decl.type_kind = self.parse_type(scope, tokenizer)?;
                     token = tokenizer.next();
                 }
                 match token.token_kind {
                     Equal => {
                         let expression = self.parse_expression_semicolon(scope, tokenizer)?;
-                        return Ok(Declaration::ValueDecl(Box::new(ValueDecl{
-                            public,
-                            decl_keyword,
-                            identifier,
-                            type_kind,
-                            decl_kind: DeclKind::AssignDecl(Box::new(expression)),
-                        })));
+                        value_decl.span.end = tokenizer.previous_end();
+                        value_decl.decl_kind = DeclKind::AssignDecl(Box::new(expression));
+                        return Ok(Declaration::ValueDecl(self.arena.add_declaration(value_decl)));
                     }
-                    OpenParen => return self.parse_func_decl(public, decl_keyword, identifier, scope, tokenizer),
-                    _ => return ParseError::unexpected(&token, &["=","("]),
+                    OpenParen => return self.parse_func_decl(value_decl, scope, tokenizer),
+                    OpenBracket => {
+                        let id = self.arena.add_declaration(value_decl);
+                        self.labels.push(id);
+                        let block = self.parse_block(scope, token.span.start, tokenizer);
+                        self.labels.pop();
+                        self.arena[id].span.end = tokenizer.previous_end();
+                        self.arena[id].decl_kind = DeclKind::BlockDecl(block?);
+                        return Ok(Declaration::ValueDecl(id));
+                    }
+                    _ => return ParseError::unexpected(&token, &["=","(","{"]),
                 };
             }
             OpenBracket => return ParseError::not_implemented(&token),
@@ -135,15 +272,23 @@ impl Parser {
         };
     }
 
-    fn parse_func_decl<R: Read, S: Scope>(&mut self, public: bool, decl_keyword: DeclKeyword, identifier: RcStr, scope: *const S, tokenizer: &mut Tokenizer<R>) -> ParseResult<Declaration> {
+    /// Parses a function after the opening parenthesis of its arguments.
+    fn parse_func_decl(&mut self, value_decl: ValueDecl, scope: ScopeId, tokenizer: &mut Tokenizer) -> ParseResult<Declaration> {
         let mut arguments = Vec::new();
         loop {
             use TokenKind::*;
             let token = tokenizer.next();
             match token.token_kind {
                 Id(arg_id) => {
-                    let parameter = self.parse_argument(arg_id, scope, tokenizer)?;
+                    let parameter = self.parse_argument(arg_id, token.span, scope, tokenizer)?;
                     arguments.push(parameter);
+
+                    let token = tokenizer.peek(0);
+                    match token.token_kind {
+                        Comma => tokenizer.consume_peeked(),
+                        CloseParen => {},
+                        _ => return ParseError::unexpected(token, &[",", ")"]),
+                    };
                 }
                 // Def => {
                 //     let parameter = self.parse_declaration_keyword(DeclKeyword::Def, scope, tokenizer);
@@ -153,7 +298,7 @@ impl Parser {
                     match token.token_kind {
                         Arrow => {
                             let return_type = self.parse_type(scope, tokenizer)?;
-                            return self.parse_func_body(public, decl_keyword, identifier, arguments.into(), return_type, scope, tokenizer);
+                            return self.parse_func_body(value_decl, arguments.into(), return_type, scope, tokenizer);
                         }
                         _ => return ParseError::unexpected(&token, &["->"]),
                     }
@@ -163,29 +308,41 @@ impl Parser {
         }
     }
 
-    fn parse_argument<R: Read, S: Scope>(&mut self, identifier: RcStr, scope: *const S, tokenizer: &mut Tokenizer<R>) -> ParseResult<Argument> {
+    fn parse_argument(&mut self, identifier: RcStr, identifier_span: Span, scope: ScopeId, tokenizer: &mut Tokenizer) -> ParseResult<Argument> {
         let token = tokenizer.next();
         match token.token_kind {
             TokenKind::Colon => {
-                let mut value_decl = Box::new(ValueDecl{
+                let type_kind = self.parse_type(scope, tokenizer)?;
+                let id = self.arena.add_declaration(ValueDecl{
                     public: true,
                     decl_keyword: DeclKeyword::Let,
-                    type_kind: TypeKind::Inferred,
+                    type_kind,
                     identifier,
+                    identifier_span,
+                    span: Span::new(identifier_span.start, tokenizer.previous_end()),
                     decl_kind: DeclKind::EmptyDecl{},
                 });
-                value_decl.type_kind = self.parse_type(&*value_decl, tokenizer)?;
-                return Ok(Argument{decl: Declaration::ValueDecl(value_decl)});
+                return Ok(Argument{decl: Declaration::ValueDecl(id)});
             }
             _ => return ParseError::unexpected(&token, &[":"]),
         };
     }
 
-    fn parse_type<R: Read, S: Scope>(&mut self, scope: *const S, tokenizer: &mut Tokenizer<R>) -> ParseResult<TypeKind> {
-        let token = tokenizer.peek(0);
+    fn parse_type(&mut self, scope: ScopeId, tokenizer: &mut Tokenizer) -> ParseResult<TypeKind> {
         use TokenKind::*;
         use TypeKind::*;
         use ast::Primitive::*;
+        if let (Type(_), Dot) = (tokenizer.peek(0).token_kind.clone(), tokenizer.peek(1).token_kind.clone()) {
+            let start = tokenizer.peek(0).span.start;
+            let (scope_described, name) = parse_path(tokenizer)?;
+            let span = Span::new(start, tokenizer.previous_end());
+            let id = self.arena.add_identifier(IdKind::Unresolved{scope_used: scope, scope_described});
+            let scoped_id = ScopedId{name, span, id};
+            self.unresolved_identifiers.push_back(scoped_id.clone());
+            return Ok(TypeKind::Identifier(Box::new(scoped_id)));
+        }
+        let arena = &mut self.arena;
+        let token = tokenizer.peek(0);
         let kind = match &token.token_kind {
             Type(identifier) => match identifier.as_ref() {
                 "U1"    => Some(Primitive(U1)),
@@ -204,13 +361,16 @@ impl Parser {
                 "F32"   => Some(Primitive(F32)),
                 "F64"   => Some(Primitive(F64)),
                 "F128"  => Some(Primitive(F128)),
-                _ => Some(TypeKind::Identifier(Box::new(ScopedId{
-                    name: identifier.clone(),
-                    id_kind: IdKind::Unresolved{
-                        scope_used: Scope::into_scopekind(scope),
-                        scope_described: Box::new([]),
-                    }
-                }))),
+                "Str"   => Some(Primitive(ast::Primitive::Str)),
+                _ => {
+                    let scoped_id = ScopedId{
+                        name: identifier.clone(),
+                        span: token.span,
+                        id: arena.add_identifier(IdKind::Unresolved{scope_used: scope, scope_described: Box::new([])}),
+                    };
+                    self.unresolved_identifiers.push_back(scoped_id.clone());
+                    Some(TypeKind::Identifier(Box::new(scoped_id)))
+                }
             }
             Comma =>        None,
             CloseParen =>   None,
@@ -231,150 +391,713 @@ impl Parser {
         };
     }
 
-    fn parse_func_body<R: Read, S: Scope>(&mut self, public: bool, decl_keyword: DeclKeyword, identifier: RcStr, arguments: Box<[Argument]>, return_type: TypeKind, scope: *const S, tokenizer: &mut Tokenizer<R>) -> ParseResult<Declaration> {
-        let mut value_decl = Box::new(ValueDecl{
-            public,
-            decl_keyword,
-            identifier,
-            type_kind: TypeKind::FuncType(Box::new(FuncType{
-                arguments,
-                return_type: Box::new(return_type),
-            })),
-            decl_kind: DeclKind::EmptyDecl{},
-        });
+    fn parse_func_body(&mut self, mut value_decl: ValueDecl, arguments: Box<[Argument]>, return_type: TypeKind, scope: ScopeId, tokenizer: &mut Tokenizer) -> ParseResult<Declaration> {
+        value_decl.type_kind = TypeKind::FuncType(Box::new(FuncType{
+            arguments,
+            return_type: Box::new(return_type),
+        }));
+        let id = self.arena.add_declaration(value_decl);
+        // External functions end after their return type, since their body is not part of the program.
+        if let DeclKind::ExternDecl = self.arena[id].decl_kind {
+            expect_semicolon(tokenizer)?;
+            self.arena[id].span.end = tokenizer.previous_end();
+            return Ok(Declaration::ValueDecl(id));
+        }
+        // Functions in namespaces are visible inside their body through the namespace, local functions through their own scope.
+        let scope = match self.arena[scope].kind {
+            ScopeKind::Namespace(..) => scope,
+            _ => self.arena.add_scope(ScopeKind::Declaration(id), scope),
+        };
+        let function_scope = self.arena.add_scope(ScopeKind::Function(id), scope);
         use TokenKind::*;
         let token = tokenizer.next();
-        value_decl.decl_kind = DeclKind::FuncDecl(Box::new(match token.token_kind {
-            OpenBracket => self.parse_block(&*value_decl, tokenizer)?,
-            Do => self.parse_expression_semicolon(&*value_decl, tokenizer)?,
-            _ => return ParseError::unexpected(&token, &["{","do"]),
-        }));
-        return Ok(Declaration::ValueDecl(value_decl));
+        // Labeled blocks around a nested function cannot be yielded from inside it.
+        let labels = std::mem::take(&mut self.labels);
+        let body = match token.token_kind {
+            OpenBracket => self.parse_block(function_scope, token.span.start, tokenizer).map(Expression::Block),
+            Do => self.parse_expression_semicolon(function_scope, tokenizer),
+            _ => ParseError::unexpected(&token, &["{","do"]),
+        };
+        self.labels = labels;
+        self.arena[id].span.end = tokenizer.previous_end();
+        self.arena[id].decl_kind = DeclKind::FuncDecl(Box::new(body?));
+        return Ok(Declaration::ValueDecl(id));
+    }
+
+    fn parse_expression_semicolon(&mut self, scope: ScopeId, tokenizer: &mut Tokenizer) -> ParseResult<Expression> {
+        let expression = self.parse_expression(scope, tokenizer)?;
+        expect_semicolon(tokenizer)?;
+        return Ok(expression);
     }
 
-    fn parse_expression_semicolon<R: Read, S: Scope>(&mut self, scope: *const S, tokenizer: &mut Tokenizer<R>) -> ParseResult<Expression> {
+    fn parse_expression(&mut self, scope: ScopeId, tokenizer: &mut Tokenizer) -> ParseResult<Expression> {
+        return self.parse_binary_expression(0, scope, tokenizer);
+    }
+
+    /// Parses binary operators with a precedence of at least `min_precedence` (precedence climbing).
+    /// See `BinaryOp` for the precedence and associativity of each operator.
+    fn parse_binary_expression(&mut self, min_precedence: u8, scope: ScopeId, tokenizer: &mut Tokenizer) -> ParseResult<Expression> {
+        let mut left = self.parse_unary_expression(scope, tokenizer)?;
+        let mut previous: Option<BinaryOp> = None;
+        loop {
+            let token = tokenizer.peek(0);
+            let operator = match &token.token_kind {
+                TokenKind::Op(string) => match BinaryOp::from_str(string) {
+                    Some(operator) => operator,
+                    None => return ParseError::unknown_operator(token),
+                },
+                _ => return Ok(left),
+            };
+            if operator.precedence() < min_precedence {
+                return Ok(left);
+            }
+            if let Some(previous) = previous {
+                if previous.associativity() == Associativity::None && previous.precedence() == operator.precedence() {
+                    return ParseError::chained_operator(token, previous);
+                }
+            }
+            let operator_span = token.span;
+            tokenizer.consume_peeked();
+
+            let right = self.parse_binary_expression(operator.precedence() + 1, scope, tokenizer)?;
+            let span = left.span().to(right.span());
+            left = Expression::Binary(Box::new(BinaryExpr{operator, left, right, operator_span, span}));
+            previous = Some(operator);
+        }
+    }
+
+    fn parse_unary_expression(&mut self, scope: ScopeId, tokenizer: &mut Tokenizer) -> ParseResult<Expression> {
+        let token = tokenizer.peek(0);
+        if let TokenKind::Op(string) = &token.token_kind {
+            let operator_span = token.span;
+            let operator = match UnaryOp::from_str(string) {
+                Some(operator) => operator,
+                None => return ParseError::unknown_operator(token),
+            };
+            tokenizer.consume_peeked();
+            let operand = self.parse_unary_expression(scope, tokenizer)?;
+            let span = operator_span.to(operand.span());
+            return Ok(Expression::Unary(Box::new(UnaryExpr{operator, operand, operator_span, span})));
+        }
+        return self.parse_postfix_expression(scope, tokenizer);
+    }
+
+    /// Parses calls and field accesses following a primary expression.
+    fn parse_postfix_expression(&mut self, scope: ScopeId, tokenizer: &mut Tokenizer) -> ParseResult<Expression> {
         use TokenKind::*;
-        let token = tokenizer.next();
-        match token.token_kind {
-            Id(identifier) => {
-                let token = tokenizer.next();
-                match token.token_kind {
-                    Semicolon => {
-                        let mut u_id = Box::new(ScopedId{
-                            id_kind: IdKind::Unresolved{
-                                scope_used: ScopeKind::from_ptr(scope),
-                                scope_described: Box::new([])
-                            },
-                            name: *Box::new(identifier.into()),
-                        });
-                        self.unresolved_identifiers.push_back(u_id.as_mut());
-                        return Ok(Expression::Identifier(u_id));
-                    }
-                    _ => ParseError::not_implemented(&token),
+        let mut expression = self.parse_primary_expression(scope, tokenizer)?;
+        loop {
+            let token = tokenizer.peek(0);
+            match token.token_kind {
+                OpenParen => {
+                    tokenizer.consume_peeked();
+                    let arguments = self.parse_call_arguments(scope, tokenizer)?;
+                    let span = Span::new(expression.span().start, tokenizer.previous_end());
+                    expression = Expression::Call(Box::new(CallExpr{callee: expression, arguments, span}));
                 }
+                Dot => {
+                    tokenizer.consume_peeked();
+                    let token = tokenizer.peek(0);
+                    let field_span = token.span;
+                    let field = match &token.token_kind {
+                        Id(field) => field.clone(),
+                        _ => return ParseError::unexpected(token, &["field name"]),
+                    };
+                    tokenizer.consume_peeked();
+                    let span = expression.span().to(field_span);
+                    expression = Expression::Field(Box::new(FieldExpr{object: expression, field, field_span, span}));
+                }
+                _ => return Ok(expression),
+            };
+        }
+    }
+
+    /// Parses the arguments of a call after the opening parenthesis, including the closing one.
+    fn parse_call_arguments(&mut self, scope: ScopeId, tokenizer: &mut Tokenizer) -> ParseResult<Box<[Expression]>> {
+        use TokenKind::*;
+        let mut arguments = Vec::new();
+        loop {
+            if let CloseParen = tokenizer.peek(0).token_kind {
+                tokenizer.consume_peeked();
+                return Ok(arguments.into());
             }
-            Int(integer) => {
-                let token = tokenizer.next();
-                match token.token_kind {
-                    Semicolon => return Ok(Expression::Integer(integer)),
-                    _ => ParseError::not_implemented(&token)
+            arguments.push(self.parse_expression(scope, tokenizer)?);
+
+            let token = tokenizer.peek(0);
+            match token.token_kind {
+                Comma => tokenizer.consume_peeked(),
+                CloseParen => {},
+                _ => return ParseError::unexpected(token, &[",", ")"]),
+            };
+        }
+    }
+
+    fn parse_primary_expression(&mut self, scope: ScopeId, tokenizer: &mut Tokenizer) -> ParseResult<Expression> {
+        use TokenKind::*;
+        let token = tokenizer.peek(0);
+        let span = token.span;
+        let expression = match &token.token_kind {
+            Id(identifier) => {
+                let id = self.arena.add_identifier(IdKind::Unresolved{scope_used: scope, scope_described: Box::new([])});
+                let scoped_id = ScopedId{name: identifier.clone(), span, id};
+                self.unresolved_identifiers.push_back(scoped_id.clone());
+                Expression::Identifier(Box::new(scoped_id))
+            }
+            Type(_) => {
+                let (scope_described, name) = parse_path(tokenizer)?;
+                let span = Span::new(span.start, tokenizer.previous_end());
+                let id = self.arena.add_identifier(IdKind::Unresolved{scope_used: scope, scope_described});
+                let scoped_id = ScopedId{name, span, id};
+                self.unresolved_identifiers.push_back(scoped_id.clone());
+                return Ok(Expression::Identifier(Box::new(scoped_id)));
+            }
+            Int(integer) => Expression::Integer(integer.clone(), span),
+            Dec(decimal) => Expression::Decimal(decimal.clone(), span),
+            Str(string) => Expression::String(string.clone(), span),
+            Bool(boolean) => Expression::Bool(*boolean, span),
+            If => {
+                tokenizer.consume_peeked();
+                return self.parse_if(false, span.start, scope, tokenizer);
+            }
+            Switch => {
+                tokenizer.consume_peeked();
+                return self.parse_switch(false, span.start, scope, tokenizer);
+            }
+            OpenParen => {
+                tokenizer.consume_peeked();
+                let expression = self.parse_expression(scope, tokenizer)?;
+                let token = tokenizer.peek(0);
+                if let CloseParen = token.token_kind {
+                    tokenizer.consume_peeked();
+                    return Ok(expression);
                 }
+                return ParseError::unexpected(token, &[")"]);
             }
-            Dec(decimal) => {
-                let token = tokenizer.next();
-                match token.token_kind {
-                    Semicolon => return Ok(Expression::Decimal(decimal)),
-                    _ => ParseError::not_implemented(&token)
+            _ => return ParseError::unexpected(token, &["expression"]),
+        };
+        tokenizer.consume_peeked();
+        return Ok(expression);
+    }
+
+    /// Parses the statements of a block after the opening bracket, including the closing one.
+    /// `start` is the location of the opening bracket.
+    /// When a statement fails to parse, the error is stored and parsing continues with the next statement.
+    fn parse_block(&mut self, scope: ScopeId, start: Location, tokenizer: &mut Tokenizer) -> ParseResult<Box<Block>> {
+        use TokenKind::*;
+        let mut block = Box::new(Block{
+            statements: Vec::new(),
+            scope: self.arena.add_scope(ScopeKind::Block, scope),
+            span: Span::new(start, start),
+        });
+        // The scope of the next statement, which includes the declarations before it.
+        let mut statement_scope = block.scope;
+        loop {
+            let unresolved_count = self.unresolved_identifiers.len();
+            let token = tokenizer.peek(0);
+            let statement = match token.token_kind {
+                CloseBracket => {
+                    tokenizer.consume_peeked();
+                    block.span.end = tokenizer.previous_end();
+                    return Ok(block);
                 }
+                EOF => return ParseError::unexpected(token, &["}"]),
+                _ => self.parse_statement(true, statement_scope, tokenizer),
+            };
+            match statement {
+                Ok(statement) => {
+                    if let Statement::Declaration(Declaration::ValueDecl(id)) = statement {
+                        statement_scope = self.arena.add_scope(ScopeKind::Declaration(id), statement_scope);
+                    }
+                    block.statements.push(statement);
+                }
+                Err(error) => {
+                    self.unresolved_identifiers.truncate(unresolved_count);
+                    self.diagnostics.push(error);
+                    synchronize(tokenizer, true);
+                }
+            };
+        }
+    }
+
+    /// Parses a statement in a block. Declarations are not allowed as the branches of `if` and `while`,
+    /// since they would not be visible to anything.
+    fn parse_statement(&mut self, allow_declaration: bool, scope: ScopeId, tokenizer: &mut Tokenizer) -> ParseResult<Statement> {
+        use TokenKind::*;
+        if let (Id(name), Equal) = (tokenizer.peek(0).token_kind.clone(), tokenizer.peek(1).token_kind.clone()) {
+            let span = tokenizer.next().span;
+            tokenizer.consume_peeked();
+            let id = self.arena.add_identifier(IdKind::Unresolved{scope_used: scope, scope_described: Box::new([])});
+            let target = ScopedId{name, span, id};
+            self.unresolved_identifiers.push_back(target.clone());
+            let expression = self.parse_expression_semicolon(scope, tokenizer)?;
+            return Ok(Statement::Assign{target, expression, span: Span::new(span.start, tokenizer.previous_end())});
+        }
+        let token = tokenizer.peek(0);
+        let keyword = token.span;
+        let start = keyword.start;
+        match token.token_kind {
+            Var | Let | Def if allow_declaration => {
+                let decl_keyword = match token.token_kind {
+                    Var => DeclKeyword::Var,
+                    Let => DeclKeyword::Let,
+                    _ => DeclKeyword::Def,
+                };
+                tokenizer.consume_peeked();
+                return Ok(Statement::Declaration(self.parse_top_declaration(false, decl_keyword, keyword, scope, tokenizer)?));
+            }
+            Pub => {
+                // Consumed so that recovery does not stop at it again.
+                let error = ParseError::unexpected(token, &["statement"]);
+                tokenizer.consume_peeked();
+                return error;
+            }
+            Return => {
+                tokenizer.consume_peeked();
+                let expression = self.parse_expression_semicolon(scope, tokenizer)?;
+                return Ok(Statement::Return{expression, span: Span::new(start, tokenizer.previous_end())});
+            }
+            Break => {
+                tokenizer.consume_peeked();
+                expect_semicolon(tokenizer)?;
+                return Ok(Statement::Break{span: Span::new(start, tokenizer.previous_end())});
+            }
+            Continue => {
+                tokenizer.consume_peeked();
+                expect_semicolon(tokenizer)?;
+                return Ok(Statement::Continue{span: Span::new(start, tokenizer.previous_end())});
             }
-            Bool(boolean) => {
+            OpenBracket => {
+                tokenizer.consume_peeked();
+                return Ok(Statement::Expression(Expression::Block(self.parse_block(scope, start, tokenizer)?)));
+            }
+            Yield => {
+                tokenizer.consume_peeked();
                 let token = tokenizer.next();
-                match token.token_kind {
-                    Semicolon => return Ok(Expression::Bool(boolean)),
-                    _ => ParseError::not_implemented(&token)
+                let label = match token.token_kind {
+                    Id(label) => label,
+                    _ => return ParseError::unexpected(&token, &["label"]),
+                };
+                let target = match self.labels.iter().rev().find(|target| self.arena[**target].identifier == label) {
+                    Some(target) => *target,
+                    None => return ParseError::unknown_label(&label, token.span),
+                };
+                let token = tokenizer.next();
+                if !matches!(token.token_kind, Equal) {
+                    return ParseError::unexpected(&token, &["="]);
                 }
+                let expression = self.parse_expression_semicolon(scope, tokenizer)?;
+                return Ok(Statement::Yield{label, target, expression, span: Span::new(start, tokenizer.previous_end())});
             }
-            _ => ParseError::not_implemented(&token),
-        }
+            If => {
+                tokenizer.consume_peeked();
+                return Ok(Statement::Expression(self.parse_if(true, start, scope, tokenizer)?));
+            }
+            While => {
+                tokenizer.consume_peeked();
+                let condition = self.parse_condition(scope, tokenizer)?;
+                let body = self.parse_statement(false, scope, tokenizer)?;
+                let span = Span::new(start, tokenizer.previous_end());
+                return Ok(Statement::Expression(Expression::While(Box::new(WhileExpr{condition, body, span}))));
+            }
+            For => {
+                tokenizer.consume_peeked();
+                return Ok(Statement::Expression(self.parse_for(start, scope, tokenizer)?));
+            }
+            Switch => {
+                tokenizer.consume_peeked();
+                return Ok(Statement::Expression(self.parse_switch(true, start, scope, tokenizer)?));
+            }
+            _ => return Ok(Statement::Expression(self.parse_expression_semicolon(scope, tokenizer)?)),
+        };
+    }
+
+    /// Parses an `if` after the keyword, including any `elif` and `else` branches.
+    /// As a statement, the branches are statements, otherwise they are expressions.
+    /// `start` is the location of the keyword.
+    fn parse_if(&mut self, is_statement: bool, start: Location, scope: ScopeId, tokenizer: &mut Tokenizer) -> ParseResult<Expression> {
+        use TokenKind::*;
+        let condition = self.parse_condition(scope, tokenizer)?;
+        let then_branch = self.parse_branch(is_statement, scope, tokenizer)?;
+
+        let token = tokenizer.peek(0);
+        let else_start = token.span.start;
+        let else_branch = match token.token_kind {
+            Elif => {
+                tokenizer.consume_peeked();
+                Some(Statement::Expression(self.parse_if(is_statement, else_start, scope, tokenizer)?))
+            }
+            Else => {
+                tokenizer.consume_peeked();
+                Some(self.parse_branch(is_statement, scope, tokenizer)?)
+            }
+            _ => None,
+        };
+        let span = Span::new(start, tokenizer.previous_end());
+        return Ok(Expression::If(Box::new(IfExpr{condition, then_branch, else_branch, span})));
     }
 
-    fn parse_block<R: Read, S: Scope>(&mut self, _scope: *const S, tokenizer: &mut Tokenizer<R>) -> ParseResult<Expression> {
-        return ParseError::not_implemented(&tokenizer.next());
+    fn parse_branch(&mut self, is_statement: bool, scope: ScopeId, tokenizer: &mut Tokenizer) -> ParseResult<Statement> {
+        if is_statement {
+            return self.parse_statement(false, scope, tokenizer);
+        }
+        let token = tokenizer.peek(0);
+        if let TokenKind::OpenBracket = token.token_kind {
+            let start = token.span.start;
+            tokenizer.consume_peeked();
+            return Ok(Statement::Expression(Expression::Block(self.parse_block(scope, start, tokenizer)?)));
+        }
+        return Ok(Statement::Expression(self.parse_expression(scope, tokenizer)?));
     }
-}
 
-impl Parser {
-    fn resolve_identifiers(&mut self) {
-        'next_unresolved: while !self.unresolved_identifiers.is_empty() {
-            let identifier = unsafe {self.unresolved_identifiers.pop_front().unwrap_unchecked()};
+    /// Parses a `switch` after the keyword, including the closing bracket of the cases.
+    /// As a value, each case is an expression followed by a `;`.
+    /// `start` is the location of the keyword.
+    fn parse_switch(&mut self, is_statement: bool, start: Location, scope: ScopeId, tokenizer: &mut Tokenizer) -> ParseResult<Expression> {
+        use TokenKind::*;
+        let value = self.parse_expression(scope, tokenizer)?;
+        let token = tokenizer.peek(0);
+        if let OpenBracket = token.token_kind {
+            tokenizer.consume_peeked();
+        }
+        else {
+            return ParseError::unexpected(token, &["{"]);
+        }
 
-            if let IdKind::Unresolved{scope_used, scope_described} = &mut unsafe {&mut *identifier}.id_kind {
-                if !scope_described.is_empty() {
-                    todo!("Could not handle scope_described {:?}", scope_described);
+        let mut cases = Vec::new();
+        let mut default = None;
+        loop {
+            let token = tokenizer.peek(0);
+            let case_start = token.span.start;
+            match token.token_kind {
+                Case => {
+                    tokenizer.consume_peeked();
+                    let mut patterns = vec![parse_pattern(tokenizer)?];
+                    while let Comma = tokenizer.peek(0).token_kind {
+                        tokenizer.consume_peeked();
+                        patterns.push(parse_pattern(tokenizer)?);
+                    }
+                    expect_colon(tokenizer)?;
+                    let body = self.parse_case_body(is_statement, scope, tokenizer)?;
+                    let span = Span::new(case_start, tokenizer.previous_end());
+                    cases.push(SwitchCase{patterns: patterns.into(), body, span});
                 }
-                if scope_used.resolve_identifier(unsafe {&mut *identifier}) {
-                    continue 'next_unresolved;
+                Else if default.is_none() => {
+                    tokenizer.consume_peeked();
+                    expect_colon(tokenizer)?;
+                    default = Some(self.parse_case_body(is_statement, scope, tokenizer)?);
                 }
-
-                let scope = scope_used;
-                loop {
-                    match *scope {
-                        ScopeKind::GlobalNamespace(namespace_ptr) => {
-                            let current_namespace = unsafe {&* namespace_ptr};
-                            if current_namespace.resolve_identifier(unsafe {&mut *identifier}) {
-                                continue 'next_unresolved;
-                            }
-                            else {
-                                panic!("Could not resolve identifier `{}`", unsafe {&mut *identifier}.name);
-                            }
-                        }
-                        ScopeKind::SubNamespace(namespace_ptr) => {
-                            let mut current_namespace = unsafe {&* namespace_ptr};
-                            'next_namespace: loop {
-                                if current_namespace.resolve_identifier(unsafe {&mut *identifier}) {
-                                    continue 'next_unresolved;
-                                }
-                                match current_namespace.parent {
-                                    NamespaceKind::GlobalNamespace(_) => {
-                                        panic!("Could not resolve identifier `{}`", unsafe {&mut *identifier}.name);
-                                    }
-                                    NamespaceKind::SubNamespace(namespace_ptr) => {
-                                        current_namespace = unsafe {&*namespace_ptr};
-                                        continue 'next_namespace;
-                                    }
-                                }
-                            }
-                        }
-                        ScopeKind::Function(function_ptr) => {
-                            let function = unsafe {&* function_ptr};
-                            if function.resolve_identifier(unsafe {&mut *identifier}) {
-                                continue 'next_unresolved;
-                            }
-                            todo!();
-                        }
-                        // ScopeKind::Block(block_ptr) => {
-                        //     let block = unsafe {&*block_ptr};
-                        //     if scope.resolve_identifier(unsafe {&mut *identifier}) {
-                        //         continue 'next_unresolved;
-                        //     }
-                        //     if block.parent.is_null() {
-                        //         panic!("Could not resolve identifier `{}`", unsafe {&mut *identifier}.name);
-                        //     }
-                        //     scope = unsafe {&mut *block.parent};
-                        // }
-                    }
+                CloseBracket => {
+                    tokenizer.consume_peeked();
+                    let span = Span::new(start, tokenizer.previous_end());
+                    return Ok(Expression::Switch(Box::new(SwitchExpr{value, cases: cases.into(), default, span})));
                 }
+                _ if default.is_none() => return ParseError::unexpected(token, &["case", "else", "}"]),
+                _ => return ParseError::unexpected(token, &["case", "}"]),
+            };
+        }
+    }
+
+    fn parse_case_body(&mut self, is_statement: bool, scope: ScopeId, tokenizer: &mut Tokenizer) -> ParseResult<Statement> {
+        if is_statement {
+            return self.parse_statement(false, scope, tokenizer);
+        }
+        let token = tokenizer.peek(0);
+        if let TokenKind::OpenBracket = token.token_kind {
+            let start = token.span.start;
+            tokenizer.consume_peeked();
+            return Ok(Statement::Expression(Expression::Block(self.parse_block(scope, start, tokenizer)?)));
+        }
+        return Ok(Statement::Expression(self.parse_expression_semicolon(scope, tokenizer)?));
+    }
+
+    /// Parses a `for` after the keyword: `for (i: Type in start..end) body`, where the `let` before `i` and the type are optional.
+    /// The loop variable is only visible in the body. `start` is the location of the keyword.
+    fn parse_for(&mut self, start: Location, scope: ScopeId, tokenizer: &mut Tokenizer) -> ParseResult<Expression> {
+        use TokenKind::*;
+        let token = tokenizer.next();
+        if !matches!(token.token_kind, OpenParen) {
+            return ParseError::unexpected(&token, &["("]);
+        }
+        let mut token = tokenizer.next();
+        let keyword = token.span;
+        if let Let = token.token_kind {
+            token = tokenizer.next();
+        }
+        let mut value_decl = match token.token_kind {
+            Id(identifier) => ValueDecl{
+                public: false,
+                decl_keyword: DeclKeyword::Let,
+                identifier,
+                identifier_span: token.span,
+                span: keyword.to(token.span),
+                type_kind: TypeKind::Inferred,
+                decl_kind: DeclKind::EmptyDecl{},
+            },
+            _ => return ParseError::unexpected(&token, &["identifier"]),
+        };
+        let mut token = tokenizer.next();
+        if let Colon = token.token_kind {
+            value_decl.type_kind = self.parse_type(scope, tokenizer)?;
+            token = tokenizer.next();
+        }
+        if !matches!(token.token_kind, In) {
+            return ParseError::unexpected(&token, &["in"]);
+        }
+        let range_start = self.parse_expression(scope, tokenizer)?;
+        let token = tokenizer.next();
+        if !matches!(token.token_kind, DotDot) {
+            return ParseError::unexpected(&token, &[".."]);
+        }
+        let range_end = self.parse_expression(scope, tokenizer)?;
+        value_decl.span.end = tokenizer.previous_end();
+        value_decl.decl_kind = DeclKind::LoopDecl{start: Box::new(range_start), end: Box::new(range_end)};
+        let token = tokenizer.next();
+        if !matches!(token.token_kind, CloseParen) {
+            return ParseError::unexpected(&token, &[")"]);
+        }
+        let variable = self.arena.add_declaration(value_decl);
+        let body_scope = self.arena.add_scope(ScopeKind::Declaration(variable), scope);
+        let body = self.parse_statement(false, body_scope, tokenizer)?;
+        let span = Span::new(start, tokenizer.previous_end());
+        return Ok(Expression::For(Box::new(ForExpr{variable, body, span})));
+    }
+
+    /// Parses a parenthesized condition, as in `if (condition)`.
+    fn parse_condition(&mut self, scope: ScopeId, tokenizer: &mut Tokenizer) -> ParseResult<Expression> {
+        let token = tokenizer.peek(0);
+        if let TokenKind::OpenParen = token.token_kind {
+            tokenizer.consume_peeked();
+        }
+        else {
+            return ParseError::unexpected(token, &["("]);
+        }
+        let condition = self.parse_expression(scope, tokenizer)?;
+        let token = tokenizer.peek(0);
+        if let TokenKind::CloseParen = token.token_kind {
+            tokenizer.consume_peeked();
+            return Ok(condition);
+        }
+        return ParseError::unexpected(token, &[")"]);
+    }
+}
+
+/// Consumes a `;`. If the next token is something else, it is left for error recovery.
+fn expect_semicolon(tokenizer: &mut Tokenizer) -> ParseResult<()> {
+    let token = tokenizer.peek(0);
+    if let TokenKind::Semicolon = token.token_kind {
+        tokenizer.consume_peeked();
+        return Ok(());
+    }
+    return ParseError::unexpected(token, &[";"]);
+}
+
+fn expect_colon(tokenizer: &mut Tokenizer) -> ParseResult<()> {
+    let token = tokenizer.peek(0);
+    if let TokenKind::Colon = token.token_kind {
+        tokenizer.consume_peeked();
+        return Ok(());
+    }
+    return ParseError::unexpected(token, &[":"]);
+}
+
+/// Parses a path to a declaration in a namespace, such as `Std.Cast.reinterpret`, starting at the peeked token.
+/// Namespaces are capitalized, so the path ends at the first lowercase name, or at a name that is not followed by a `.`.
+/// Returns the namespaces and the name of the declaration.
+fn parse_path(tokenizer: &mut Tokenizer) -> ParseResult<(Box<[IdStr]>, RcStr)> {
+    use TokenKind::*;
+    let mut namespaces = Vec::new();
+    loop {
+        let token = tokenizer.next();
+        let name = match token.token_kind {
+            Id(name) => return Ok((namespaces.into(), name)),
+            Type(name) => name,
+            _ => return ParseError::unexpected(&token, &["identifier"]),
+        };
+        if let Dot = tokenizer.peek(0).token_kind {
+            tokenizer.consume_peeked();
+            namespaces.push(name);
+        }
+        else {
+            return Ok((namespaces.into(), name));
+        }
+    }
+}
+
+/// Parses the path of an `import` or `use` after the keyword, including the `;`.
+fn parse_import(kind: ImportKind, tokenizer: &mut Tokenizer) -> ParseResult<Import> {
+    use TokenKind::*;
+    let token = tokenizer.next();
+    let mut span = token.span;
+    let mut path = match token.token_kind {
+        Id(name) | Type(name) | Str(name) => vec![name],
+        _ => return ParseError::unexpected(&token, &["path"]),
+    };
+    loop {
+        let token = tokenizer.next();
+        match token.token_kind {
+            Dot => {},
+            Semicolon => return Ok(ast::Import{kind, path: path.into(), span}),
+            _ => return ParseError::unexpected(&token, &[".", ";"]),
+        };
+        let token = tokenizer.next();
+        span = span.to(token.span);
+        match token.token_kind {
+            Id(name) | Type(name) => path.push(name),
+            _ => return ParseError::unexpected(&token, &["identifier"]),
+        };
+    }
+}
+
+/// Parses a `case` pattern: `true`, `-1` or `0..9`.
+fn parse_pattern(tokenizer: &mut Tokenizer) -> ParseResult<Pattern> {
+    let token = tokenizer.peek(0);
+    let span = token.span;
+    if let TokenKind::Bool(boolean) = token.token_kind {
+        tokenizer.consume_peeked();
+        return Ok(Pattern{kind: PatternKind::Bool(boolean), span});
+    }
+    let start = parse_int_literal(tokenizer)?;
+    if let TokenKind::DotDot = tokenizer.peek(0).token_kind {
+        tokenizer.consume_peeked();
+        let end = parse_int_literal(tokenizer)?;
+        return Ok(Pattern{kind: PatternKind::Range(start, end), span: Span::new(span.start, tokenizer.previous_end())});
+    }
+    return Ok(Pattern{kind: PatternKind::Integer(start), span: Span::new(span.start, tokenizer.previous_end())});
+}
+
+fn parse_int_literal(tokenizer: &mut Tokenizer) -> ParseResult<IntLiteral> {
+    let mut negative = false;
+    if let TokenKind::Op(operator) = &tokenizer.peek(0).token_kind {
+        if &**operator == "-" {
+            tokenizer.consume_peeked();
+            negative = true;
+        }
+    }
+    let token = tokenizer.peek(0);
+    if let TokenKind::Int(literal) = &token.token_kind {
+        let literal = literal.clone();
+        tokenizer.consume_peeked();
+        return Ok(IntLiteral{literal, negative});
+    }
+    return ParseError::unexpected(token, &["pattern"]);
+}
+
+/// Skips tokens until parsing can continue after an error.
+/// Stops after a `;` or `}`, or before a keyword that starts a new declaration.
+/// Inside a block or a namespace in brackets, it also stops before a `return` or the `}` that closes it.
+/// Blocks that are opened while skipping are skipped entirely.
+fn synchronize(tokenizer: &mut Tokenizer, in_block: bool) {
+    use TokenKind::*;
+    let mut depth = 0;
+    loop {
+        match tokenizer.peek(0).token_kind {
+            EOF => return,
+            Pub | Var | Let | Def | Namespace | Module | Import | Use if depth == 0 => return,
+            Return | Yield | CloseBracket if depth == 0 && in_block => return,
+            Semicolon if depth == 0 => {
+                tokenizer.consume_peeked();
+                return;
             }
-            else {
-                unreachable!("Identifier `{}` was stored in unresolved_identifiers but was not unresolved.", unsafe {&*identifier}.name);
+            CloseBracket if depth <= 1 => {
+                tokenizer.consume_peeked();
+                return;
             }
-        }
+            CloseBracket => depth -= 1,
+            OpenBracket => depth += 1,
+            _ => {},
+        };
+        tokenizer.consume_peeked();
     }
 }
 
-impl Into<GlobalNamespace> for Parser {
-    fn into(mut self) -> GlobalNamespace {
-        self.resolve_identifiers();
-        return self.ast;
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Parses the source as a single file, and returns the arena and the codes of the diagnostics.
+    fn parse(source: &str) -> (Arena, Vec<&'static str>) {
+        let mut parser = Parser::new();
+        parser.parse(Tokenizer::new(source.as_bytes(), 0));
+        let (arena, diagnostics) = parser.finish();
+        return (arena, diagnostics.iter().map(|diagnostic| diagnostic.code).collect());
+    }
+
+    /// The value of the global declaration, with parentheses around every operation.
+    fn value_str(arena: &Arena, name: &str) -> String {
+        let id = arena.namespace(GLOBAL_NAMESPACE).declarations.iter()
+            .map(|Declaration::ValueDecl(id)| *id)
+            .find(|id| &*arena[*id].identifier == name)
+            .unwrap_or_else(|| panic!("`{}` was not declared", name));
+        return match &arena[id].decl_kind {
+            DeclKind::AssignDecl(expression) => expression_str(expression),
+            decl_kind => panic!("`{}` is not assigned a value: {:?}", name, decl_kind),
+        };
+    }
+
+    fn expression_str(expression: &Expression) -> String {
+        return match expression {
+            Expression::Identifier(scoped_id) => scoped_id.name.to_string(),
+            Expression::Integer(literal, _) => literal.to_string(),
+            Expression::Unary(unary) => format!("({}{})", unary.operator.str(), expression_str(&unary.operand)),
+            Expression::Binary(binary) => format!("({} {} {})", expression_str(&binary.left), binary.operator.str(), expression_str(&binary.right)),
+            Expression::Call(call) => {
+                let arguments: Vec<String> = call.arguments.iter().map(expression_str).collect();
+                format!("{}({})", expression_str(&call.callee), arguments.join(", "))
+            }
+            _ => panic!("Unexpected expression {:?}", expression),
+        };
+    }
+
+    #[test]
+    fn precedence() {
+        let (arena, codes) = parse("
+            let a = 1; let b = 2; let c = 3;
+            def f(x: I32) -> I32 { return x; }
+            let sum = 1 + 2 * 3 - 4;
+            let left = a - b - c;
+            let mixed = a / b * c;
+            let negated = -a * b;
+            let call = -f(a) + 1;
+            let grouped = (a + b) * c;
+            let comparisons = a + b < c * 2 == b > a;
+            let not_equal = a != b + 1;
+        ");
+        assert!(codes.is_empty(), "{:?}", codes);
+        assert_eq!(value_str(&arena, "sum"), "((1 + (2 * 3)) - 4)");
+        assert_eq!(value_str(&arena, "left"), "((a - b) - c)");
+        assert_eq!(value_str(&arena, "mixed"), "((a / b) * c)");
+        assert_eq!(value_str(&arena, "negated"), "((-a) * b)");
+        assert_eq!(value_str(&arena, "call"), "((-f(a)) + 1)");
+        assert_eq!(value_str(&arena, "grouped"), "((a + b) * c)");
+        assert_eq!(value_str(&arena, "comparisons"), "(((a + b) < (c * 2)) == (b > a))");
+        assert_eq!(value_str(&arena, "not_equal"), "(a != (b + 1))");
+    }
+
+    #[test]
+    fn comparisons_cannot_be_chained() {
+        let (_, codes) = parse("
+            let a = 1; let b = 2; let c = 3;
+            let less = a < b < c;
+            let equal = a == b != c;
+            let grouped = (a == b) == (b < c);
+        ");
+        assert_eq!(codes, ["E0204", "E0204"]);
+    }
+
+    #[test]
+    fn recovers_after_errors() {
+        let (arena, codes) = parse("
+            let a = ;
+            def f() -> I32 {
+                let b = 1 +;
+                let c: I32 = 2;
+                return c
+            }
+            let d I32 = 4;
+            let e = 5;
+            def g() -> I32 { return e; }
+        ");
+        assert_eq!(codes, ["E0201", "E0201", "E0201", "E0201"]);
+        assert_eq!(value_str(&arena, "e"), "5");
     }
 }
@@ -0,0 +1,414 @@
+use std::collections::HashMap;
+
+use crate::ast::*;
+use crate::diagnostics::Diagnostic;
+
+/// Why an identifier could not be resolved.
+#[derive(Clone,Debug)]
+pub enum UnresolvedKind {
+    /// No declaration with the name is visible from the scope it is used in.
+    NotInScope,
+    /// `Namespace.name`, where the namespace has no declaration with the name. Contains the full name of the namespace.
+    NotInNamespace(RcStr),
+    /// A namespace in the path does not exist. Contains the full name of the namespace it was looked up in, if any.
+    UnknownNamespace(Option<RcStr>),
+    /// `Namespace.name`, where the declaration is not `pub` and is used outside the namespace.
+    Private{namespace: RcStr, declaration: Span},
+}
+
+/// An identifier that could not be resolved.
+#[derive(Clone,Debug)]
+pub struct UnresolvedName {
+    /// The name that was not found, which is a namespace for `UnknownNamespace`.
+    pub name: RcStr,
+    pub span: Span,
+    pub kind: UnresolvedKind,
+    /// A similar name that could have been meant.
+    pub suggestion: Option<RcStr>,
+}
+
+impl UnresolvedName {
+    pub fn diagnostic(&self) -> Diagnostic {
+        use UnresolvedKind::*;
+        let diagnostic = match &self.kind {
+            NotInScope => Diagnostic::error("E0301", format!("Cannot find `{}` in this scope", self.name), self.span)
+                .with_label("not found in this scope"),
+            NotInNamespace(namespace) => Diagnostic::error("E0301", format!("Cannot find `{}` in `{}`", self.name, namespace), self.span)
+                .with_label(format!("not found in `{}`", namespace)),
+            UnknownNamespace(parent) => {
+                let parent = match parent {
+                    Some(parent) => format!("`{}`", parent),
+                    None => "the global namespace".into(),
+                };
+                Diagnostic::error("E0302", format!("Cannot find namespace `{}` in {}", self.name, parent), self.span)
+                    .with_label("not a namespace")
+            }
+            Private{namespace, declaration} => Diagnostic::error("E0303", format!("`{}` is private to `{}`", self.name, namespace), self.span)
+                .with_label("private declaration")
+                .with_secondary(*declaration, "declared here without `pub`"),
+        };
+        return match &self.suggestion {
+            Some(suggestion) => diagnostic.with_note(format!("did you mean `{}`?", suggestion)),
+            None => diagnostic,
+        };
+    }
+}
+
+/// Resolves identifiers after every file of the program has been parsed, so that declarations can be used before they are declared.
+pub struct Resolver<'a> {
+    arena: &'a mut Arena,
+}
+
+impl<'a> Resolver<'a> {
+    pub fn new(arena: &'a mut Arena) -> Resolver<'a> {
+        return Resolver{arena};
+    }
+
+    /// Resolves the identifiers, stores what they resolve to in the arena, and returns the ones that could not be resolved.
+    pub fn resolve<I: IntoIterator<Item = ScopedId>>(&mut self, identifiers: I) -> Vec<UnresolvedName> {
+        let mut unresolved = Vec::new();
+        for identifier in identifiers {
+            let result = match &self.arena[identifier.id] {
+                IdKind::Unresolved{scope_used, scope_described} if scope_described.is_empty() => self.resolve_in_scope(&identifier, *scope_used),
+                IdKind::Unresolved{scope_used, scope_described} => self.resolve_qualified(&identifier, *scope_used, scope_described),
+                IdKind::Resolved{..} => unreachable!("Identifier `{}` already resolved", identifier.name),
+            };
+            match result {
+                Ok(id_kind) => self.arena[identifier.id] = id_kind,
+                Err(name) => unresolved.push(name),
+            };
+        }
+        return unresolved;
+    }
+
+    /// Looks up the identifier in the scope it is used in, and then in each enclosing scope.
+    fn resolve_in_scope(&self, identifier: &ScopedId, scope_used: ScopeId) -> Result<IdKind, UnresolvedName> {
+        let mut scope = Some(scope_used);
+        while let Some(current) = scope {
+            if let Some(declaration) = self.find_declaration(current, &identifier.name) {
+                return Ok(IdKind::Resolved{declaration, scope: current});
+            }
+            for used in self.used_namespaces(current) {
+                if let Some(declaration) = self.find_declaration(used, &identifier.name) {
+                    if self.arena[declaration].public || self.is_inside(scope_used, used) {
+                        return Ok(IdKind::Resolved{declaration, scope: used});
+                    }
+                }
+            }
+            scope = self.arena[current].parent;
+        }
+
+        let mut candidates = Vec::new();
+        let mut scope = Some(scope_used);
+        while let Some(current) = scope {
+            self.add_declared_names(current, identifier.span, &mut candidates);
+            for used in self.used_namespaces(current) {
+                let is_inside = self.is_inside(scope_used, used);
+                candidates.extend(self.declarations(used).into_iter()
+                    .filter(|id| self.arena[*id].public || is_inside)
+                    .map(|id| self.arena[id].identifier.clone()));
+            }
+            scope = self.arena[current].parent;
+        }
+        return Err(UnresolvedName{
+            name: identifier.name.clone(),
+            span: identifier.span,
+            kind: UnresolvedKind::NotInScope,
+            suggestion: suggest(&identifier.name, &candidates),
+        });
+    }
+
+    /// Resolves `A.B.name` by walking the nested namespaces from the global namespace.
+    /// A declaration found this way must be public, unless it is used inside the namespace that declares it.
+    fn resolve_qualified(&self, identifier: &ScopedId, scope_used: ScopeId, path: &[IdStr]) -> Result<IdKind, UnresolvedName> {
+        let mut namespace_id = GLOBAL_NAMESPACE;
+        for name in path {
+            let subnamespaces = &self.arena.namespace(namespace_id).subnamespaces;
+            match subnamespaces.iter().find(|subnamespace| self.arena.namespace(**subnamespace).name == *name) {
+                Some(subnamespace) => namespace_id = *subnamespace,
+                None => {
+                    let candidates: Vec<RcStr> = subnamespaces.iter().map(|subnamespace| self.arena.namespace(*subnamespace).name.clone()).collect();
+                    let parent = match namespace_id {
+                        GLOBAL_NAMESPACE => None,
+                        _ => Some(self.arena.namespace(namespace_id).full_name.clone()),
+                    };
+                    return Err(UnresolvedName{
+                        name: name.clone(),
+                        span: identifier.span,
+                        kind: UnresolvedKind::UnknownNamespace(parent),
+                        suggestion: suggest(name, &candidates),
+                    });
+                }
+            };
+        }
+        let namespace = self.arena.namespace(namespace_id);
+        let is_inside = self.is_inside(scope_used, namespace_id);
+
+        let declaration = match self.find_declaration(namespace_id, &identifier.name) {
+            Some(declaration) => declaration,
+            None => {
+                let candidates: Vec<RcStr> = namespace.declarations.iter()
+                    .map(|Declaration::ValueDecl(id)| &self.arena[*id])
+                    .filter(|value_decl| (value_decl.public || is_inside) && !is_being_defined(value_decl, identifier.span))
+                    .map(|value_decl| value_decl.identifier.clone())
+                    .collect();
+                return Err(UnresolvedName{
+                    name: identifier.name.clone(),
+                    span: identifier.span,
+                    kind: UnresolvedKind::NotInNamespace(namespace.full_name.clone()),
+                    suggestion: suggest(&identifier.name, &candidates),
+                });
+            }
+        };
+
+        let value_decl = &self.arena[declaration];
+        if !value_decl.public && !is_inside {
+            return Err(UnresolvedName{
+                name: identifier.name.clone(),
+                span: identifier.span,
+                kind: UnresolvedKind::Private{namespace: namespace.full_name.clone(), declaration: value_decl.identifier_span},
+                suggestion: None,
+            });
+        }
+        return Ok(IdKind::Resolved{declaration, scope: namespace_id});
+    }
+
+    /// The namespaces that the `use` imports of the scope name, if it is a namespace.
+    fn used_namespaces(&self, scope: ScopeId) -> Vec<ScopeId> {
+        return match &self.arena[scope].kind {
+            ScopeKind::Namespace(namespace) => namespace.imports.iter()
+                .filter(|import| import.kind == ImportKind::Use)
+                .filter_map(|import| find_namespace(self.arena, &import.path))
+                .collect(),
+            _ => Vec::new(),
+        };
+    }
+
+    /// Returns the declaration with the name that the scope itself declares, ignoring its parents.
+    fn find_declaration(&self, scope: ScopeId, name: &str) -> Option<DeclId> {
+        return self.declarations(scope).into_iter().find(|id| &*self.arena[*id].identifier == name);
+    }
+
+    /// Adds the names that are declared directly in the scope and can be used at the span.
+    fn add_declared_names(&self, scope: ScopeId, span: Span, names: &mut Vec<RcStr>) {
+        for id in self.declarations(scope) {
+            if !is_being_defined(&self.arena[id], span) {
+                names.push(self.arena[id].identifier.clone());
+            }
+        }
+    }
+
+    /// The declarations of the scope itself, ignoring its parents.
+    fn declarations(&self, scope: ScopeId) -> Vec<DeclId> {
+        return match &self.arena[scope].kind {
+            ScopeKind::Namespace(namespace) => namespace.declarations.iter().map(|Declaration::ValueDecl(id)| *id).collect(),
+            ScopeKind::Function(function) => match &self.arena[*function].type_kind {
+                TypeKind::FuncType(func_type) => func_type.arguments.iter().map(|argument| { let Declaration::ValueDecl(id) = argument.decl; id }).collect(),
+                _ => unreachable!("Function scope without a function type"),
+            },
+            ScopeKind::Block => Vec::new(),
+            ScopeKind::Declaration(id) => vec![*id],
+        };
+    }
+
+    /// Returns an error for each declaration with the same name as an earlier one in the same namespace.
+    /// The earlier one can also be in another file, or in another block of a namespace that is declared more than once.
+    pub fn find_duplicates(&self) -> Vec<Diagnostic> {
+        let mut diagnostics = Vec::new();
+        self.add_duplicates(GLOBAL_NAMESPACE, &mut diagnostics);
+        return diagnostics;
+    }
+
+    fn add_duplicates(&self, namespace_id: ScopeId, diagnostics: &mut Vec<Diagnostic>) {
+        let namespace = self.arena.namespace(namespace_id);
+        let mut first_declarations: HashMap<&str, DeclId> = HashMap::new();
+        for Declaration::ValueDecl(id) in &namespace.declarations {
+            let value_decl = &self.arena[*id];
+            match first_declarations.get(&*value_decl.identifier) {
+                Some(first) => diagnostics.push(Diagnostic::error("E0304", format!("`{}` is already defined", value_decl.identifier), value_decl.identifier_span)
+                    .with_label("defined again here")
+                    .with_secondary(self.arena[*first].identifier_span, format!("`{}` is first defined here", value_decl.identifier))),
+                None => { first_declarations.insert(&value_decl.identifier, *id); },
+            };
+        }
+        for subnamespace in &namespace.subnamespaces {
+            self.add_duplicates(*subnamespace, diagnostics);
+        }
+    }
+
+    /// Whether the scope is the namespace or is nested in it.
+    fn is_inside(&self, scope: ScopeId, namespace: ScopeId) -> bool {
+        let mut scope = Some(scope);
+        while let Some(current) = scope {
+            if current == namespace {
+                return true;
+            }
+            scope = self.arena[current].parent;
+        }
+        return false;
+    }
+}
+
+/// Returns the namespace that the path names, starting from the global namespace.
+pub fn find_namespace(arena: &Arena, path: &[RcStr]) -> Option<ScopeId> {
+    let mut namespace_id = GLOBAL_NAMESPACE;
+    for name in path {
+        namespace_id = *arena.namespace(namespace_id).subnamespaces.iter().find(|subnamespace| arena.namespace(**subnamespace).name == *name)?;
+    }
+    return Some(namespace_id);
+}
+
+/// Whether the span is in the value of the declaration, which cannot use the declaration itself, as in `let count = coutn;`.
+/// Functions can call themselves, so they are never being defined in this sense.
+fn is_being_defined(value_decl: &ValueDecl, span: Span) -> bool {
+    return match value_decl.decl_kind {
+        DeclKind::FuncDecl(..) | DeclKind::ExternDecl => false,
+        _ => value_decl.span.contains(span),
+    };
+}
+
+/// Returns the candidate that is closest to the name, if it is close enough to be a typo.
+/// Earlier candidates win ties, so candidates should be ordered from the nearest scope outwards.
+fn suggest(name: &str, candidates: &[RcStr]) -> Option<RcStr> {
+    let max_distance = (name.chars().count() / 3).max(1);
+    let mut best: Option<(usize, &RcStr)> = None;
+    for candidate in candidates {
+        let distance = edit_distance(name, candidate);
+        if distance <= max_distance && best.is_none_or(|(best_distance, _)| distance < best_distance) {
+            best = Some((distance, candidate));
+        }
+    }
+    return best.map(|(_, candidate)| candidate.clone());
+}
+
+/// The number of characters that must be inserted, removed, replaced or swapped with the next one to turn one string into the other.
+/// Changing only the case of a character counts as no change.
+fn edit_distance(left: &str, right: &str) -> usize {
+    let left: Vec<char> = left.chars().flat_map(char::to_lowercase).collect();
+    let right: Vec<char> = right.chars().flat_map(char::to_lowercase).collect();
+    // distances[i][j] is the distance between the first i characters of `left` and the first j characters of `right`.
+    let mut distances = vec![vec![0; right.len() + 1]; left.len() + 1];
+    for i in 0..=left.len() {
+        distances[i][0] = i;
+    }
+    for j in 0..=right.len() {
+        distances[0][j] = j;
+    }
+    for i in 1..=left.len() {
+        for j in 1..=right.len() {
+            let cost = if left[i - 1] == right[j - 1] { 0 } else { 1 };
+            let mut distance = (distances[i - 1][j - 1] + cost).min(distances[i - 1][j] + 1).min(distances[i][j - 1] + 1);
+            if i > 1 && j > 1 && left[i - 1] == right[j - 2] && left[i - 2] == right[j - 1] {
+                distance = distance.min(distances[i - 2][j - 2] + 1);
+            }
+            distances[i][j] = distance;
+        }
+    }
+    return distances[left.len()][right.len()];
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::diagnostics::Diagnostic;
+    use crate::parser::Parser;
+    use crate::tokenizer::Tokenizer;
+
+    fn resolve(source: &str) -> Box<[Diagnostic]> {
+        let mut parser = Parser::new();
+        parser.parse(Tokenizer::new(source.as_bytes(), 0));
+        return parser.finish().1;
+    }
+
+    fn codes(source: &str) -> Vec<&'static str> {
+        return resolve(source).iter().map(|diagnostic| diagnostic.code).collect();
+    }
+
+    /// The notes of the only diagnostic of the source.
+    fn notes(source: &str) -> Vec<String> {
+        let diagnostics = resolve(source);
+        assert_eq!(diagnostics.len(), 1, "{:?}", diagnostics);
+        return diagnostics[0].notes.iter().map(|note| note.to_string()).collect();
+    }
+
+    #[test]
+    fn declarations_can_be_used_before_they_are_declared() {
+        assert_eq!(codes("
+            def f() -> I32 { return g() + value; }
+            def g() -> I32 { return Math.square(2); }
+            let value = 1;
+            namespace Math { pub def square(x: I32) -> I32 { return x * x; } }
+        "), [] as [&str; 0]);
+    }
+
+    #[test]
+    fn locals_are_visible_after_their_declaration() {
+        assert_eq!(codes("def f() -> I32 { let a = b; let b = 1; return a; }"), ["E0301"]);
+        assert_eq!(codes("def f() -> I32 { { let a = 1; } return a; }"), ["E0301"]);
+        assert_eq!(codes("def f() -> I32 { for (i in 0..3) {} return i; }"), ["E0301"]);
+        assert_eq!(codes("def f(x: I32) -> I32 { let y = x; return y; }"), [] as [&str; 0]);
+    }
+
+    #[test]
+    fn used_namespaces_are_visible() {
+        assert_eq!(codes("
+            use Math;
+            def f() -> I32 { return square(2); }
+            namespace Math { pub def square(x: I32) -> I32 { return x * x; } }
+        "), [] as [&str; 0]);
+        assert_eq!(codes("use Math; def f() -> I32 { return hidden(); } namespace Math { def hidden() -> I32 { return 1; } }"), ["E0301"]);
+        // Declarations of the namespace itself come first.
+        assert_eq!(codes("use Math; let one = 1; def f() -> I32 { return one; } namespace Math { pub let one = 2; }"), [] as [&str; 0]);
+    }
+
+    #[test]
+    fn local_functions_can_call_themselves() {
+        assert_eq!(codes("
+            def f() -> I32 {
+                def factorial(n: I32) -> I32 {
+                    if (n <= 1) { return 1; }
+                    return n * factorial(n - 1);
+                }
+                return factorial(5);
+            }
+        "), [] as [&str; 0]);
+        // The function is still not visible before its declaration.
+        assert_eq!(codes("def f() -> I32 { let a = g(); def g() -> I32 { return 1; } return a; }"), ["E0301"]);
+    }
+
+    #[test]
+    fn private_declarations() {
+        let source = "
+            namespace Math {
+                pub def square(x: I32) -> I32 { return double(x) * x / 2; }
+                def double(x: I32) -> I32 { return x + x; }
+            }
+            def f() -> I32 { return Math.square(2) + Math.double(2); }
+        ";
+        assert_eq!(codes(source), ["E0303"]);
+        assert_eq!(codes("def f() -> I32 { return Math.cube(2); } namespace Math {}"), ["E0301"]);
+        assert_eq!(codes("def f() -> I32 { return Geometry.area(2); }"), ["E0302"]);
+    }
+
+    #[test]
+    fn type_paths_are_resolved() {
+        assert_eq!(codes("let x: Compare.Eq = 1;"), ["E0302"]);
+        assert_eq!(codes("def f(x: Missing) -> I32 { return 1; }"), ["E0301"]);
+        assert_eq!(codes("namespace Compare { let eq = 1; } let x: Compare.eq = 1;"), ["E0303"]);
+    }
+
+    #[test]
+    fn suggestions() {
+        assert_eq!(notes("let total = 1; def f() -> I32 { return totl; }"), ["did you mean `total`?"]);
+        assert_eq!(notes("def f() -> I32 { let count = 1; return cuont; }"), ["did you mean `count`?"]);
+        assert_eq!(notes("namespace Math { pub def square(x: I32) -> I32 { return x * x; } } def f() -> I32 { return Math.sqare(2); }"), ["did you mean `square`?"]);
+        // A value is not suggested inside its own definition, where it cannot be used.
+        assert_eq!(notes("def f() -> I32 { let value = valeu; return value; }"), [] as [&str; 0]);
+    }
+
+    #[test]
+    fn duplicate_declarations() {
+        assert_eq!(codes("let a = 1; def a() -> I32 { return 1; }"), ["E0304"]);
+        assert_eq!(codes("namespace A { let x = 1; } namespace A { let x = 2; }"), ["E0304"]);
+        assert_eq!(codes("let x = 1; namespace A { let x = 2; }"), [] as [&str; 0]);
+    }
+}
@@ -0,0 +1,29 @@
+use std::fs;
+use std::path::PathBuf;
+
+/// An empty directory for the files of a test, which is removed when the test ends.
+/// `name` must be unique among the tests, since they run in parallel.
+pub struct TestDir(pub PathBuf);
+
+impl TestDir {
+    pub fn new(name: &str) -> TestDir {
+        let path = std::env::temp_dir().join(format!("mylang-{}-{}", std::process::id(), name));
+        let _ = fs::remove_dir_all(&path);
+        fs::create_dir_all(&path).unwrap();
+        return TestDir(fs::canonicalize(path).unwrap());
+    }
+
+    /// Writes the file, creating its directories.
+    pub fn write(&self, path: &str, contents: &str) -> PathBuf {
+        let path = self.0.join(path);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(&path, contents).unwrap();
+        return path;
+    }
+}
+
+impl Drop for TestDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
@@ -1,24 +1,31 @@
-use std::io::{BufReader, Read, BufRead};
 use std::collections::VecDeque as Queue;
 
-use crate::ast::{RcStr, Location};
+use crate::ast::{BinaryOp, FileId, NumberLiteral, RcStr, Location, Span, UnaryOp};
+use crate::diagnostics::Diagnostic;
 
 #[derive(Clone,Debug,Eq,PartialEq)]
 pub enum TokenKind {
     Invalid(RcStr),
     Id(RcStr), Type(RcStr),
-    Int(RcStr), Dec(RcStr), Str(RcStr), Bool(bool), // NOTE: The builtin Bool type should at some point be replaced by a type from the standard library
+    /// A macro name after `#`, e.g. `throw` in `.#throw`.
+    MacroId(RcStr),
+    Int(RcStr), Dec(RcStr), Str(RcStr), Char(char), Bool(bool), // NOTE: The builtin Bool type should at some point be replaced by a type from the standard library
     Op(RcStr), Arrow, Equal,
-    Dot, Comma,
+    Dot, DotDot, DotBracket, Comma,
     Colon, Semicolon,
+    Question, Bang, Caret, Ampersand, Bar, Hash,
     OpenParen, CloseParen,
     OpenSquare, CloseSquare,
     OpenBracket, CloseBracket,
-    Pub,
+    Pub, Namespace, Module, Import, Use,
     Alias, Newtype, Struct, Union, Enum, Trait,
-    Impl, Of, For,
-    Var, Let, Def, Virt, Pure, Macro, Extern,
+    Impl, Of, For, In, Where, Constraint,
+    Var, Let, Def, Virt, Pure, Macro, Extern, Static, Builtin,
+    /// `some Trait`, an opaque type. It is not named `Some`, which would shadow `Option::Some` where `TokenKind::*` is used.
+    Opaque,
     Return, Break, Continue, Do,
+    If, Elif, Else, While, Switch, Case, Yield,
+    And, Or, Not,
     EOF,
 }
 
@@ -29,18 +36,29 @@ impl TokenKind {
             Invalid(s)  => s.clone(),
             Id(i)       => i.clone(),
             Type(i)     => i.clone(),
+            MacroId(i)  => format!("#{}", i).into(),
             Int(i)      => i.clone(),
             Dec(s)      => s.clone(),
-            Str(s)      => s.clone(),
+            // Quoted and escaped, so that it is shown on one line
+            Str(s)      => format!("{:?}", s).into(),
+            Char(c)     => format!("{:?}", c).into(),
             Bool(true)  => "true".into(),
             Bool(false) => "false".into(),
             Op(i)       => i.clone(),
             Arrow       => "->".into(),
             Equal       => "=".into(),
             Dot         => ".".into(),
+            DotDot      => "..".into(),
+            DotBracket  => ".{".into(),
             Comma       => ",".into(),
             Colon       => ":".into(),
             Semicolon   => ";".into(),
+            Question    => "?".into(),
+            Bang        => "!".into(),
+            Caret       => "^".into(),
+            Ampersand   => "&".into(),
+            Bar         => "|".into(),
+            Hash        => "#".into(),
             OpenParen   => "(".into(),
             CloseParen  => ")".into(),
             OpenSquare  => "[".into(),
@@ -48,6 +66,10 @@ impl TokenKind {
             OpenBracket => "{".into(),
             CloseBracket=> "}".into(),
             Pub         => "pub".into(),
+            Namespace   => "namespace".into(),
+            Module      => "module".into(),
+            Import      => "import".into(),
+            Use         => "use".into(),
             Alias       => "alias".into(),
             Newtype     => "newtype".into(),
             Struct      => "struct".into(),
@@ -57,6 +79,9 @@ impl TokenKind {
             Impl        => "impl".into(),
             Of          => "of".into(),
             For         => "for".into(),
+            In          => "in".into(),
+            Where       => "where".into(),
+            Constraint  => "constraint".into(),
             Var         => "var".into(),
             Let         => "let".into(),
             Def         => "def".into(),
@@ -64,10 +89,23 @@ impl TokenKind {
             Pure        => "pure".into(),
             Macro       => "macro".into(),
             Extern      => "extern".into(),
+            Static      => "static".into(),
+            Builtin     => "builtin".into(),
+            Opaque      => "some".into(),
             Return      => "return".into(),
             Break       => "break".into(),
             Continue    => "continue".into(),
             Do          => "do".into(),
+            If          => "if".into(),
+            Elif        => "elif".into(),
+            Else        => "else".into(),
+            While       => "while".into(),
+            Switch      => "switch".into(),
+            Case        => "case".into(),
+            Yield       => "yield".into(),
+            And         => "and".into(),
+            Or          => "or".into(),
+            Not         => "not".into(),
             EOF         => "<EOF>".into(),
         };
     }
@@ -76,33 +114,69 @@ impl TokenKind {
 #[derive(Debug)]
 pub struct Token {
     pub token_kind: TokenKind,
-    pub location: Location,
+    pub span: Span,
 }
 
-pub struct Tokenizer<R: Read> {
-    reader: BufReader<R>,
+pub struct Tokenizer {
+    /// The whole file, so that tokens do not depend on how the input is read.
+    /// Invalid UTF-8 has been reported and replaced by spaces.
+    source: Box<str>,
+    /// The offset of the first byte that is not part of a token yet.
+    position: usize,
     word: String,
     location: Location,
+    /// The location of the first character of the token being built.
+    token_start: Location,
+    /// The end of the last consumed token.
+    previous_end: Location,
     peeked: Queue<Token>,
+    diagnostics: Vec<Diagnostic>,
 }
 
-impl<R: Read> Tokenizer<R> {
-    pub fn new(read: R) -> Tokenizer<R> {
+impl Tokenizer {
+    /// Creates a tokenizer for the file with the given id, which is stored in the locations of the tokens.
+    pub fn new(bytes: &[u8], file: FileId) -> Tokenizer {
+        let mut diagnostics = Vec::new();
         return Tokenizer{
-            reader: BufReader::new(read),
+            source: decode_utf8(bytes, file, &mut diagnostics),
+            position: 0,
             word: String::new(),
-            location: Location{line: 1, char: 1},
+            location: Location{file, offset: 0},
+            token_start: Location{file, offset: 0},
+            previous_end: Location{file, offset: 0},
             peeked: Queue::new(),
+            diagnostics,
         };
     }
 
+    /// Returns the problems found in the tokens produced so far, and clears them.
+    pub fn take_diagnostics(&mut self) -> Vec<Diagnostic> {
+        return std::mem::take(&mut self.diagnostics);
+    }
+
+    /// Creates a token starting at `token_start`. The end of its span is set when the token is done.
     fn to_token(&self, token_type: TokenKind) -> Token {
-        return Token{token_kind: token_type, location: self.location};
+        return Token{token_kind: token_type, span: Span::new(self.token_start, self.token_start)};
     }
 
+    /// Returns the next valid token.
+    /// Invalid characters are reported as diagnostics and skipped.
     fn tokenize(&mut self) -> Token {
+        loop {
+            let token = self.tokenize_any();
+            match &token.token_kind {
+                TokenKind::Invalid(string) => {
+                    self.diagnostics.push(Diagnostic::error("E0101", format!("Invalid character `{}`", string), token.span)
+                        .with_label("not recognized by the tokenizer"));
+                }
+                _ => return token,
+            }
+        }
+    }
+
+    fn tokenize_any(&mut self) -> Token {
         fn is_delimiter(c: char) -> bool {
-            return ",:;()[]{}".chars().any(|e| c == e);
+            return ",:;?^&|()[]{}".chars().any(|e| c == e);
         }
         fn from_delimiter(c: char) -> TokenKind {
             use TokenKind::*;
@@ -110,6 +184,10 @@ impl<R: Read> Tokenizer<R> {
                 ',' => Comma,
                 ':' => Colon,
                 ';' => Semicolon,
+                '?' => Question,
+                '^' => Caret,
+                '&' => Ampersand,
+                '|' => Bar,
                 '(' => OpenParen,
                 ')' => CloseParen,
                 '[' => OpenSquare,
@@ -127,10 +205,14 @@ impl<R: Read> Tokenizer<R> {
             IsEmpty,
             IsId,
             IsType,
-            IsString,
             IsNumber,
+            /// A number with a `0x`, `0o` or `0b` prefix.
+            IsPrefixed,
             IsDecimal,
+            IsExponent,
             IsOperator,
+            /// A macro name after `#`.
+            IsMacro,
             IsComment{
                 is_line: bool,
                 block_depth: u16
@@ -138,143 +220,184 @@ impl<R: Read> Tokenizer<R> {
         }
 
         let mut consumed = 0;
-        let token: Token;
+        let mut token: Token;
+        self.token_start = self.location;
 
         'build_token: loop {
             use TokenState::*;
             let mut state = IsEmpty;
 
             'build_word: while self.word.is_empty() {
-                let bytes = self.reader.fill_buf().unwrap(); // NOTE: Constantly refilling the buffer might be dumb
+                let bytes = &self.source[self.position..];
 
                 if bytes.is_empty() {
+                    self.token_start = self.location;
                     return self.to_token(TokenKind::EOF);
                 }
 
-                let mut c = match bytes.get(consumed) {
-                    Some(b) => *b as char,
+                let mut c = match char_at(bytes, consumed) {
+                    Some(c) => c,
                     None => break 'build_word,
                 };
-
-                let mut next = bytes.get(consumed + 1).map(|b| *b as char);
+                let mut width = c.len_utf8();
+                let mut next = char_at(bytes, consumed + width);
 
                 loop {
+                    if state == IsEmpty {
+                        self.token_start = self.location;
+                    }
                     match state {
                         IsEmpty => match c {
                             '\n' => {
-                                consumed += 1;
-                                self.location.inc_line();
+                                consumed += width;
+                                self.location.advance(width);
                             }
                             ws if ws.is_ascii_whitespace() => {
-                                consumed += 1;
-                                self.location.inc_char(1);
+                                consumed += width;
+                                self.location.advance(width);
                             }
                             '/' if next == Some('/') => {
                                 consumed += 2;
-                                self.location.inc_char(2);
+                                self.location.advance(2);
                                 state = IsComment{is_line: true, block_depth: 0};
                             }
                             '/' if next == Some('*') => {
                                 consumed += 2;
-                                self.location.inc_char(2);
+                                self.location.advance(2);
                                 state = IsComment{is_line: false, block_depth: 1};
                             }
-                            '"' => {
-                                consumed += 1;
-                                self.location.inc_char(1);
-                                state = IsString;
+                            '"' | '\'' => {
+                                let (token_kind, length) = scan_quoted(&bytes[consumed..], self.location, &mut self.diagnostics);
+                                token = self.to_token(token_kind);
+                                consumed += length;
+                                self.location.advance(length);
+                                break 'build_token;
+                            }
+                            'r' if is_raw_string_start(&bytes[consumed..]) => {
+                                let (token_kind, length) = scan_quoted(&bytes[consumed..], self.location, &mut self.diagnostics);
+                                token = self.to_token(token_kind);
+                                consumed += length;
+                                self.location.advance(length);
+                                break 'build_token;
                             }
                             '-' if next == Some('>') => {
                                 token = self.to_token(TokenKind::Arrow);
                                 consumed += 2;
-                                self.location.inc_char(2);
+                                self.location.advance(2);
                                 break 'build_token;
                             }
-                            '=' => 'block: {
-                                consumed += 1;
-                                self.location.inc_char(1);
-                                if let Some(op) = next { if is_operator_symbol(op) { // Waiting for better if-let expressions
-                                    state = IsOperator;
-                                    self.word.push(op);
-                                    break 'block;
-                                }}
+                            '=' if next == Some('=') => {
+                                consumed += width;
+                                self.location.advance(width);
+                                state = IsOperator;
+                                self.word.push('=');
+                            }
+                            '=' => {
                                 token = self.to_token(TokenKind::Equal);
+                                consumed += width;
+                                self.location.advance(width);
+                                break 'build_token;
+                            }
+                            '.' if next == Some('.') => {
+                                token = self.to_token(TokenKind::DotDot);
+                                consumed += 2;
+                                self.location.advance(2);
+                                break 'build_token;
+                            }
+                            '.' if next == Some('{') => {
+                                token = self.to_token(TokenKind::DotBracket);
+                                consumed += 2;
+                                self.location.advance(2);
                                 break 'build_token;
                             }
                             '.' => {
                                 token = self.to_token(TokenKind::Dot);
-                                consumed += 1;
-                                self.location.inc_char(1);
+                                consumed += width;
+                                self.location.advance(width);
                                 break 'build_token;
                             }
-                            nu if nu.is_numeric() => {
-                                consumed += 1;
-                                self.location.inc_char(1);
+                            '0' if matches!(next, Some('x' | 'o' | 'b')) => {
+                                consumed += 2;
+                                self.location.advance(2);
+                                state = IsPrefixed;
+                                self.word.push('0');
+                                self.word.push(next.unwrap());
+                            }
+                            nu if nu.is_ascii_digit() => {
+                                consumed += width;
+                                self.location.advance(width);
                                 state = IsNumber;
                                 self.word.push(nu);
                             }
                             op if is_operator_symbol(op) => {
-                                consumed += 1;
-                                self.location.inc_char(1);
+                                consumed += width;
+                                self.location.advance(width);
                                 state = IsOperator;
                                 self.word.push(op);
                             }
                             de if is_delimiter(de) => {
                                 token = self.to_token(from_delimiter(de));
-                                consumed += 1;
-                                self.location.inc_char(1);
+                                consumed += width;
+                                self.location.advance(width);
                                 break 'build_token;
                             }
                             ch if ch.is_alphanumeric() && ch.is_uppercase() => {
-                                consumed += 1;
-                                self.location.inc_char(1);
+                                consumed += width;
+                                self.location.advance(width);
                                 state = IsType;
                                 self.word.push(ch);
                             }
-                            ch if ch.is_alphanumeric() && ch.is_lowercase() => {
-                                consumed += 1;
-                                self.location.inc_char(1);
+                            ch if ch.is_alphabetic() => {
+                                consumed += width;
+                                self.location.advance(width);
                                 state = IsId;
                                 self.word.push(ch);
                             }
                             '_' => {
-                                consumed += 1;
-                                self.location.inc_char(1);
+                                consumed += width;
+                                self.location.advance(width);
                                 state = IsId;
                                 self.word.push('_');
                             }
+                            '#' if next.is_some_and(|ch| ch.is_alphabetic() || ch == '_') => {
+                                consumed += width;
+                                self.location.advance(width);
+                                state = IsMacro;
+                            }
                             '#' => {
-                                consumed += 1;
-                                self.location.inc_char(1);
+                                token = self.to_token(TokenKind::Hash);
+                                consumed += width;
+                                self.location.advance(width);
+                                break 'build_token;
+                            }
+                            '!' if next == Some('=') => {
+                                consumed += width;
+                                self.location.advance(width);
                                 state = IsOperator;
-                                self.word.push('#');
+                                self.word.push('!');
+                            }
+                            '!' => {
+                                token = self.to_token(TokenKind::Bang);
+                                consumed += width;
+                                self.location.advance(width);
+                                break 'build_token;
                             }
                             ch => {
                                 token = self.to_token(TokenKind::Invalid(ch.to_string().into()));
-                                consumed += 1;
-                                self.location.inc_char(1);
+                                consumed += width;
+                                self.location.advance(width);
                                 break 'build_token;
                             }
                         }
                         IsId => match c {
-                            '\n' => {
-                                consumed += 1;
-                                self.location.inc_line();
-                                break 'build_word;
-                            }
-                            ws if ws.is_ascii_whitespace() => {
-                                consumed += 1;
-                                self.location.inc_char(1);
-                                break 'build_word;
-                            }
                             ch if ch.is_alphanumeric() => {
-                                consumed += 1;
-                                self.location.inc_char(1);
+                                consumed += width;
+                                self.location.advance(width);
                                 self.word.push(ch);
                             }
                             '_' => {
-                                consumed += 1;
-                                self.location.inc_char(1);
+                                consumed += width;
+                                self.location.advance(width);
                                 self.word.push('_');
                             }
                             _ => {
@@ -282,58 +405,20 @@ impl<R: Read> Tokenizer<R> {
                             }
                         }
                         IsType => match c {
-                            '\n' => {
-                                consumed += 1;
-                                self.location.inc_line();
-                                break 'build_word;
-                            }
-                            ws if ws.is_ascii_whitespace() => {
-                                consumed += 1;
-                                self.location.inc_char(1);
-                                break 'build_word;
-                            }
                             ch if ch.is_alphanumeric() => {
-                                consumed += 1;
-                                self.location.inc_char(1);
+                                consumed += width;
+                                self.location.advance(width);
                                 self.word.push(ch);
                             }
                             _ => {
                                 break 'build_word;
                             }
                         }
-                        IsString => match c {
-                            '\n' => {
-                                token = self.to_token(TokenKind::Invalid(self.word.to_owned().into()));
-                                consumed += 1;
-                                self.location.inc_line();
-                                break 'build_token;
-                            }
-                            '"' => {
-                                consumed += 1;
-                                self.location.inc_char(1);
-                                break 'build_word;
-                            }
-                            ch => {
-                                consumed += 1;
-                                self.location.inc_char(1);
-                                self.word.push(ch);
-                            }
-                        }
                         IsNumber => match c {
-                            '\n' => {
-                                consumed += 1;
-                                self.location.inc_line();
-                                break 'build_word;
-                            }
-                            ws if ws.is_ascii_whitespace() => {
-                                consumed += 1;
-                                self.location.inc_char(1);
-                                break 'build_word;
-                            }
                             '.' => 'block: {
-                                if let Some(nu) = next { if nu.is_numeric() { // Waiting for better better if-let expressions
+                                if let Some(nu) = next { if nu.is_ascii_digit() { // Waiting for better better if-let expressions
                                     consumed += 2;
-                                    self.location.inc_char(2);
+                                    self.location.advance(2);
                                     state = IsDecimal;
                                     self.word.push('.');
                                     self.word.push(nu);
@@ -341,9 +426,16 @@ impl<R: Read> Tokenizer<R> {
                                 }}
                                 break 'build_word;
                             }
-                            ch if ch.is_numeric() => {
-                                consumed += 1;
-                                self.location.inc_char(1);
+                            'e' | 'E' if is_exponent(&bytes[consumed..]) => {
+                                consumed += width;
+                                self.location.advance(width);
+                                state = IsExponent;
+                                self.word.push(c);
+                            }
+                            // Digits, `_` separators and the suffix, which are checked when the token is done
+                            ch if ch.is_alphanumeric() || ch == '_' => {
+                                consumed += width;
+                                self.location.advance(width);
                                 self.word.push(ch);
                             }
                             _ => {
@@ -351,48 +443,70 @@ impl<R: Read> Tokenizer<R> {
                             }
                         }
                         IsDecimal => match c {
-                            '\n' => {
-                                consumed += 1;
-                                self.location.inc_line();
-                                break 'build_word;
+                            'e' | 'E' if is_exponent(&bytes[consumed..]) => {
+                                consumed += width;
+                                self.location.advance(width);
+                                state = IsExponent;
+                                self.word.push(c);
                             }
-                            ws if ws.is_ascii_whitespace() => {
-                                consumed += 1;
-                                self.location.inc_char(1);
+                            ch if ch.is_alphanumeric() || ch == '_' => {
+                                consumed += width;
+                                self.location.advance(width);
+                                self.word.push(ch);
+                            }
+                            _ => {
                                 break 'build_word;
                             }
-                            ch if ch.is_numeric() => {
-                                consumed += 1;
-                                self.location.inc_char(1);
+                        }
+                        IsExponent => match c {
+                            '+' | '-' if self.word.ends_with(['e', 'E']) => {
+                                consumed += width;
+                                self.location.advance(width);
+                                self.word.push(c);
+                            }
+                            ch if ch.is_alphanumeric() || ch == '_' => {
+                                consumed += width;
+                                self.location.advance(width);
                                 self.word.push(ch);
                             }
                             _ => {
                                 break 'build_word;
                             }
                         }
-                        IsOperator => match c {
-                            '\n' => {
-                                consumed += 1;
-                                self.location.inc_line();
+                        IsPrefixed => match c {
+                            ch if ch.is_alphanumeric() || ch == '_' => {
+                                consumed += width;
+                                self.location.advance(width);
+                                self.word.push(ch);
+                            }
+                            _ => {
                                 break 'build_word;
                             }
-                            ws if ws.is_ascii_whitespace() => {
-                                consumed += 1;
-                                self.location.inc_char(1);
+                        }
+                        IsMacro => match c {
+                            ch if ch.is_alphanumeric() || ch == '_' => {
+                                consumed += width;
+                                self.location.advance(width);
+                                self.word.push(ch);
+                            }
+                            _ => {
                                 break 'build_word;
                             }
+                        }
+                        IsOperator => match c {
                             '/' if next == Some('/') || next == Some('*') => {
                                 break 'build_word;
                             }
                             '-' if next == Some('>') => {
                                 break 'build_word;
                             }
-                            nu if nu.is_numeric() => {
+                            nu if nu.is_ascii_digit() => {
                                 break 'build_word;
                             }
-                            op if is_operator_symbol(op) => {
-                                consumed += 1;
-                                self.location.inc_char(1);
+                            // Only the longest known operator is read, so `x*-1` is `*` followed by `-`.
+                            op if is_operator_symbol(op) && is_known_operator(&format!("{}{}", self.word, op)) => {
+                                consumed += width;
+                                self.location.advance(width);
                                 self.word.push(op);
                             }
                             de if is_delimiter(de) => {
@@ -409,59 +523,55 @@ impl<R: Read> Tokenizer<R> {
                                     if block_depth == 0 {
                                         state = IsEmpty;
                                     } else {
-                                        state = IsComment{is_line: true, block_depth};
+                                        state = IsComment{is_line: false, block_depth};
                                     };
-                                    self.location.inc_line();
+                                    self.location.advance(1);
                                 }
                                 _ => {
-                                    consumed += 1;
-                                    self.location.inc_char(1);
+                                    consumed += width;
+                                    self.location.advance(width);
                                 }
                             }}
                             else if !is_line { match c {
                                 '\n' => {
-                                    consumed += 1;
-                                    self.location.inc_line();
+                                    consumed += width;
+                                    self.location.advance(width);
                                 }
                                 '/' if next == Some('/') => {
                                     consumed += 2;
-                                    self.location.inc_char(2);
+                                    self.location.advance(2);
                                     state = IsComment{is_line: true, block_depth};
                                 }
                                 '/' if next == Some('*') => {
                                     consumed += 2;
-                                    self.location.inc_char(2);
+                                    self.location.advance(2);
                                     state = IsComment{is_line: false, block_depth: block_depth + 1};
                                 }
                                 '*' if next == Some('/') => {
                                     consumed += 2;
-                                    self.location.inc_char(2);
-                                    if block_depth == 0 {
+                                    self.location.advance(2);
+                                    if block_depth == 1 {
                                         state = IsEmpty;
                                     } else {
                                         state = IsComment{is_line: false, block_depth: block_depth - 1};
                                     }
                                 }
                                 _ => {
-                                    consumed += 1;
-                                    self.location.inc_char(1);
+                                    consumed += width;
+                                    self.location.advance(width);
                                 }
                             }}
                         }
                     }
 
-                    c = match bytes.get(consumed) {
-                        Some(b) => *b as char,
+                    c = match char_at(bytes, consumed) {
+                        Some(c) => c,
                         None => { match state {
-                            IsString => {
-                                token = self.to_token(TokenKind::Invalid(self.word.to_owned().into()));
-                                break 'build_token;
-                            }
-                            IsDecimal => {
+                            IsDecimal | IsExponent => {
                                 token = self.to_token(TokenKind::Dec(self.word.to_owned().into()));
                                 break 'build_token;
                             }
-                            IsNumber => {
+                            IsNumber | IsPrefixed => {
                                 token = self.to_token(TokenKind::Int(self.word.to_owned().into()));
                                 break 'build_token;
                             }
@@ -469,7 +579,16 @@ impl<R: Read> Tokenizer<R> {
                                 token = self.to_token(TokenKind::Op(self.word.to_owned().into()));
                                 break 'build_token;
                             }
-                            IsComment{is_line: _, block_depth: _} => {
+                            IsMacro => {
+                                token = self.to_token(TokenKind::MacroId(self.word.to_owned().into()));
+                                break 'build_token;
+                            }
+                            IsComment{is_line, block_depth} => {
+                                if !is_line || block_depth > 0 {
+                                    self.diagnostics.push(Diagnostic::warning("E0103", "Unterminated block comment", Span::new(self.token_start, self.location))
+                                        .with_label("the comment is not closed before the end of the file"));
+                                }
+                                self.token_start = self.location;
                                 token = self.to_token(TokenKind::EOF);
                                 break 'build_token;
                             }
@@ -479,7 +598,8 @@ impl<R: Read> Tokenizer<R> {
                         }}
                     };
 
-                    next = bytes.get(consumed + 1).map(|b| *b as char);
+                    width = c.len_utf8();
+                    next = char_at(bytes, consumed + width);
                 }
             }
 
@@ -492,6 +612,10 @@ impl<R: Read> Tokenizer<R> {
                     "true"      => Bool(true),
                     "false"     => Bool(false),
                     "pub"       => Pub,
+                    "namespace" => Namespace,
+                    "module"    => Module,
+                    "import"    => Import,
+                    "use"       => Use,
                     "alias"     => Alias,
                     "newtype"   => Newtype,
                     "struct"    => Struct,
@@ -501,6 +625,9 @@ impl<R: Read> Tokenizer<R> {
                     "impl"      => Impl,
                     "of"        => Of,
                     "for"       => For,
+                    "in"        => In,
+                    "where"     => Where,
+                    "constraint"=> Constraint,
                     "var"       => Var,
                     "let"       => Let,
                     "def"       => Def,
@@ -508,36 +635,58 @@ impl<R: Read> Tokenizer<R> {
                     "pure"      => Pure,
                     "macro"     => Macro,
                     "extern"    => Extern,
+                    "static"    => Static,
+                    "builtin"   => Builtin,
+                    "some"      => Opaque,
                     "return"    => Return,
                     "break"     => Break,
                     "continue"  => Continue,
                     "do"        => Do,
+                    "if"        => If,
+                    "elif"      => Elif,
+                    "else"      => Else,
+                    "while"     => While,
+                    "switch"    => Switch,
+                    "case"      => Case,
+                    "yield"     => Yield,
+                    "and"       => And,
+                    "or"        => Or,
+                    "not"       => Not,
                     _ => Id(self.word.as_str().into()),
                 }
                 IsType => Type(self.word.as_str().into()),
-                IsString => Str(RcStr::from(w)),
-                IsNumber => Int(RcStr::from(w)),
-                IsDecimal => Dec(RcStr::from(w)),
+                IsNumber | IsPrefixed => Int(RcStr::from(w)),
+                IsDecimal | IsExponent => Dec(RcStr::from(w)),
                 IsOperator => Op(RcStr::from(w)),
+                IsMacro => MacroId(RcStr::from(w)),
                 IsComment{is_line: _, block_depth: _} => panic!("Comment state should not be reached"),
             });
 
             break 'build_token;
         }
 
-        self.reader.consume(consumed);
+        self.position += consumed;
         self.word.clear();
 
+        token.span.end = self.location;
+        if let TokenKind::Int(literal) | TokenKind::Dec(literal) = &token.token_kind {
+            if let Err(problem) = check_number(literal) {
+                self.diagnostics.push(Diagnostic::error("E0107", format!("Invalid number literal `{}`", literal), token.span)
+                    .with_label(problem));
+            }
+        }
         return token;
     }
 
     /// Returns the next token and consumes it.
     /// (Will reuse peeked tokens if possible)
     pub fn next(&mut self) -> Token {
-        if self.peeked.is_empty() {
-            return self.tokenize();
-        }
-        return unsafe {self.peeked.pop_front().unwrap_unchecked()};
+        let token = match self.peeked.pop_front() {
+            Some(token) => token,
+            None => self.tokenize(),
+        };
+        self.previous_end = token.span.end;
+        return token;
     }
 
     /// Returns the n'th token after the last consumed token.
@@ -557,7 +706,13 @@ impl<R: Read> Tokenizer<R> {
         if self.peeked.is_empty() {
             panic!("No peeked tokens to consume");
         }
-        self.peeked.pop_front();
+        let token = self.peeked.pop_front().unwrap();
+        self.previous_end = token.span.end;
+    }
+
+    /// The end of the last consumed token, used by the parser as the end of the node it just finished.
+    pub fn previous_end(&self) -> Location {
+        return self.previous_end;
     }
 
     /// Returns a string representation of alle tokens until an EOF.
@@ -573,11 +728,12 @@ impl<R: Read> Tokenizer<R> {
             let token = &self.peek(index).token_kind;
             match token {
                 EOF => break,
-                OpenBracket => {
+                OpenBracket | DotBracket => {
                     if new_line { for _ in 0..indent {
                         string.push_str("    ");
                     }}
-                    string.push_str("{\n");
+                    string.push_str(&token.str());
+                    string.push('\n');
                     indent += 1;
                     new_line = true;
                 }
@@ -611,3 +767,314 @@ impl<R: Read> Tokenizer<R> {
         return string.into();
     }
 }
+
+/// Returns the character that starts at the byte offset.
+fn char_at(text: &str, offset: usize) -> Option<char> {
+    return text.get(offset..)?.chars().next();
+}
+
+/// Decodes the file as UTF-8. Invalid bytes are reported, and replaced by spaces
+/// so that the offsets of the rest of the file do not change.
+fn decode_utf8(bytes: &[u8], file: FileId, diagnostics: &mut Vec<Diagnostic>) -> Box<str> {
+    let mut text = String::with_capacity(bytes.len());
+    // The byte ranges of invalid UTF-8, where adjacent invalid sequences are merged.
+    let mut invalid_ranges: Vec<(usize, usize)> = Vec::new();
+    for chunk in bytes.utf8_chunks() {
+        text.push_str(chunk.valid());
+        if chunk.invalid().is_empty() {
+            continue;
+        }
+        let start = text.len();
+        text.push_str(&" ".repeat(chunk.invalid().len()));
+        match invalid_ranges.last_mut() {
+            Some((_, end)) if *end == start => *end = text.len(),
+            _ => invalid_ranges.push((start, text.len())),
+        };
+    }
+    for (start, end) in invalid_ranges {
+        let sequence: String = bytes[start..end].iter().map(|byte| format!("\\x{:02X}", byte)).collect();
+        let span = Span::new(Location{file, offset: start as u32}, Location{file, offset: end as u32});
+        diagnostics.push(Diagnostic::error("E0104", format!("Invalid UTF-8 `{}`", sequence), span)
+            .with_label("the file must be valid UTF-8"));
+    }
+    return text.into();
+}
+
+/// Returns whether the text starts with the exponent of a decimal, e.g. `e5` or `E-3`.
+fn is_exponent(text: &str) -> bool {
+    let exponent = text[1..].strip_prefix(['+', '-']).unwrap_or(&text[1..]);
+    return exponent.starts_with(|c: char| c.is_ascii_digit());
+}
+
+/// Checks the digits and the suffix of a number literal, and returns what is wrong with it.
+fn check_number(literal: &str) -> Result<(), String> {
+    let number = NumberLiteral::split(literal);
+    let kind = match number.radix {
+        2 => "binary",
+        8 => "octal",
+        16 => "hexadecimal",
+        _ => "decimal",
+    };
+    if number.digits.is_empty() {
+        return Err(format!("expected digits after `{}`", &literal[..2]));
+    }
+    if let Some(digit) = number.digits.chars().find(|c| number.radix != 10 && !c.is_digit(number.radix)) {
+        return Err(format!("`{}` is not a {} digit", digit, kind));
+    }
+    if number.suffix.is_empty() {
+        return Ok(());
+    }
+    return match number.primitive() {
+        Some(primitive) if number.is_decimal && !primitive.is_float() => Err(format!("a decimal cannot have the integer suffix `{}`", number.suffix)),
+        Some(..) => Ok(()),
+        None => Err(format!("unknown suffix `{}`, expected a type such as `u8`, `i64` or `f32`", number.suffix)),
+    };
+}
+
+/// Whether the text is an operator that the parser knows.
+fn is_known_operator(text: &str) -> bool {
+    return BinaryOp::from_str(text).is_some() || UnaryOp::from_str(text).is_some();
+}
+
+/// Returns whether the text starts with a raw string, i.e. `r"`, or `r#"` with any number of `#`.
+fn is_raw_string_start(text: &str) -> bool {
+    return text.strip_prefix('r').is_some_and(|raw| raw.trim_start_matches('#').starts_with('"'));
+}
+
+/// Scans the string or char literal at the start of the text, where `start` is the location of its first character.
+/// The literal can be
+/// - a string `"..."`, which ends at the end of the line,
+/// - a multi-line string `"""..."""`,
+/// - a raw string `r"..."` or `r#"..."#`, which can span lines and has no escapes,
+/// - a char `'.'`.
+///
+/// Returns the token with the escapes replaced, and the number of bytes of the literal.
+fn scan_quoted(text: &str, start: Location, diagnostics: &mut Vec<Diagnostic>) -> (TokenKind, usize) {
+    let location = |offset: usize| Location{file: start.file, offset: start.offset + offset as u32};
+    let is_char = text.starts_with('\'');
+    let (delimiter, mut offset, has_escapes, is_multi_line) = if is_char {
+        ("'".to_string(), 1, true, false)
+    } else if text.starts_with("\"\"\"") {
+        ("\"\"\"".to_string(), 3, true, true)
+    } else if let Some(raw) = text.strip_prefix('r') {
+        let hashes = raw.chars().take_while(|c| *c == '#').count();
+        (format!("\"{}", "#".repeat(hashes)), hashes + 2, false, true)
+    } else {
+        ("\"".to_string(), 1, true, false)
+    };
+    let kind = if is_char { "char" } else { "string" };
+
+    let mut value = String::new();
+    loop {
+        let rest = &text[offset..];
+        if rest.starts_with(delimiter.as_str()) {
+            offset += delimiter.len();
+            break;
+        }
+        let c = match rest.chars().next() {
+            Some(c) => c,
+            None => {
+                diagnostics.push(Diagnostic::error("E0102", format!("Unterminated {} literal", kind), Span::new(start, location(offset)))
+                    .with_label(format!("the {} is not closed before the end of the file", kind)));
+                break;
+            }
+        };
+        if c == '\n' && !is_multi_line {
+            diagnostics.push(Diagnostic::error("E0102", format!("Unterminated {} literal", kind), Span::new(start, location(offset)))
+                .with_label(format!("the {} is not closed before the end of the line", kind))
+                .with_note("use `\\n` for a newline, or `\"\"\"` for a multi-line string"));
+            break;
+        }
+        if c == '\\' && has_escapes {
+            let (escaped, length) = scan_escape(rest);
+            match escaped {
+                Ok(escaped) => value.push(escaped),
+                Err(problem) => {
+                    diagnostics.push(Diagnostic::error("E0105", format!("Invalid escape sequence `{}`", &rest[..length]), Span::new(location(offset), location(offset + length)))
+                        .with_label(problem)
+                        .with_note("the escapes are `\\n`, `\\r`, `\\t`, `\\0`, `\\\\`, `\\\"`, `\\'` and `\\u{...}`"));
+                }
+            };
+            offset += length;
+            continue;
+        }
+        value.push(c);
+        offset += c.len_utf8();
+    }
+
+    if !is_char {
+        return (TokenKind::Str(value.into()), offset);
+    }
+    let mut chars = value.chars();
+    let c = chars.next();
+    if c.is_none() || chars.next().is_some() {
+        diagnostics.push(Diagnostic::error("E0106", "Invalid char literal", Span::new(start, location(offset)))
+            .with_label("a char literal must contain exactly one character"));
+    }
+    return (TokenKind::Char(c.unwrap_or('\0')), offset);
+}
+
+/// Decodes the escape sequence at the start of the text, which starts with a backslash.
+/// Returns the character, or what is wrong with the sequence, and the number of bytes of the sequence.
+fn scan_escape(text: &str) -> (Result<char, &'static str>, usize) {
+    let escaped = match text[1..].chars().next() {
+        Some('n')  => '\n',
+        Some('r')  => '\r',
+        Some('t')  => '\t',
+        Some('0')  => '\0',
+        Some('\\') => '\\',
+        Some('"')  => '"',
+        Some('\'') => '\'',
+        Some('u')  => return scan_unicode_escape(text),
+        // The end of the line is left to end the literal
+        None | Some('\n') => return (Err("expected an escaped character after `\\`"), 1),
+        Some(c) => return (Err("unknown escape character"), 1 + c.len_utf8()),
+    };
+    return (Ok(escaped), 2);
+}
+
+/// Decodes a `\u{...}` escape with 1 to 6 hex digits.
+fn scan_unicode_escape(text: &str) -> (Result<char, &'static str>, usize) {
+    if !text[2..].starts_with('{') {
+        return (Err("expected `{` after `\\u`"), 2);
+    }
+    let digits = text[3..].chars().take_while(|c| c.is_ascii_hexdigit()).count();
+    let end = 3 + digits;
+    if !text[end..].starts_with('}') {
+        return (Err("expected hex digits followed by `}`"), end);
+    }
+    if digits == 0 || digits > 6 {
+        return (Err("a unicode escape must have 1 to 6 hex digits"), end + 1);
+    }
+    let escaped = u32::from_str_radix(&text[3..end], 16).ok().and_then(char::from_u32);
+    return match escaped {
+        Some(escaped) => (Ok(escaped), end + 1),
+        None => (Err("not a unicode character"), end + 1),
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::{Path, PathBuf};
+
+    use super::*;
+
+    fn find_mylang_files(directory: &Path, files: &mut Vec<PathBuf>) {
+        for entry in std::fs::read_dir(directory).unwrap() {
+            let path = entry.unwrap().path();
+            if path.is_dir() {
+                find_mylang_files(&path, files);
+            }
+            else if path.extension().is_some_and(|extension| extension == "mylang") {
+                files.push(path);
+            }
+        }
+    }
+
+    /// Every file of the standard library is tokenized without invalid tokens or other problems.
+    #[test]
+    fn tokenize_std() {
+        let mut files = Vec::new();
+        find_mylang_files(&Path::new(env!("CARGO_MANIFEST_DIR")).join("std"), &mut files);
+        assert!(!files.is_empty(), "the standard library was not found");
+
+        for path in files {
+            let bytes = std::fs::read(&path).unwrap();
+            let mut tokenizer = Tokenizer::new(&bytes, 0);
+            loop {
+                let token = tokenizer.tokenize_any();
+                match token.token_kind {
+                    TokenKind::Invalid(string) => panic!("{}: invalid token `{}` at {}", path.display(), string, token.span.start.str()),
+                    TokenKind::EOF => break,
+                    _ => {},
+                };
+            }
+            let diagnostics = tokenizer.take_diagnostics();
+            assert!(diagnostics.is_empty(), "{}: {:?}", path.display(), diagnostics);
+        }
+    }
+
+    /// Returns the tokens of the source until the end of the file, and the codes of the diagnostics.
+    fn tokenize(source: &str) -> (Vec<TokenKind>, Vec<&'static str>) {
+        let mut tokenizer = Tokenizer::new(source.as_bytes(), 0);
+        let mut tokens = Vec::new();
+        loop {
+            let token = tokenizer.next();
+            if token.token_kind == TokenKind::EOF {
+                break;
+            }
+            tokens.push(token.token_kind);
+        }
+        let codes = tokenizer.take_diagnostics().iter().map(|diagnostic| diagnostic.code).collect();
+        return (tokens, codes);
+    }
+
+    fn string(value: &str) -> TokenKind {
+        return TokenKind::Str(value.into());
+    }
+
+    fn operator(value: &str) -> TokenKind {
+        return TokenKind::Op(value.into());
+    }
+
+    #[test]
+    fn escapes_are_replaced() {
+        let (tokens, codes) = tokenize(r#""a\nb\t\"\\\0" 'x' '\'' "\u{48}\u{1F600}""#);
+        assert_eq!(tokens, [string("a\nb\t\"\\\0"), TokenKind::Char('x'), TokenKind::Char('\''), string("H\u{1F600}")]);
+        assert!(codes.is_empty(), "{:?}", codes);
+    }
+
+    #[test]
+    fn invalid_escapes_are_reported() {
+        let (tokens, codes) = tokenize(r#""a\qb" "\u{}" "\u{110000}" "\u12""#);
+        assert_eq!(tokens, [string("ab"), string(""), string(""), string("12")]);
+        assert_eq!(codes, ["E0105", "E0105", "E0105", "E0105"]);
+    }
+
+    #[test]
+    fn unterminated_strings_end_at_the_line() {
+        let (tokens, codes) = tokenize("\"abc\nx");
+        assert_eq!(tokens, [string("abc"), TokenKind::Id("x".into())]);
+        assert_eq!(codes, ["E0102"]);
+    }
+
+    #[test]
+    fn raw_strings_have_no_escapes() {
+        let (tokens, codes) = tokenize(r###"r"a\n" r#"say "hi""# r##"a"#b"## r"line
+next""###);
+        assert_eq!(tokens, [string("a\\n"), string("say \"hi\""), string("a\"#b"), string("line\nnext")]);
+        assert!(codes.is_empty(), "{:?}", codes);
+    }
+
+    #[test]
+    fn multi_line_strings() {
+        let (tokens, codes) = tokenize("\"\"\"one\n\"two\"\\n\"\"\"");
+        assert_eq!(tokens, [string("one\n\"two\"\n")]);
+        assert!(codes.is_empty(), "{:?}", codes);
+    }
+
+    #[test]
+    fn longest_known_operator_is_read() {
+        let (tokens, _) = tokenize("x*-1 a!=b a<=-b a==b");
+        let id = |name: &str| TokenKind::Id(name.into());
+        assert_eq!(tokens, [
+            id("x"), operator("*"), operator("-"), TokenKind::Int("1".into()),
+            id("a"), operator("!="), id("b"),
+            id("a"), operator("<="), operator("-"), id("b"),
+            id("a"), operator("=="), id("b"),
+        ]);
+    }
+
+    #[test]
+    fn invalid_utf8_is_replaced_by_spaces() {
+        let mut tokenizer = Tokenizer::new(b"a \xFF\xFE b", 0);
+        let a = tokenizer.next();
+        let b = tokenizer.next();
+        assert_eq!(a.token_kind, TokenKind::Id("a".into()));
+        assert_eq!(b.token_kind, TokenKind::Id("b".into()));
+        // The offsets after the invalid bytes are those of the original bytes.
+        assert_eq!(b.span.start.offset, 5);
+        let codes: Vec<&str> = tokenizer.take_diagnostics().iter().map(|diagnostic| diagnostic.code).collect();
+        assert_eq!(codes, ["E0104"]);
+    }
+}
@@ -1,22 +1,1526 @@
+use std::collections::{HashMap, HashSet};
+use std::ops::Deref;
 
-const NULL: *const () = std::ptr::null();
+use crate::ast::*;
+use crate::diagnostics::Diagnostic;
 
-fn null<T>() -> *const T {
-    return std::ptr::null();
+pub type TypeError = Diagnostic;
+
+/// Constructors for the errors reported by the type checker.
+trait TypeErrors {
+    fn mismatch(expected: &str, found: &str, span: Span) -> TypeError;
+    fn out_of_range(literal: &str, primitive: Primitive, span: Span) -> TypeError;
+    fn message<T>(code: &'static str, message: String, span: Span) -> TypeResult<T>;
+}
+
+impl TypeErrors for TypeError {
+    fn mismatch(expected: &str, found: &str, span: Span) -> TypeError {
+        return Diagnostic::error("E0401", "Mismatched types", span)
+            .with_label(format!("expected a value of type `{}`, found {}", expected, found));
+    }
+
+    fn out_of_range(literal: &str, primitive: Primitive, span: Span) -> TypeError {
+        let error = Diagnostic::error("E0418", format!("`{}` is out of range for `{}`", literal, primitive.str()), span);
+        if !primitive.is_int() {
+            return error;
+        }
+        let (min, max) = ordinal_bounds(primitive);
+        return error.with_note(format!("`{}` holds values from {} to {}", primitive.str(), ordinal_str(min, primitive), ordinal_str(max, primitive)));
+    }
+
+    fn message<T>(code: &'static str, message: String, span: Span) -> TypeResult<T> {
+        return Err(Diagnostic::error(code, message, span));
+    }
+}
+
+pub type TypeResult<T> = Result<T, TypeError>;
+
+/// A program where every declaration has been type checked.
+/// Code generation only accepts programs of this type, so it can rely on the types being correct.
+pub struct TypedProgram {
+    program: Program,
+}
+
+impl Deref for TypedProgram {
+    type Target = Program;
+
+    fn deref(&self) -> &Program {
+        return &self.program;
+    }
+}
+
+/// Returns the type of an expression in a type checked program,
+/// or `None` if the expression does not have a value type.
+pub fn type_of_expression(arena: &Arena, expression: &Expression) -> Option<TypeKind> {
+    return TypeInferrer::new(arena).type_of_expression(expression);
+}
+
+/// Returns the type that the operands of a binary expression are converted to before the operation.
+pub fn type_of_operands(arena: &Arena, binary: &BinaryExpr) -> Option<TypeKind> {
+    return TypeInferrer::new(arena).type_of_operands(&binary.left, &binary.right);
+}
+
+/// The type of integer literals when no other type can be inferred.
+const DEFAULT_INT: Primitive = Primitive::I32;
+/// The type of decimal literals when no other type can be inferred.
+const DEFAULT_FLOAT: Primitive = Primitive::F32;
+
+/// Infers the missing types of the program, then checks every declaration
+/// and returns all type errors that were found.
+pub fn check(mut program: Program) -> Result<TypedProgram, Box<[TypeError]>> {
+    infer_types(&mut program);
+
+    let mut checker = TypeChecker{
+        arena: &program.arena,
+        inferrer: TypeInferrer::new(&program.arena),
+        errors: Vec::new(),
+    };
+
+    for (_, id) in program.get_all_namespaced_declarations().iter() {
+        if let Err(error) = checker.check_decl(&program.arena[*id]).and_then(|()| checker.check_global(*id)) {
+            checker.errors.push(error);
+        }
+    }
+
+    let errors = checker.errors;
+    if !errors.is_empty() {
+        return Err(errors.into());
+    }
+    return Ok(TypedProgram{program});
 }
 
-fn main() {
-    //const FUNC: Fn<T>() -> *const T = std::ptr::null;
+/// Replaces the inferred types of all declarations, including local ones (and inferred function return types),
+/// with the types of the expressions they are assigned.
+/// Types that cannot be inferred are left as `TypeKind::Inferred`, and are reported when checking.
+fn infer_types(program: &mut Program) {
+    let mut inferrer = TypeInferrer::new(&program.arena);
+    let declarations = program.get_all_namespaced_declarations();
+    for (_, id) in declarations.iter() {
+        inferrer.find_uses(*id, None);
+    }
+    for (_, id) in declarations.iter() {
+        inferrer.infer_all(*id);
+    }
+    let inferred = inferrer.inferred;
+    for (id, type_kind) in inferred {
+        program.arena[id].type_kind = type_kind;
+    }
+}
 
-    let a: *const i32 = NULL;
+struct TypeInferrer<'a> {
+    arena: &'a Arena,
+    inferred: HashMap<DeclId, TypeKind>,
+    /// Used to detect declarations whose types depend on themselves.
+    in_progress: HashSet<DeclId>,
+    /// The types that declarations assigned an untyped literal are returned or passed as, e.g. `I64` for
+    /// `var total = 0; return total;` in a function returning `I64`. These replace the default type of the literal.
+    uses: HashMap<DeclId, TypeKind>,
 }
 
-/*
-def nullptr: ?&T: Any = Std.Cast.reinterpret(0);
+impl<'a> TypeInferrer<'a> {
+    fn new(arena: &'a Arena) -> TypeInferrer<'a> {
+        return TypeInferrer{
+            arena,
+            inferred: HashMap::new(),
+            in_progress: HashSet::new(),
+            uses: HashMap::new(),
+        };
+    }
+
+    /// Infers the type of the declaration and of all local declarations in its body.
+    fn infer_all(&mut self, id: DeclId) {
+        self.type_of_decl(id);
+        let arena = self.arena;
+        match &arena[id].decl_kind {
+            DeclKind::FuncDecl(body) => if let Expression::Block(block) = &**body {
+                self.infer_block(block);
+            },
+            DeclKind::BlockDecl(block) => self.infer_block(block),
+            _ => {},
+        };
+    }
+
+    fn infer_block(&mut self, block: &Block) {
+        for statement in &block.statements {
+            self.infer_statement(statement);
+        }
+    }
+
+    fn infer_statement(&mut self, statement: &Statement) {
+        match statement {
+            Statement::Declaration(Declaration::ValueDecl(id)) => self.infer_all(*id),
+            Statement::Expression(Expression::Block(block)) => self.infer_block(block),
+            Statement::Expression(Expression::If(if_expr)) => {
+                self.infer_statement(&if_expr.then_branch);
+                if let Some(else_branch) = &if_expr.else_branch {
+                    self.infer_statement(else_branch);
+                }
+            }
+            Statement::Expression(Expression::While(while_expr)) => self.infer_statement(&while_expr.body),
+            Statement::Expression(Expression::For(for_expr)) => {
+                self.infer_all(for_expr.variable);
+                self.infer_statement(&for_expr.body);
+            }
+            Statement::Expression(Expression::Switch(switch)) => {
+                for body in switch_bodies(switch) {
+                    self.infer_statement(body);
+                }
+            }
+            _ => {},
+        };
+    }
+
+    /// Returns the type of the declaration, inferring it if it was not declared.
+    /// Returns `None` if the type could not be inferred.
+    fn type_of_decl(&mut self, id: DeclId) -> Option<TypeKind> {
+        match &self.arena[id].type_kind {
+            TypeKind::Inferred => {},
+            TypeKind::FuncType(func_type) if matches!(*func_type.return_type, TypeKind::Inferred) => {},
+            type_kind => return Some(type_kind.clone()),
+        };
+
+        if let Some(type_kind) = self.inferred.get(&id) {
+            return Some(type_kind.clone());
+        }
+        if !self.in_progress.insert(id) {
+            return None;
+        }
+        let type_kind = self.infer_decl(id);
+        self.in_progress.remove(&id);
+
+        if let Some(type_kind) = &type_kind {
+            self.inferred.insert(id, type_kind.clone());
+        }
+        return type_kind;
+    }
+
+    fn infer_decl(&mut self, id: DeclId) -> Option<TypeKind> {
+        let value_decl = &self.arena[id];
+        return match (&value_decl.type_kind, &value_decl.decl_kind) {
+            (TypeKind::FuncType(func_type), DeclKind::FuncDecl(body)) => {
+                let return_type = match &**body {
+                    Expression::Block(block) => self.type_of_returns(block, None)?,
+                    expression => self.type_of_expression(expression)?,
+                };
+                Some(TypeKind::FuncType(Box::new(FuncType{
+                    arguments: func_type.arguments.clone(),
+                    return_type: Box::new(return_type),
+                })))
+            }
+            (TypeKind::Inferred, DeclKind::AssignDecl(expression)) => match self.uses.get(&id) {
+                Some(type_kind) => Some(type_kind.clone()),
+                None => self.type_of_expression(expression),
+            },
+            (TypeKind::Inferred, DeclKind::BlockDecl(block)) => self.type_of_returns(block, Some(id)),
+            (TypeKind::Inferred, DeclKind::LoopDecl{start, end}) => self.type_of_operands(start, end),
+            _ => None,
+        };
+    }
+
+    fn type_of_expression(&mut self, expression: &Expression) -> Option<TypeKind> {
+        return match expression {
+            Expression::Integer(literal, _) => Some(TypeKind::Primitive(NumberLiteral::split(literal).primitive().unwrap_or(DEFAULT_INT))),
+            Expression::Decimal(literal, _) => Some(TypeKind::Primitive(NumberLiteral::split(literal).primitive().unwrap_or(DEFAULT_FLOAT))),
+            Expression::Bool(..) => Some(TypeKind::Primitive(Primitive::Bool)),
+            Expression::String(..) => Some(TypeKind::Primitive(Primitive::Str)),
+            Expression::Identifier(scoped_id) => match self.arena[scoped_id.id] {
+                IdKind::Resolved{declaration, ..} => self.type_of_decl(declaration),
+                IdKind::Unresolved{..} => None,
+            },
+            Expression::Unary(unary) => self.type_of_expression(&unary.operand),
+            Expression::Binary(binary) => {
+                if binary.operator.is_comparison() {
+                    Some(TypeKind::Primitive(Primitive::Bool))
+                }
+                else {
+                    self.type_of_operands(&binary.left, &binary.right)
+                }
+            }
+            Expression::Call(call) => match self.type_of_expression(&call.callee)? {
+                TypeKind::FuncType(func_type) => Some(*func_type.return_type),
+                _ => None,
+            },
+            Expression::Field(..) => None,
+            Expression::Block(..) => None,
+            Expression::If(if_expr) => match (&if_expr.then_branch, &if_expr.else_branch) {
+                (Statement::Expression(then_expression), Some(Statement::Expression(else_expression))) => self.type_of_operands(then_expression, else_expression),
+                _ => None,
+            },
+            Expression::While(..) | Expression::For(..) => None,
+            Expression::Switch(switch) => {
+                let values: Vec<&Expression> = switch_bodies(switch).filter_map(statement_expression).collect();
+                let typed_value = values.iter().find(|value| !is_untyped_literal(value)).or(values.first())?;
+                self.type_of_expression(typed_value)
+            }
+        };
+    }
+
+    /// Returns the type of the first `return` in the block whose type can be inferred,
+    /// or of the first `yield` to `yield_target` when it is set.
+    fn type_of_returns(&mut self, block: &Block, yield_target: Option<DeclId>) -> Option<TypeKind> {
+        for statement in &block.statements {
+            let type_kind = self.type_of_statement_returns(statement, yield_target);
+            if type_kind.is_some() {
+                return type_kind;
+            }
+        }
+        return None;
+    }
+
+    fn type_of_statement_returns(&mut self, statement: &Statement, yield_target: Option<DeclId>) -> Option<TypeKind> {
+        return match statement {
+            Statement::Return{expression, ..} if yield_target.is_none() => self.type_of_expression(expression),
+            Statement::Yield{target, expression, ..} if yield_target == Some(*target) => self.type_of_expression(expression),
+            Statement::Expression(Expression::Block(block)) => self.type_of_returns(block, yield_target),
+            Statement::Expression(Expression::If(if_expr)) => {
+                let then_type = self.type_of_statement_returns(&if_expr.then_branch, yield_target);
+                match &if_expr.else_branch {
+                    Some(else_branch) if then_type.is_none() => self.type_of_statement_returns(else_branch, yield_target),
+                    _ => then_type,
+                }
+            }
+            Statement::Expression(Expression::While(while_expr)) => self.type_of_statement_returns(&while_expr.body, yield_target),
+            Statement::Expression(Expression::For(for_expr)) => self.type_of_statement_returns(&for_expr.body, yield_target),
+            Statement::Expression(Expression::Switch(switch)) => switch_bodies(switch).find_map(|body| self.type_of_statement_returns(body, yield_target)),
+            _ => None,
+        };
+    }
+
+    /// Returns the common type of two operands.
+    /// An untyped literal operand takes the type of the other operand.
+    fn type_of_operands(&mut self, left: &Expression, right: &Expression) -> Option<TypeKind> {
+        if is_untyped_literal(left) && !is_untyped_literal(right) {
+            return self.type_of_expression(right);
+        }
+        return self.type_of_expression(left);
+    }
+
+    /// Records the uses of untyped literal declarations in the declaration, see `uses`.
+    /// `return_type` is the declared return type of the function that the declaration is in.
+    fn find_uses(&mut self, id: DeclId, return_type: Option<&'a TypeKind>) {
+        let arena = self.arena;
+        match (&arena[id].type_kind, &arena[id].decl_kind) {
+            (TypeKind::FuncType(func_type), DeclKind::FuncDecl(body)) => {
+                let return_type = Some(&*func_type.return_type).filter(|return_type| is_value_type(return_type));
+                match &**body {
+                    Expression::Block(block) => self.find_uses_in_block(block, return_type),
+                    expression => {
+                        if let Some(return_type) = return_type {
+                            self.use_as(expression, return_type);
+                        }
+                        self.find_uses_in_expression(expression, return_type);
+                    }
+                };
+            }
+            (_, DeclKind::AssignDecl(expression)) => self.find_uses_in_expression(expression, return_type),
+            (_, DeclKind::BlockDecl(block)) => self.find_uses_in_block(block, return_type),
+            (_, DeclKind::LoopDecl{start, end}) => {
+                self.find_uses_in_expression(start, return_type);
+                self.find_uses_in_expression(end, return_type);
+            }
+            _ => {},
+        };
+    }
+
+    fn find_uses_in_block(&mut self, block: &'a Block, return_type: Option<&'a TypeKind>) {
+        for statement in &block.statements {
+            self.find_uses_in_statement(statement, return_type);
+        }
+    }
+
+    fn find_uses_in_statement(&mut self, statement: &'a Statement, return_type: Option<&'a TypeKind>) {
+        match statement {
+            Statement::Declaration(Declaration::ValueDecl(id)) => self.find_uses(*id, return_type),
+            Statement::Return{expression, ..} => {
+                if let Some(return_type) = return_type {
+                    self.use_as(expression, return_type);
+                }
+                self.find_uses_in_expression(expression, return_type);
+            }
+            Statement::Expression(expression) | Statement::Yield{expression, ..} | Statement::Assign{expression, ..} => self.find_uses_in_expression(expression, return_type),
+            Statement::Break{..} | Statement::Continue{..} => {},
+        };
+    }
+
+    fn find_uses_in_expression(&mut self, expression: &'a Expression, return_type: Option<&'a TypeKind>) {
+        let arena = self.arena;
+        match expression {
+            Expression::Identifier(..) | Expression::Integer(..) | Expression::Decimal(..) | Expression::Bool(..) | Expression::String(..) => {},
+            Expression::Unary(unary) => self.find_uses_in_expression(&unary.operand, return_type),
+            Expression::Binary(binary) => {
+                self.find_uses_in_expression(&binary.left, return_type);
+                self.find_uses_in_expression(&binary.right, return_type);
+            }
+            Expression::Call(call) => {
+                if let Expression::Identifier(callee) = &call.callee {
+                    if let IdKind::Resolved{declaration, ..} = arena[callee.id] {
+                        if let TypeKind::FuncType(func_type) = &arena[declaration].type_kind {
+                            for (argument, parameter) in call.arguments.iter().zip(func_type.arguments.iter()) {
+                                let Declaration::ValueDecl(parameter) = parameter.decl;
+                                self.use_as(argument, &arena[parameter].type_kind);
+                            }
+                        }
+                    }
+                }
+                for argument in call.arguments.iter() {
+                    self.find_uses_in_expression(argument, return_type);
+                }
+            }
+            Expression::Field(field) => self.find_uses_in_expression(&field.object, return_type),
+            Expression::Block(block) => self.find_uses_in_block(block, return_type),
+            Expression::If(if_expr) => {
+                self.find_uses_in_expression(&if_expr.condition, return_type);
+                self.find_uses_in_statement(&if_expr.then_branch, return_type);
+                if let Some(else_branch) = &if_expr.else_branch {
+                    self.find_uses_in_statement(else_branch, return_type);
+                }
+            }
+            Expression::While(while_expr) => {
+                self.find_uses_in_expression(&while_expr.condition, return_type);
+                self.find_uses_in_statement(&while_expr.body, return_type);
+            }
+            Expression::For(for_expr) => {
+                self.find_uses(for_expr.variable, return_type);
+                self.find_uses_in_statement(&for_expr.body, return_type);
+            }
+            Expression::Switch(switch) => {
+                self.find_uses_in_expression(&switch.value, return_type);
+                for body in switch_bodies(switch) {
+                    self.find_uses_in_statement(body, return_type);
+                }
+            }
+        };
+    }
+
+    /// Records that the value of the expression is used as the type, if the value comes from a declaration
+    /// that is assigned an untyped literal, and the literal can have the type.
+    /// The first use of a declaration decides its type.
+    fn use_as(&mut self, expression: &Expression, type_kind: &TypeKind) {
+        let primitive = match type_kind {
+            TypeKind::Primitive(primitive) => *primitive,
+            _ => return,
+        };
+        let id = match untyped_literal_decl(self.arena, expression) {
+            Some(id) => id,
+            None => return,
+        };
+        let fits = match &self.arena[id].decl_kind {
+            DeclKind::AssignDecl(value) if has_decimal(value) => primitive.is_float(),
+            _ => primitive.is_int() || primitive.is_float(),
+        };
+        if fits {
+            self.uses.entry(id).or_insert_with(|| type_kind.clone());
+        }
+    }
+}
+
+/// The declaration that the value of the expression comes from, if it is assigned an untyped literal
+/// and the expression only combines it with untyped literals, as in `total` or `total * 2`.
+fn untyped_literal_decl(arena: &Arena, expression: &Expression) -> Option<DeclId> {
+    return match expression {
+        Expression::Identifier(scoped_id) => match arena[scoped_id.id] {
+            IdKind::Resolved{declaration, ..} => match (&arena[declaration].type_kind, &arena[declaration].decl_kind) {
+                (TypeKind::Inferred, DeclKind::AssignDecl(value)) if is_untyped_literal(value) => Some(declaration),
+                _ => None,
+            },
+            IdKind::Unresolved{..} => None,
+        },
+        Expression::Unary(unary) => untyped_literal_decl(arena, &unary.operand),
+        Expression::Binary(binary) if !binary.operator.is_comparison() => {
+            match (untyped_literal_decl(arena, &binary.left), untyped_literal_decl(arena, &binary.right)) {
+                (Some(id), None) if is_untyped_literal(&binary.right) => Some(id),
+                (None, Some(id)) if is_untyped_literal(&binary.left) => Some(id),
+                _ => None,
+            }
+        }
+        _ => None,
+    };
+}
+
+/// Whether the untyped literal contains a decimal literal, so that it cannot be an integer.
+fn has_decimal(expression: &Expression) -> bool {
+    return match expression {
+        Expression::Decimal(..) => true,
+        Expression::Unary(unary) => has_decimal(&unary.operand),
+        Expression::Binary(binary) => has_decimal(&binary.left) || has_decimal(&binary.right),
+        _ => false,
+    };
+}
+
+/// The value of a global, which is computed when compiling.
+#[derive(Clone,Debug,PartialEq)]
+pub enum Constant {
+    /// The two's complement bits of an integer, extended to 128 bits by the sign of its type.
+    Int(u128),
+    /// A floating point literal in decimal notation, which keeps all of its digits for types wider than `F64`.
+    FloatLiteral(RcStr),
+    /// A floating point value computed from literals, which is an `F32` or `F64`.
+    Float(f64),
+    Bool(bool),
+    Str(RcStr),
+}
+
+/// Returns the value of a global of a type checked program.
+///
+/// Values of globals can be literals, other global values, and negations, arithmetic and comparisons of them.
+/// Globals computed by labeled blocks are not constants, since they are computed when the program starts.
+/// Integer arithmetic wraps around as it does when the program runs. Floating point arithmetic
+/// is only computed for `F32` and `F64`, since the compiler cannot round to the other types.
+pub fn constant_value(arena: &Arena, id: DeclId) -> Constant {
+    return match ConstantEvaluator::new(arena).value_of_decl(id) {
+        Ok(value) => value,
+        Err(..) => unreachable!("Global value is not a constant"),
+    };
+}
+
+enum ConstantError {
+    Error(TypeError),
+    /// The value depends on a cycle of other globals, which is reported at the declarations in the cycle.
+    Cycle,
+}
+
+struct ConstantEvaluator<'a> {
+    arena: &'a Arena,
+    inferrer: TypeInferrer<'a>,
+    /// The globals whose values are being computed, starting with the one whose value was asked for.
+    globals: Vec<DeclId>,
+}
+
+impl<'a> ConstantEvaluator<'a> {
+    fn new(arena: &'a Arena) -> ConstantEvaluator<'a> {
+        return ConstantEvaluator{arena, inferrer: TypeInferrer::new(arena), globals: Vec::new()};
+    }
+
+    fn value_of_decl(&mut self, id: DeclId) -> Result<Constant, ConstantError> {
+        if let Some(start) = self.globals.iter().position(|global| *global == id) {
+            if start > 0 {
+                return Err(ConstantError::Cycle);
+            }
+            let value_decl = &self.arena[id];
+            let names: Vec<&str> = self.globals.iter().chain([&id]).map(|id| &*self.arena[*id].identifier).collect();
+            return Err(ConstantError::Error(Diagnostic::error("E0422", format!("Global value `{}` is defined in terms of itself", value_decl.identifier), value_decl.identifier_span)
+                .with_note(format!("the values form the cycle {}", names.join(" -> ")))));
+        }
+        let value_decl = &self.arena[id];
+        let (expression, primitive) = match (&value_decl.decl_kind, &value_decl.type_kind) {
+            (DeclKind::AssignDecl(expression), TypeKind::Primitive(primitive)) => (expression, *primitive),
+            _ => unreachable!("Global value is not a constant"),
+        };
+        self.globals.push(id);
+        let value = self.value_of(expression, primitive);
+        self.globals.pop();
+        return value;
+    }
+
+    /// `primitive` is the type that the type checker gave the expression.
+    fn value_of(&mut self, expression: &Expression, primitive: Primitive) -> Result<Constant, ConstantError> {
+        let not_constant = || ConstantError::Error(Diagnostic::error("E0421", "Global values must be constants", expression.span())
+            .with_label("not a constant")
+            .with_note("global values can only be computed from literals and other global values, since they are computed when compiling"));
+        match expression {
+            Expression::Integer(literal, _) if primitive.is_int() => {
+                let value = NumberLiteral::split(literal).int_value().expect("Literal out of range");
+                return Ok(Constant::Int(extend_bits(value, primitive)));
+            }
+            Expression::Integer(literal, _) | Expression::Decimal(literal, _) => {
+                let value = NumberLiteral::split(literal).decimal_str().expect("Literal out of range");
+                return Ok(Constant::FloatLiteral(value.into()));
+            }
+            Expression::Bool(value, _) => return Ok(Constant::Bool(*value)),
+            Expression::String(value, _) => return Ok(Constant::Str(value.clone())),
+            Expression::Identifier(scoped_id) => {
+                let declaration = match self.arena[scoped_id.id] {
+                    IdKind::Resolved{declaration, ..} => declaration,
+                    IdKind::Unresolved{..} => unreachable!("Unresolved identifier `{}`", scoped_id.name),
+                };
+                return match &self.arena[declaration].decl_kind {
+                    DeclKind::AssignDecl(..) => self.value_of_decl(declaration),
+                    DeclKind::BlockDecl(..) => Err(ConstantError::Error(Diagnostic::error("E0421", "Global values must be constants", expression.span())
+                        .with_label("not a constant")
+                        .with_note(format!("`{}` is computed by a labeled block when the program starts", scoped_id.name)))),
+                    _ => Err(not_constant()),
+                };
+            }
+            Expression::Unary(unary) => {
+                return match (unary.operator, self.value_of(&unary.operand, primitive)?) {
+                    (UnaryOp::Neg, Constant::Int(value)) => Ok(Constant::Int(extend_bits(value.wrapping_neg(), primitive))),
+                    (UnaryOp::Neg, Constant::FloatLiteral(literal)) => Ok(Constant::FloatLiteral(match literal.strip_prefix('-') {
+                        Some(positive) => positive.into(),
+                        None => format!("-{}", literal).into(),
+                    })),
+                    (UnaryOp::Neg, Constant::Float(value)) => Ok(Constant::Float(-value)),
+                    _ => Err(not_constant()),
+                };
+            }
+            Expression::Binary(binary) if binary.operator.is_comparison() => {
+                let operand_type = match self.inferrer.type_of_operands(&binary.left, &binary.right) {
+                    Some(TypeKind::Primitive(operand_type)) => operand_type,
+                    _ => return Err(not_constant()),
+                };
+                let left = self.value_of(&binary.left, operand_type)?;
+                let right = self.value_of(&binary.right, operand_type)?;
+                return compare(binary.operator, operand_type, &left, &right).map(Constant::Bool).ok_or_else(not_constant);
+            }
+            Expression::Binary(binary) => {
+                let left = self.value_of(&binary.left, primitive)?;
+                let right = self.value_of(&binary.right, primitive)?;
+                if let (Constant::Int(left), Constant::Int(right)) = (&left, &right) {
+                    if binary.operator == BinaryOp::Div && *right == 0 {
+                        return Err(ConstantError::Error(Diagnostic::error("E0426", "Division by zero in a global value", binary.span)
+                            .with_label("the divisor is zero")));
+                    }
+                    return Ok(Constant::Int(extend_bits(int_arithmetic(binary.operator, primitive, *left, *right), primitive)));
+                }
+                return match (float_value(&left, primitive), float_value(&right, primitive)) {
+                    (Some(left), Some(right)) => Ok(Constant::Float(round_float(float_arithmetic(binary.operator, left, right), primitive))),
+                    _ => Err(not_constant()),
+                };
+            }
+            _ => return Err(not_constant()),
+        };
+    }
+}
+
+/// Truncates the bits to the width of the integer type, and extends them to 128 bits by the sign of the type.
+fn extend_bits(bits: u128, primitive: Primitive) -> u128 {
+    let width = bit_width(primitive);
+    if width == 128 {
+        return bits;
+    }
+    let mask = u128::MAX >> (128 - width);
+    let bits = bits & mask;
+    if is_signed(&primitive) && bits >> (width - 1) == 1 {
+        return bits | !mask;
+    }
+    return bits;
+}
+
+fn int_arithmetic(operator: BinaryOp, primitive: Primitive, left: u128, right: u128) -> u128 {
+    use BinaryOp::*;
+    return match operator {
+        Add => left.wrapping_add(right),
+        Sub => left.wrapping_sub(right),
+        Mul => left.wrapping_mul(right),
+        Div if is_signed(&primitive) => (left as i128).wrapping_div(right as i128) as u128,
+        Div => left / right,
+        _ => unreachable!("Comparison is not arithmetic"),
+    };
+}
+
+fn float_arithmetic(operator: BinaryOp, left: f64, right: f64) -> f64 {
+    use BinaryOp::*;
+    return match operator {
+        Add => left + right,
+        Sub => left - right,
+        Mul => left * right,
+        Div => left / right,
+        _ => unreachable!("Comparison is not arithmetic"),
+    };
+}
+
+/// The value of a floating point constant, rounded to the type, or `None` if the compiler cannot compute with the type.
+fn float_value(constant: &Constant, primitive: Primitive) -> Option<f64> {
+    if !matches!(primitive, Primitive::F32 | Primitive::F64) {
+        return None;
+    }
+    return match constant {
+        Constant::FloatLiteral(literal) => Some(round_float(literal.parse().ok()?, primitive)),
+        Constant::Float(value) => Some(*value),
+        _ => None,
+    };
+}
+
+fn round_float(value: f64, primitive: Primitive) -> f64 {
+    if let Primitive::F32 = primitive {
+        return value as f32 as f64;
+    }
+    return value;
+}
+
+/// Compares two constants of the type as the program would, or returns `None` if the compiler cannot compare them.
+fn compare(operator: BinaryOp, primitive: Primitive, left: &Constant, right: &Constant) -> Option<bool> {
+    use BinaryOp::*;
+    let ordering = match (left, right) {
+        (Constant::Int(left), Constant::Int(right)) if is_signed(&primitive) => (*left as i128).partial_cmp(&(*right as i128)),
+        (Constant::Int(left), Constant::Int(right)) => left.partial_cmp(right),
+        (Constant::Bool(left), Constant::Bool(right)) => left.partial_cmp(right),
+        _ => float_value(left, primitive)?.partial_cmp(&float_value(right, primitive)?),
+    };
+    return Some(match operator {
+        Eq => ordering == Some(std::cmp::Ordering::Equal),
+        Ne => ordering != Some(std::cmp::Ordering::Equal),
+        Lt => ordering == Some(std::cmp::Ordering::Less),
+        Le => matches!(ordering, Some(std::cmp::Ordering::Less | std::cmp::Ordering::Equal)),
+        Gt => ordering == Some(std::cmp::Ordering::Greater),
+        Ge => matches!(ordering, Some(std::cmp::Ordering::Greater | std::cmp::Ordering::Equal)),
+        _ => unreachable!("Arithmetic is not a comparison"),
+    });
+}
+
+/// The cases of the `switch` followed by its `else` case.
+fn switch_bodies(switch: &SwitchExpr) -> impl Iterator<Item = &Statement> {
+    return switch.cases.iter().map(|case| &case.body).chain(&switch.default);
+}
+
+fn statement_expression(statement: &Statement) -> Option<&Expression> {
+    return match statement {
+        Statement::Expression(expression) => Some(expression),
+        _ => None,
+    };
+}
 
-def main() {
-    let a: ?&T: Any = nullptr;
-    var b: ?&some Any = nullptr;
+/// Whether the expression consists only of integer and decimal literals without a type suffix,
+/// meaning that its type depends on where it is used.
+fn is_untyped_literal(expression: &Expression) -> bool {
+    return match expression {
+        Expression::Integer(literal, _) | Expression::Decimal(literal, _) => NumberLiteral::split(literal).suffix.is_empty(),
+        Expression::Unary(unary) => is_untyped_literal(&unary.operand),
+        Expression::Binary(binary) => !binary.operator.is_comparison() && is_untyped_literal(&binary.left) && is_untyped_literal(&binary.right),
+        _ => false,
+    };
 }
-*/
 
+struct TypeChecker<'a> {
+    arena: &'a Arena,
+    inferrer: TypeInferrer<'a>,
+    /// Errors found in statements, which do not stop the rest of the block from being checked.
+    errors: Vec<TypeError>,
+}
+
+impl<'a> TypeChecker<'a> {
+    fn check_decl(&mut self, value_decl: &ValueDecl) -> TypeResult<()> {
+        match &value_decl.decl_kind {
+            DeclKind::EmptyDecl => {
+                return TypeError::message("E0405", format!("Declaration `{}` must be assigned a value", value_decl.identifier), value_decl.identifier_span);
+            }
+            DeclKind::AssignDecl(expression) => {
+                check_value_type(value_decl, self.arena)?;
+                return self.check_expression(expression, &value_decl.type_kind);
+            }
+            DeclKind::FuncDecl(expression) => {
+                let func_type = self.check_signature(value_decl)?;
+                if let Expression::Block(block) = &**expression {
+                    self.check_block(block, Some(&func_type.return_type), false);
+                    if !block.always_returns(self.arena, &|switch| is_exhaustive(self.arena, switch)) {
+                        return Err(Diagnostic::error("E0410", format!("Function `{}` does not always return a value", value_decl.identifier), value_decl.identifier_span)
+                            .with_note(format!("the body must end with `return` and a value of type `{}`", func_type.return_type.str(self.arena))));
+                    }
+                    return Ok(());
+                }
+                return self.check_expression(expression, &func_type.return_type);
+            }
+            DeclKind::BlockDecl(block) => return self.check_labeled_block(value_decl, block, None, false),
+            DeclKind::ExternDecl => return self.check_signature(value_decl).map(|_| ()),
+            DeclKind::LoopDecl{start, end} => {
+                check_value_type(value_decl, self.arena)?;
+                if !matches!(value_decl.type_kind, TypeKind::Primitive(primitive) if primitive.is_int()) {
+                    return Err(Diagnostic::error("E0424", format!("Cannot loop over a range of `{}`", value_decl.type_kind.str(self.arena)), value_decl.identifier_span)
+                        .with_note("only ranges of integers can be looped over"));
+                }
+                self.check_expression(start, &value_decl.type_kind)?;
+                return self.check_expression(end, &value_decl.type_kind);
+            }
+        }
+    }
+
+    /// Checks that a declaration in a namespace can be generated as a global.
+    /// Globals are initialized when the program is compiled, so their values must be constants, see `constant_value`.
+    /// Only globals computed by labeled blocks are computed when the program starts instead.
+    fn check_global(&self, id: DeclId) -> TypeResult<()> {
+        if !matches!(self.arena[id].decl_kind, DeclKind::AssignDecl(..)) {
+            return Ok(());
+        }
+        return match ConstantEvaluator::new(self.arena).value_of_decl(id) {
+            Ok(..) | Err(ConstantError::Cycle) => Ok(()),
+            Err(ConstantError::Error(error)) => Err(error),
+        };
+    }
+
+    /// Checks the types of the arguments and the return type of a function.
+    fn check_signature<'d>(&self, value_decl: &'d ValueDecl) -> TypeResult<&'d FuncType> {
+        let func_type = match &value_decl.type_kind {
+            TypeKind::FuncType(func_type) => func_type,
+            _ => unreachable!("Function `{}` without a function type", value_decl.identifier),
+        };
+        for argument in func_type.arguments.iter() {
+            let Declaration::ValueDecl(argument_decl) = argument.decl; {
+                check_value_type(&self.arena[argument_decl], self.arena)?;
+            }
+        }
+        if let TypeKind::Inferred = *func_type.return_type {
+            return Err(Diagnostic::error("E0402", format!("Could not infer the return type of `{}`", value_decl.identifier), value_decl.identifier_span)
+                .with_note("the return type can be declared after the `->`"));
+        }
+        if let TypeKind::Identifier(scoped_id) = &*func_type.return_type {
+            return Err(not_a_type(self.arena, scoped_id));
+        }
+        if !is_value_type(&func_type.return_type) {
+            return TypeError::message("E0403", format!("Functions returning `{}` are not supported", func_type.return_type.str(self.arena)), value_decl.identifier_span);
+        }
+        return Ok(func_type);
+    }
+
+    /// Checks the block of a labeled block declaration, which must always yield a value.
+    fn check_labeled_block(&mut self, value_decl: &ValueDecl, block: &Block, return_type: Option<&TypeKind>, in_loop: bool) -> TypeResult<()> {
+        check_value_type(value_decl, self.arena)?;
+        self.check_block(block, return_type, in_loop);
+        if !block.always_exits(self.arena, &|switch| is_exhaustive(self.arena, switch)) {
+            return Err(Diagnostic::error("E0420", format!("Labeled block `{}` does not always yield a value", value_decl.identifier), value_decl.identifier_span)
+                .with_note(format!("the block must end with `yield {} = value;` and a value of type `{}`", value_decl.identifier, value_decl.type_kind.str(self.arena))));
+        }
+        return Ok(());
+    }
+
+    /// Checks every statement in the block, where `return_type` is the return type of the enclosing function if there is one,
+    /// and `in_loop` is whether the block is inside a loop of that function.
+    fn check_block(&mut self, block: &Block, return_type: Option<&TypeKind>, in_loop: bool) {
+        for statement in &block.statements {
+            self.check_statement(statement, return_type, in_loop);
+        }
+    }
+
+    /// Checks the statement, and stores its errors so the remaining statements are also checked.
+    fn check_statement(&mut self, statement: &Statement, return_type: Option<&TypeKind>, in_loop: bool) {
+        let result = match statement {
+            Statement::Declaration(Declaration::ValueDecl(id)) => {
+                let value_decl = &self.arena[*id];
+                match &value_decl.decl_kind {
+                    DeclKind::BlockDecl(block) => self.check_labeled_block(value_decl, block, return_type, in_loop),
+                    _ => self.check_decl(value_decl),
+                }
+            }
+            Statement::Expression(Expression::Block(inner_block)) => {
+                self.check_block(inner_block, return_type, in_loop);
+                Ok(())
+            }
+            Statement::Expression(Expression::If(if_expr)) => {
+                let result = self.check_condition(&if_expr.condition);
+                self.check_statement(&if_expr.then_branch, return_type, in_loop);
+                if let Some(else_branch) = &if_expr.else_branch {
+                    self.check_statement(else_branch, return_type, in_loop);
+                }
+                result
+            }
+            Statement::Expression(Expression::While(while_expr)) => {
+                let result = self.check_condition(&while_expr.condition);
+                self.check_statement(&while_expr.body, return_type, true);
+                result
+            }
+            Statement::Expression(Expression::For(for_expr)) => {
+                let result = self.check_decl(&self.arena[for_expr.variable]);
+                self.check_statement(&for_expr.body, return_type, true);
+                result
+            }
+            Statement::Expression(Expression::Switch(switch)) => {
+                let result = self.check_switch(switch, false);
+                for case in switch.cases.iter() {
+                    self.check_statement(&case.body, return_type, in_loop);
+                }
+                if let Some(default) = &switch.default {
+                    self.check_statement(default, return_type, in_loop);
+                }
+                result
+            }
+            Statement::Expression(expression) => self.check_statement_expression(expression),
+            Statement::Return{expression, span} => match return_type {
+                Some(return_type) => self.check_expression(expression, return_type),
+                None => TypeError::message("E0419", "`return` can only be used inside a function".into(), *span),
+            },
+            Statement::Yield{target, expression, ..} => {
+                let target_decl = &self.arena[*target];
+                // Labeled blocks without a valid type are reported at their declaration.
+                if is_value_type(&target_decl.type_kind) {
+                    self.check_expression(expression, &target_decl.type_kind)
+                }
+                else {
+                    Ok(())
+                }
+            }
+            Statement::Assign{target, expression, ..} => self.check_assign(target, expression),
+            Statement::Break{span} if !in_loop => TypeError::message("E0412", "`break` can only be used inside a loop".into(), *span),
+            Statement::Continue{span} if !in_loop => TypeError::message("E0412", "`continue` can only be used inside a loop".into(), *span),
+            Statement::Break{..} | Statement::Continue{..} => Ok(()),
+        };
+        if let Err(error) = result {
+            self.errors.push(error);
+        }
+    }
+
+    /// Checks that the target of an assignment is a `var` and that the value has its type.
+    fn check_assign(&mut self, target: &ScopedId, expression: &Expression) -> TypeResult<()> {
+        let target_decl = match self.arena[target.id] {
+            IdKind::Resolved{declaration, ..} => &self.arena[declaration],
+            IdKind::Unresolved{..} => unreachable!("Unresolved identifier `{}`", target.name),
+        };
+        if target_decl.decl_keyword != DeclKeyword::Var {
+            return Err(Diagnostic::error("E0425", format!("Cannot assign to `{}`, it is not declared with `var`", target.name), target.span)
+                .with_secondary(target_decl.identifier_span, format!("`{}` is declared here", target.name))
+                .with_note("only variables declared with `var` can be assigned a new value"));
+        }
+        // Variables without a valid type are reported at their declaration.
+        if is_value_type(&target_decl.type_kind) {
+            return self.check_expression(expression, &target_decl.type_kind);
+        }
+        return Ok(());
+    }
+
+    fn check_condition(&mut self, condition: &Expression) -> TypeResult<()> {
+        return self.check_expression(condition, &TypeKind::Primitive(Primitive::Bool));
+    }
+
+    /// Checks an expression whose value is not used.
+    fn check_statement_expression(&mut self, expression: &Expression) -> TypeResult<()> {
+        match self.inferrer.type_of_expression(expression) {
+            Some(type_kind) if is_value_type(&type_kind) => return self.check_expression(expression, &type_kind),
+            Some(..) => return Ok(()),
+            None => {},
+        };
+        // Expressions without a type are either reported elsewhere, or are invalid.
+        return match expression {
+            Expression::Call(call) => self.check_call(call).map(|_| ()),
+            Expression::Field(field) => Err(field_error(field)),
+            _ => Ok(()),
+        };
+    }
+
+    /// Checks that the expression is a valid value of the expected type.
+    fn check_expression(&mut self, expression: &Expression, expected: &TypeKind) -> TypeResult<()> {
+        let primitive = match expected {
+            TypeKind::Primitive(primitive) => primitive,
+            _ => unreachable!("Expected type is not a primitive"),
+        };
+        match expression {
+            Expression::Integer(literal, span) | Expression::Decimal(literal, span) => {
+                return check_number(literal, false, *primitive, *span);
+            }
+            Expression::Bool(literal, span) => {
+                if primitive.is_bool() {
+                    return Ok(());
+                }
+                return Err(TypeError::mismatch(&expected.str(self.arena), &format!("boolean literal `{}`", literal), *span));
+            }
+            Expression::String(literal, span) => {
+                if let Primitive::Str = primitive {
+                    return Ok(());
+                }
+                return Err(TypeError::mismatch(&expected.str(self.arena), &format!("string literal `{:?}`", literal), *span));
+            }
+            Expression::Identifier(scoped_id) => {
+                let other_decl = resolved_decl(self.arena, scoped_id);
+                if let TypeKind::Inferred = other_decl.type_kind {
+                    // The missing type is reported at the declaration itself.
+                    return Ok(());
+                }
+                if !is_value_type(&other_decl.type_kind) {
+                    return Err(Diagnostic::error("E0403", format!("`{}` of type `{}` cannot be used as a value", scoped_id.name, other_decl.type_kind.str(self.arena)), scoped_id.span)
+                        .with_secondary(other_decl.identifier_span, format!("`{}` is declared here", scoped_id.name)));
+                }
+                if !expected.is_same_type(&other_decl.type_kind, self.arena) {
+                    return Err(TypeError::mismatch(&expected.str(self.arena), &format!("`{}` of type `{}`", scoped_id.name, other_decl.type_kind.str(self.arena)), scoped_id.span)
+                        .with_secondary(other_decl.identifier_span, format!("`{}` is declared here", scoped_id.name)));
+                }
+                return Ok(());
+            }
+            Expression::Unary(unary) => {
+                match unary.operator {
+                    UnaryOp::Neg => if !is_signed(primitive) {
+                        return TypeError::message("E0406", format!("Cannot apply `{}` to a value of type `{}`", unary.operator.str(), expected.str(self.arena)), unary.operator_span);
+                    },
+                };
+                // The lowest value of a signed integer type can only be written as a negated literal.
+                if let Expression::Integer(literal, _) = &unary.operand {
+                    return check_number(literal, true, *primitive, unary.span);
+                }
+                return self.check_expression(&unary.operand, expected);
+            }
+            Expression::Binary(binary) if binary.operator.is_comparison() => {
+                if !primitive.is_bool() {
+                    return Err(TypeError::mismatch(&expected.str(self.arena), &format!("the result of `{}` of type `Bool`", binary.operator.str()), binary.operator_span));
+                }
+                let operand_type = match self.inferrer.type_of_operands(&binary.left, &binary.right) {
+                    Some(operand_type) => operand_type,
+                    // The operand that could not be inferred is reported at its declaration.
+                    None => return Ok(()),
+                };
+                let is_comparable = match &operand_type {
+                    TypeKind::Primitive(operand_primitive) => is_numeric(operand_primitive) || (matches!(binary.operator, BinaryOp::Eq | BinaryOp::Ne) && operand_primitive.is_bool()),
+                    _ => false,
+                };
+                if !is_comparable {
+                    return TypeError::message("E0406", format!("Cannot apply `{}` to values of type `{}`", binary.operator.str(), operand_type.str(self.arena)), binary.operator_span);
+                }
+                self.check_expression(&binary.left, &operand_type)?;
+                return self.check_expression(&binary.right, &operand_type);
+            }
+            Expression::Binary(binary) => {
+                if !is_numeric(primitive) {
+                    return TypeError::message("E0406", format!("Cannot apply `{}` to values of type `{}`", binary.operator.str(), expected.str(self.arena)), binary.operator_span);
+                }
+                self.check_expression(&binary.left, expected)?;
+                return self.check_expression(&binary.right, expected);
+            }
+            Expression::Call(call) => {
+                let (function_decl, func_type) = self.check_call(call)?;
+                if let TypeKind::Inferred = *func_type.return_type {
+                    // The missing return type is reported at the function itself.
+                    return Ok(());
+                }
+                if !expected.is_same_type(&func_type.return_type, self.arena) {
+                    return Err(TypeError::mismatch(&expected.str(self.arena), &format!("the result of `{}` of type `{}`", function_decl.identifier, func_type.return_type.str(self.arena)), call.span)
+                        .with_secondary(function_decl.identifier_span, format!("`{}` is declared here", function_decl.identifier)));
+                }
+                return Ok(());
+            }
+            Expression::Field(field) => {
+                return Err(field_error(field));
+            }
+            Expression::Block(block) => {
+                return TypeError::message("E0411", "Blocks cannot be used as values".into(), block.span);
+            }
+            Expression::If(if_expr) => {
+                let (then_expression, else_expression) = match (&if_expr.then_branch, &if_expr.else_branch) {
+                    (Statement::Expression(then_expression), Some(Statement::Expression(else_expression))) => (then_expression, else_expression),
+                    (_, None) => return Err(Diagnostic::error("E0413", "`if` without `else` cannot be used as a value", if_expr.span)
+                        .with_label("there is no value when the condition is false")),
+                    _ => unreachable!("`if` branch is not an expression"),
+                };
+                self.check_condition(&if_expr.condition)?;
+                self.check_expression(then_expression, expected)?;
+                return self.check_expression(else_expression, expected);
+            }
+            Expression::While(..) => unreachable!("`while` used as a value"),
+            Expression::For(..) => unreachable!("`for` used as a value"),
+            Expression::Switch(switch) => {
+                self.check_switch(switch, true)?;
+                for case in switch.cases.iter() {
+                    self.check_expression(switch_value(&case.body), expected)?;
+                }
+                if let Some(default) = &switch.default {
+                    return self.check_expression(switch_value(default), expected);
+                }
+                return Ok(());
+            }
+        }
+    }
+
+    /// Checks the value and the patterns of a `switch`, and that no value is matched by more than one case.
+    /// When `exhaustive` is set, every value must be matched by a case, unless there is an `else` case.
+    fn check_switch(&mut self, switch: &SwitchExpr, exhaustive: bool) -> TypeResult<()> {
+        let value_type = match self.inferrer.type_of_expression(&switch.value) {
+            Some(value_type) => value_type,
+            None => return self.check_statement_expression(&switch.value),
+        };
+        let primitive = match &value_type {
+            TypeKind::Primitive(primitive) if primitive.is_int() || primitive.is_bool() => *primitive,
+            _ => return Err(Diagnostic::error("E0414", format!("Cannot switch on a value of type `{}`", value_type.str(self.arena)), switch.value.span())
+                .with_note("only integers and booleans can be switched on")),
+        };
+        self.check_expression(&switch.value, &value_type)?;
+
+        // Patterns as inclusive ranges of ordinals, which are ordered like the values they represent.
+        let mut ranges: Vec<(u128, u128, Span)> = Vec::new();
+        for case in switch.cases.iter() {
+            for pattern in case.patterns.iter() {
+                let (start, end) = pattern_range(pattern, primitive)?;
+                if let Some((.., earlier)) = ranges.iter().find(|(other_start, other_end, _)| start <= *other_end && *other_start <= end) {
+                    return Err(Diagnostic::error("E0416", "Pattern overlaps with an earlier case", pattern.span)
+                        .with_secondary(*earlier, "the earlier pattern"));
+                }
+                ranges.push((start, end, pattern.span));
+            }
+        }
+
+        if !exhaustive || switch.default.is_some() {
+            return Ok(());
+        }
+        return match first_unmatched(ranges.iter().map(|(start, end, _)| (*start, *end)).collect(), primitive) {
+            Some(ordinal) => Err(Diagnostic::error("E0417", "`switch` does not match every value", switch.span)
+                .with_label(format!("`{}` is not matched by any case", ordinal_str(ordinal, primitive)))
+                .with_note("add the missing cases, or an `else` case")),
+            None => Ok(()),
+        };
+    }
+
+    /// Checks that the callee is a function and that the arguments match its parameters.
+    /// Returns the function and its type, so the caller can check the return type.
+    fn check_call(&mut self, call: &CallExpr) -> TypeResult<(&'a ValueDecl, &'a FuncType)> {
+        let (function_decl, func_type) = match &call.callee {
+            Expression::Identifier(scoped_id) => {
+                let function_decl = resolved_decl(self.arena, scoped_id);
+                match &function_decl.type_kind {
+                    TypeKind::FuncType(func_type) => (function_decl, &**func_type),
+                    _ => return Err(Diagnostic::error("E0407", format!("`{}` is not a function", scoped_id.name), call.span)
+                        .with_secondary(function_decl.identifier_span, format!("`{}` is declared here", scoped_id.name))),
+                }
+            }
+            _ => return TypeError::message("E0407", "Only functions can be called".into(), call.span),
+        };
+        if func_type.arguments.len() != call.arguments.len() {
+            return Err(Diagnostic::error("E0408", format!("`{}` takes {} arguments, but {} were given", function_decl.identifier, func_type.arguments.len(), call.arguments.len()), call.span)
+                .with_secondary(function_decl.identifier_span, format!("`{}` is declared here", function_decl.identifier)));
+        }
+        for (argument, parameter) in call.arguments.iter().zip(func_type.arguments.iter()) {
+            let Declaration::ValueDecl(parameter_id) = parameter.decl;
+            let parameter_decl = &self.arena[parameter_id];
+            if is_value_type(&parameter_decl.type_kind) {
+                self.check_expression(argument, &parameter_decl.type_kind)?;
+            }
+        }
+        return Ok((function_decl, func_type));
+    }
+}
+
+fn switch_value(body: &Statement) -> &Expression {
+    return match body {
+        Statement::Expression(expression) => expression,
+        _ => unreachable!("Switch case is not an expression"),
+    };
+}
+
+/// Whether the cases of the `switch` match every value of its type, so that it cannot be left without taking a case.
+fn is_exhaustive(arena: &Arena, switch: &SwitchExpr) -> bool {
+    let primitive = match type_of_expression(arena, &switch.value) {
+        Some(TypeKind::Primitive(primitive)) if primitive.is_int() || primitive.is_bool() => primitive,
+        _ => return false,
+    };
+    let mut ranges = Vec::new();
+    for pattern in switch.cases.iter().flat_map(|case| case.patterns.iter()) {
+        match pattern_range(pattern, primitive) {
+            Ok(range) => ranges.push(range),
+            // Invalid patterns are reported by `check_switch`.
+            Err(..) => return false,
+        };
+    }
+    return first_unmatched(ranges, primitive).is_none();
+}
+
+/// Returns the lowest ordinal of the type that is not in any of the inclusive ranges, or `None` if every value is.
+fn first_unmatched(mut ranges: Vec<(u128, u128)>, primitive: Primitive) -> Option<u128> {
+    ranges.sort_by_key(|(start, _)| *start);
+    let (min, max) = ordinal_bounds(primitive);
+    // The lowest ordinal that is not matched yet, or `None` once the last value is matched.
+    let mut uncovered = Some(min);
+    for (start, end) in ranges {
+        match uncovered {
+            Some(ordinal) if start <= ordinal => uncovered = end.checked_add(1).map(|next| next.max(ordinal)),
+            _ => break,
+        };
+    }
+    return uncovered.filter(|ordinal| *ordinal <= max);
+}
+
+/// The bit flipped to order the ordinals of signed integers like their values.
+const SIGN_BIT: u128 = 1 << 127;
+
+/// Returns the ordinals of the first and last value matched by the pattern.
+fn pattern_range(pattern: &Pattern, primitive: Primitive) -> TypeResult<(u128, u128)> {
+    match &pattern.kind {
+        PatternKind::Bool(boolean) => {
+            if !primitive.is_bool() {
+                return Err(TypeError::mismatch(&primitive.str(), &format!("boolean pattern `{}`", boolean), pattern.span));
+            }
+            return Ok((*boolean as u128, *boolean as u128));
+        }
+        PatternKind::Integer(literal) => {
+            let ordinal = int_ordinal(literal, primitive, pattern.span)?;
+            return Ok((ordinal, ordinal));
+        }
+        PatternKind::Range(start, end) => {
+            let start_ordinal = int_ordinal(start, primitive, pattern.span)?;
+            let end_ordinal = int_ordinal(end, primitive, pattern.span)?;
+            if start_ordinal > end_ordinal {
+                return Err(Diagnostic::error("E0415", format!("Range `{}..{}` is empty", start.str(), end.str()), pattern.span)
+                    .with_note("ranges include both ends, and the start must not be greater than the end"));
+            }
+            return Ok((start_ordinal, end_ordinal));
+        }
+    }
+}
+
+fn int_ordinal(literal: &IntLiteral, primitive: Primitive, span: Span) -> TypeResult<u128> {
+    let suffix = NumberLiteral::split(&literal.literal).primitive();
+    if !primitive.is_int() || suffix.is_some_and(|suffix| suffix != primitive) {
+        return Err(TypeError::mismatch(&primitive.str(), &format!("integer pattern `{}`", literal.str()), span));
+    }
+    let bits = match literal.bits() {
+        Some(bits) if int_fits(literal, primitive) => bits,
+        _ => return Err(TypeError::out_of_range(&literal.str(), primitive, span)),
+    };
+    if is_signed(&primitive) {
+        return Ok(bits ^ SIGN_BIT);
+    }
+    return Ok(bits);
+}
+
+/// Checks that the number literal, which is negated when `negative` is set, is a value of the type.
+/// A suffix must match the type, and the value must fit in it.
+fn check_number(literal: &str, negative: bool, primitive: Primitive, span: Span) -> TypeResult<()> {
+    let number = NumberLiteral::split(literal);
+    let kind = if number.is_decimal { "decimal" } else { "integer" };
+    let literal = IntLiteral{literal: literal.into(), negative};
+    match number.primitive() {
+        Some(suffix) if suffix != primitive => {
+            return Err(TypeError::mismatch(&primitive.str(), &format!("{} literal `{}` of type `{}`", kind, literal.str(), suffix.str()), span));
+        }
+        _ => {},
+    };
+    if primitive.is_float() {
+        let value = number.decimal_str().and_then(|value| value.parse::<f64>().ok());
+        if !value.is_some_and(|value| float_fits(value, primitive)) {
+            return Err(TypeError::out_of_range(&literal.str(), primitive, span));
+        }
+        return Ok(());
+    }
+    if number.is_decimal || !primitive.is_int() {
+        return Err(TypeError::mismatch(&primitive.str(), &format!("{} literal `{}`", kind, literal.str()), span));
+    }
+    if !int_fits(&literal, primitive) {
+        return Err(TypeError::out_of_range(&literal.str(), primitive, span));
+    }
+    return Ok(());
+}
+
+/// Whether the value does not become infinite when it is stored in the floating point type.
+fn float_fits(value: f64, primitive: Primitive) -> bool {
+    use Primitive::*;
+    return match primitive {
+        // Values from 65520 are rounded to infinity
+        F16 => value.abs() < 65520.0,
+        F32 => (value as f32).is_finite(),
+        F64 => value.is_finite(),
+        // Every finite `F64` fits, and larger literals cannot be checked without a wider type
+        _ => true,
+    };
+}
+
+/// Whether the value of the literal can be stored in the integer type.
+fn int_fits(literal: &IntLiteral, primitive: Primitive) -> bool {
+    let magnitude = match literal.magnitude() {
+        Some(magnitude) => magnitude,
+        None => return false,
+    };
+    let bits = bit_width(primitive);
+    if is_signed(&primitive) {
+        let half = 1u128 << (bits - 1);
+        return magnitude < half || (literal.negative && magnitude == half);
+    }
+    return (magnitude == 0 || !literal.negative) && magnitude <= u128::MAX >> (128 - bits);
+}
+
+/// Returns the ordinals of the lowest and highest value of the integer or boolean type.
+fn ordinal_bounds(primitive: Primitive) -> (u128, u128) {
+    let bits = bit_width(primitive);
+    if is_signed(&primitive) {
+        let half = 1u128 << (bits - 1);
+        return (half.wrapping_neg() ^ SIGN_BIT, (half - 1) ^ SIGN_BIT);
+    }
+    return (0, u128::MAX >> (128 - bits));
+}
+
+fn ordinal_str(ordinal: u128, primitive: Primitive) -> String {
+    if let Primitive::Bool = primitive {
+        return (ordinal == 1).to_string();
+    }
+    if is_signed(&primitive) {
+        return ((ordinal ^ SIGN_BIT) as i128).to_string();
+    }
+    return ordinal.to_string();
+}
+
+const fn bit_width(primitive: Primitive) -> u32 {
+    use Primitive::*;
+    return match primitive {
+        U1 | Bool => 1,
+        U8 | I8 => 8,
+        U16 | I16 | F16 => 16,
+        U32 | I32 | F32 => 32,
+        U64 | I64 | F64 => 64,
+        U128 | I128 | F128 => 128,
+        Str => panic!("`Str` does not have a bit width"),
+    };
+}
+
+fn field_error(field: &FieldExpr) -> TypeError {
+    return Diagnostic::error("E0409", format!("Cannot access the field `{}`, values do not have fields yet", field.field), field.field_span);
+}
+
+fn resolved_decl<'a>(arena: &'a Arena, scoped_id: &ScopedId) -> &'a ValueDecl {
+    return match arena[scoped_id.id] {
+        IdKind::Resolved{declaration, ..} => &arena[declaration],
+        IdKind::Unresolved{..} => unreachable!("Unresolved identifier `{}`", scoped_id.name),
+    };
+}
+
+/// Whether arithmetic can be done on values of the type.
+const fn is_numeric(primitive: &Primitive) -> bool {
+    return (primitive.is_int() || primitive.is_float()) && !primitive.is_bool();
+}
+
+/// Whether values of the type can be negated.
+const fn is_signed(primitive: &Primitive) -> bool {
+    use Primitive::*;
+    return match primitive {
+        I8 | I16 | I32 | I64 | I128 => true,
+        F16 | F32 | F64 | F128 => true,
+        _ => false,
+    };
+}
+
+/// Only primitive types can currently be stored in values.
+fn is_value_type(type_kind: &TypeKind) -> bool {
+    return match type_kind {
+        TypeKind::Primitive(..) => true,
+        _ => false,
+    };
+}
+
+fn check_value_type(value_decl: &ValueDecl, arena: &Arena) -> TypeResult<()> {
+    return match &value_decl.type_kind {
+        TypeKind::Primitive(..) => Ok(()),
+        TypeKind::Inferred => Err(Diagnostic::error("E0402", format!("Could not infer the type of `{}`", value_decl.identifier), value_decl.identifier_span)
+            .with_note(format!("the type can be declared with `{}: Type`", value_decl.identifier))),
+        TypeKind::Identifier(scoped_id) => Err(not_a_type(arena, scoped_id)
+            .with_secondary(value_decl.identifier_span, format!("the type of `{}`", value_decl.identifier))),
+        TypeKind::FuncType(func_type) => TypeError::message("E0403", format!("`{}` cannot have the function type `{}`", value_decl.identifier, func_type.str(arena)), value_decl.identifier_span),
+    };
+}
+
+/// Type paths resolve to declarations, which are all values, since types cannot be declared yet.
+fn not_a_type(arena: &Arena, scoped_id: &ScopedId) -> Diagnostic {
+    let declaration = resolved_decl(arena, scoped_id);
+    return Diagnostic::error("E0403", format!("`{}` is not a type", scoped_id.name), scoped_id.span)
+        .with_label("not a type")
+        .with_secondary(declaration.identifier_span, format!("`{}` is declared here as a value", declaration.identifier));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::Parser;
+    use crate::tokenizer::Tokenizer;
+
+    /// Parses and checks the source as a single file, which must parse without problems.
+    /// Returns the codes of the type errors.
+    fn check_source(source: &str) -> Result<TypedProgram, Vec<&'static str>> {
+        let mut parser = Parser::new();
+        parser.parse(Tokenizer::new(source.as_bytes(), 0));
+        let (arena, diagnostics) = parser.finish();
+        assert!(diagnostics.is_empty(), "{:?}", diagnostics);
+        return check(Program{name: "test".into(), arena})
+            .map_err(|errors| errors.iter().map(|error| error.code).collect());
+    }
+
+    fn errors(source: &str) -> Vec<&'static str> {
+        return check_source(source).err().unwrap_or_default();
+    }
+
+    /// The type of the global declaration, as it is written in the source.
+    fn type_str(program: &TypedProgram, name: &str) -> String {
+        let arena = &program.arena;
+        return program.get_all_namespaced_declarations().iter()
+            .map(|(_, id)| &arena[*id])
+            .find(|value_decl| &*value_decl.identifier == name)
+            .unwrap_or_else(|| panic!("`{}` was not declared", name))
+            .type_kind.str(arena).to_string();
+    }
+
+    #[test]
+    fn mismatched_types() {
+        assert_eq!(errors("let x: I32 = 5.5;"), ["E0401"]);
+        assert_eq!(errors("let x: Bool = 1;"), ["E0401"]);
+        assert_eq!(errors("let x: F32 = true;"), ["E0401"]);
+        assert_eq!(errors("let x: Str = 1;"), ["E0401"]);
+        assert_eq!(errors("def f() -> I32 { return true; }"), ["E0401"]);
+        assert_eq!(errors("def f(x: I32) -> I32 { return x; } def g() -> I32 { return f(1.5); }"), ["E0401"]);
+        assert_eq!(errors("let a: I32 = 1; let b: U8 = 2; def f() -> I32 { return a + b; }"), ["E0401"]);
+        assert_eq!(errors("def f() -> I32 { if (1) { return 1; } return 2; }"), ["E0401"]);
+    }
+
+    #[test]
+    fn inferred_types() {
+        let program = check_source("
+            let int = 1;
+            let decimal = 2.5;
+            let suffixed = 3u8;
+            let copied = suffixed;
+            let text = \"text\";
+            def f() -> I64 { return 1; }
+            def g() -> I64 {
+                let local = f() + 1;
+                return 2 * local;
+            }
+            def h() -> Bool {
+                let comparison = 1 < 2;
+                return comparison;
+            }
+        ").unwrap();
+        assert_eq!(type_str(&program, "int"), "I32");
+        assert_eq!(type_str(&program, "decimal"), "F32");
+        assert_eq!(type_str(&program, "suffixed"), "U8");
+        assert_eq!(type_str(&program, "copied"), "U8");
+        assert_eq!(type_str(&program, "text"), "Str");
+    }
+
+    #[test]
+    fn untyped_literals_take_the_type_they_are_used_as() {
+        let program = check_source("
+            let limit = 10;
+            def f() -> I64 { return limit; }
+            def sum(n: I64) -> I64 {
+                var total = 0;
+                for (i in 1..n) {
+                    total = total + i;
+                }
+                return total;
+            }
+            def g() -> I64 {
+                let n = 3;
+                return sum(n * 2);
+            }
+        ").unwrap();
+        assert_eq!(type_str(&program, "limit"), "I64");
+        // A decimal literal cannot become an integer.
+        assert_eq!(errors("def f() -> I64 { let x = 1.5; return x; }"), ["E0401"]);
+    }
+
+    #[test]
+    fn types_that_depend_on_themselves_are_not_inferred() {
+        assert_eq!(errors("let a = b; let b = a;"), ["E0402", "E0402"]);
+    }
+
+    #[test]
+    fn values_are_not_types() {
+        assert_eq!(errors("namespace Compare { pub let eq = 1; } let x: Compare.eq = 1;"), ["E0403"]);
+        assert_eq!(errors("namespace Compare { pub let eq = 1; } def f() -> Compare.eq { return 1; }"), ["E0403"]);
+    }
+
+    #[test]
+    fn literal_ranges() {
+        assert!(check_source("
+            let a: U8 = 255;
+            let b: I8 = -128;
+            let c: I8 = 127;
+            let d: U64 = 18446744073709551615;
+            let e: I128 = -170141183460469231731687303715884105728;
+            let f: U8 = 0xFF;
+            let g: U1 = 1;
+        ").is_ok());
+        assert_eq!(errors("let a: U8 = 256;"), ["E0418"]);
+        assert_eq!(errors("let a: I8 = -129;"), ["E0418"]);
+        assert_eq!(errors("let a: I8 = 128;"), ["E0418"]);
+        assert_eq!(errors("let a: U8 = -1;"), ["E0406"]);
+        assert_eq!(errors("let a: U1 = 2;"), ["E0418"]);
+        assert_eq!(errors("let a = 300u8;"), ["E0418"]);
+    }
+
+    #[test]
+    fn exhaustive_switches() {
+        assert!(check_source("
+            def f(b: Bool) -> I32 {
+                switch b {
+                case true: return 1;
+                case false: return 2;
+                }
+            }
+            def g(x: U8) -> I32 {
+                return switch x {
+                case 0: 1;
+                case 1..254: 2;
+                case 255: 3;
+                };
+            }
+            def h(x: I8) -> I32 {
+                return switch x {
+                case -128..-1: 1;
+                case 0..127: 2;
+                };
+            }
+        ").is_ok());
+        assert_eq!(errors("def f(x: U8) -> I32 { return switch x { case 0..254: 1; }; }"), ["E0417"]);
+        assert_eq!(errors("def f(b: Bool) -> I32 { return switch b { case true: 1; }; }"), ["E0417"]);
+        // A switch statement without an `else` case only returns when it matches every value.
+        assert_eq!(errors("def f(x: U8) -> I32 { switch x { case 0..100: return 1; } }"), ["E0410"]);
+        assert!(check_source("def f(x: U8) -> I32 { return switch x { case 0: 1; else: 2; }; }").is_ok());
+    }
+
+    #[test]
+    fn overlapping_switch_cases() {
+        assert_eq!(errors("def f(x: I32) -> I32 { return switch x { case 1..5: 1; case 5: 2; else: 3; }; }"), ["E0416"]);
+        assert_eq!(errors("def f(x: I32) -> I32 { return switch x { case 1, 2, 1: 1; else: 3; }; }"), ["E0416"]);
+        assert_eq!(errors("def f(x: I32) -> I32 { return switch x { case 5..1: 1; else: 3; }; }"), ["E0415"]);
+        assert_eq!(errors("def f(x: F32) -> I32 { return switch x { case 1: 1; else: 3; }; }"), ["E0414"]);
+    }
+
+    #[test]
+    fn loops_and_assignments() {
+        assert!(check_source("
+            var counter: I32 = 0;
+            def f() -> I32 {
+                var total = 0;
+                for (i in 1..10) {
+                    if (i == 5) continue;
+                    total = total + i;
+                    counter = counter + 1;
+                }
+                for (let j: U8 in 0..255) {}
+                while (total > 0) {
+                    total = total - 1;
+                    if (total == 3) break;
+                }
+                return total;
+            }
+        ").is_ok());
+        assert_eq!(errors("def f() -> I32 { let x = 1; x = 2; return x; }"), ["E0425"]);
+        assert_eq!(errors("def f() -> I32 { for (i in 0..3) { i = 1; } return 0; }"), ["E0425"]);
+        assert_eq!(errors("def f() -> I32 { var x = 1; x = true; return x; }"), ["E0401"]);
+        assert_eq!(errors("def f() -> I32 { for (x in 0.5..2.0) {} return 0; }"), ["E0424"]);
+        assert_eq!(errors("def f() -> I32 { for (x: U8 in 0..256) {} return 0; }"), ["E0418"]);
+        assert_eq!(errors("def f() -> I32 { break; }"), ["E0412", "E0410"]);
+    }
+
+    #[test]
+    fn global_values_are_constants() {
+        assert!(check_source("let a = b; let b = -1; let c: F64 = -2.5; let d = \"text\";").is_ok());
+        assert!(check_source("let a = 1 + 2 * b; let b = -(3 - 1); let c = a < b; let d: F64 = 0.5 / 2.0;").is_ok());
+        assert_eq!(errors("def f() -> I32 { return 1; } let a = f();"), ["E0421"]);
+        assert_eq!(errors("let a = 1 / (2 - 2);"), ["E0426"]);
+        // The compiler can only compute `F32` and `F64` arithmetic.
+        assert_eq!(errors("let a: F128 = 1.0 + 2.0;"), ["E0421"]);
+        assert_eq!(errors("let a: I32 = b; let b: I32 = a;"), ["E0422", "E0422"]);
+        assert_eq!(errors("let a: I32 = b + 1; let b: I32 = 2 * a; let c = b;"), ["E0422", "E0422"]);
+    }
+
+    #[test]
+    fn global_values_are_computed_when_compiling() {
+        let program = check_source("
+            let a = 1 + 2 * 3;
+            let b: U8 = 200 + 100;
+            let c: I8 = -128 / -1;
+            let d = -7 / 2 < a;
+            let e: F32 = 0.1 + 0.2;
+            let f: F128 = -1.5;
+        ").unwrap();
+        let value = |name: &str| {
+            let id = program.get_all_namespaced_declarations().iter()
+                .map(|(_, id)| *id)
+                .find(|id| &*program.arena[*id].identifier == name)
+                .unwrap();
+            return constant_value(&program.arena, id);
+        };
+        assert_eq!(value("a"), Constant::Int(7));
+        // Integer arithmetic wraps around.
+        assert_eq!(value("b"), Constant::Int(44));
+        assert_eq!(value("c"), Constant::Int(-128i128 as u128));
+        assert_eq!(value("d"), Constant::Bool(true));
+        assert_eq!(value("e"), Constant::Float((0.1f32 + 0.2f32) as f64));
+        assert_eq!(value("f"), Constant::FloatLiteral("-1.5".into()));
+    }
+
+    #[test]
+    fn global_labeled_blocks() {
+        assert!(check_source("let a: I32 { var total = 0; for (i in 1..3) { total = total + i; } yield a = total; }").is_ok());
+        assert_eq!(errors("let a: I32 { return 1; }"), ["E0419"]);
+        // The value of a labeled block is only known when the program starts.
+        assert_eq!(errors("let a: I32 { yield a = 1; } let b = a + 1;"), ["E0421"]);
+    }
+}